@@ -0,0 +1,190 @@
+//! Writing evaluated values out as CBOR (RFC 8949).
+
+use num_bigint::Sign;
+use parser::BigInt;
+
+use crate::output::{convert, Converted, OutputOptions};
+use crate::value::Value;
+
+const UNSIGNED: u8 = 0;
+const NEGATIVE: u8 = 1;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+const TAG: u8 = 6;
+
+/// `value` as a CBOR data item.
+///
+/// Bytes are written as a byte string, integers beyond 64 bits as bignums
+/// (tags 2 and 3) and decimals as decimal fractions (tag 4). Durations, byte
+/// sizes and date-times follow `options` like [`to_json`](crate::to_json),
+/// `pretty` has no effect.
+pub fn to_cbor(value: &Value, options: &OutputOptions) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_cbor(&mut out, value, options);
+    out
+}
+
+fn write_cbor(out: &mut Vec<u8>, value: &Value, options: &OutputOptions) {
+    match value {
+        Value::Null => out.push(0xf6),
+        Value::Bool(b) => out.push(if *b { 0xf5 } else { 0xf4 }),
+        Value::Int(i) => write_int(out, (*i).into()),
+        Value::BigInt(i) => write_big(out, i),
+        Value::Float(x) => write_float(out, *x),
+        Value::Decimal(d) => {
+            let (mantissa, scale) = d.as_bigint_and_exponent();
+            write_head(out, TAG, 4);
+            write_head(out, ARRAY, 2);
+            write_int(out, (-scale).into());
+            write_big(out, &mantissa);
+        }
+        Value::Str(s) => write_text(out, s),
+        Value::Bytes(b) => {
+            write_head(out, BYTES, b.len() as u64);
+            out.extend_from_slice(b);
+        }
+        Value::Duration(_) | Value::ByteSize(_) | Value::Temporal(_) => {
+            match convert(value, options) {
+                Converted::Int(i) => write_int(out, i),
+                Converted::Float(x) => write_float(out, x),
+                Converted::Str(s) => write_text(out, &s),
+            }
+        }
+        Value::List(items) => {
+            write_head(out, ARRAY, items.len() as u64);
+            for item in items {
+                write_cbor(out, item, options);
+            }
+        }
+        Value::Dict(entries) => {
+            write_head(out, MAP, entries.len() as u64);
+            for (key, value) in entries {
+                write_text(out, key);
+                write_cbor(out, value, options);
+            }
+        }
+    }
+}
+
+/// The initial byte of a data item and the argument following it, in as
+/// few bytes as possible.
+fn write_head(out: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    if n < 24 {
+        out.push(major | n as u8);
+    } else if let Ok(n) = u8::try_from(n) {
+        out.extend_from_slice(&[major | 24, n]);
+    } else if let Ok(n) = u16::try_from(n) {
+        out.push(major | 25);
+        out.extend_from_slice(&n.to_be_bytes());
+    } else if let Ok(n) = u32::try_from(n) {
+        out.push(major | 26);
+        out.extend_from_slice(&n.to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+/// An integer in `-2^64..2^64`.
+fn write_int(out: &mut Vec<u8>, i: i128) {
+    if i >= 0 {
+        write_head(out, UNSIGNED, i as u64);
+    } else {
+        write_head(out, NEGATIVE, (-1 - i) as u64);
+    }
+}
+
+fn write_big(out: &mut Vec<u8>, i: &BigInt) {
+    let limit = 1i128 << 64;
+    match i128::try_from(i) {
+        Ok(i) if (-limit..limit).contains(&i) => write_int(out, i),
+        _ if i.sign() == Sign::Minus => {
+            write_head(out, TAG, 3);
+            write_magnitude(out, &(-1 - i));
+        }
+        _ => {
+            write_head(out, TAG, 2);
+            write_magnitude(out, i);
+        }
+    }
+}
+
+fn write_magnitude(out: &mut Vec<u8>, i: &BigInt) {
+    let (_, bytes) = i.to_bytes_be();
+    write_head(out, BYTES, bytes.len() as u64);
+    out.extend_from_slice(&bytes);
+}
+
+fn write_float(out: &mut Vec<u8>, x: f64) {
+    out.push(0xfb);
+    out.extend_from_slice(&x.to_be_bytes());
+}
+
+fn write_text(out: &mut Vec<u8>, s: &str) {
+    write_head(out, TEXT, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use indexmap::IndexMap;
+
+    use super::*;
+
+    fn cbor(value: Value) -> Vec<u8> {
+        to_cbor(&value, &OutputOptions::default())
+    }
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!(cbor(Value::Null), [0xf6]);
+        assert_eq!(cbor(Value::Bool(true)), [0xf5]);
+        assert_eq!(cbor(Value::Int(23)), [0x17]);
+        assert_eq!(cbor(Value::Int(500)), [0x19, 0x01, 0xf4]);
+        assert_eq!(cbor(Value::Int(-1)), [0x20]);
+        assert_eq!(cbor(Value::Int(-1000)), [0x39, 0x03, 0xe7]);
+        assert_eq!(
+            cbor(Value::Float(1.5)),
+            [0xfb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(cbor(Value::from("a")), [0x61, b'a']);
+        assert_eq!(cbor(Value::Bytes(vec![0, 255])), [0x42, 0, 255]);
+        assert_eq!(cbor(Value::Duration(Duration::from_secs(30))), [0x18, 30]);
+    }
+
+    #[test]
+    fn test_numbers() {
+        let big = |s: &str| Value::BigInt(s.parse().unwrap());
+        assert_eq!(
+            cbor(big("18446744073709551615")),
+            [0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(
+            cbor(big("18446744073709551616")),
+            [0xc2, 0x49, 1, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            cbor(big("-18446744073709551617")),
+            [0xc3, 0x49, 1, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        // 273.15 is 27315 * 10^-2
+        assert_eq!(
+            cbor(Value::Decimal("273.15".parse().unwrap())),
+            [0xc4, 0x82, 0x21, 0x19, 0x6a, 0xb3]
+        );
+    }
+
+    #[test]
+    fn test_containers() {
+        let value = Value::Dict(IndexMap::from([(
+            "a".to_string(),
+            Value::List(vec![Value::Int(1), Value::Null]),
+        )]));
+        assert_eq!(cbor(value), [0xa1, 0x61, b'a', 0x82, 0x01, 0xf6]);
+    }
+}
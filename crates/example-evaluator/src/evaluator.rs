@@ -0,0 +1,1585 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use parser::{
+    Document, EsonSegment, ExprChunk, ExprToken, ExprTokenChunk, Key, KeyKind, RefIndex,
+    RefPronoun, Span, Spanned, TemplatePart,
+};
+
+use crate::imports::{EvalOptions, Imports};
+use crate::render::render;
+use crate::value::Value;
+use crate::{functions, ops};
+
+/// Evaluate every expression and reference in `document`.
+///
+/// References are resolved against the document tree: `$` is the root,
+/// `self` the dict or list the expression is written in and `super` the one
+/// around that. A variable is the innermost `let` binding of its name, in
+/// the dicts around the expression or else in the document's prelude. Each
+/// value is evaluated once, however often it is referenced.
+///
+/// Calling a variable bound to `fn(a, b) => value` evaluates the value in the
+/// scope the function is written in, with `a` and `b` bound to the arguments.
+pub fn evaluate(document: &Document) -> Result<Value, EvalError> {
+    evaluate_with(document, &EvalOptions::default())
+}
+
+/// [`evaluate`] with imports found as `options` say.
+///
+/// `import("./base.eson")` evaluates another file, each file at most once,
+/// and gives its value. A problem in an imported file is reported where it
+/// is in that file, together with the chain of `import(...)` calls that
+/// led there.
+pub fn evaluate_with(document: &Document, options: &EvalOptions) -> Result<Value, EvalError> {
+    let mut imports = Imports::new(options);
+    let mut dir = PathBuf::new();
+    if let Some(file) = &options.file {
+        if let Ok(canonical) = std::fs::canonicalize(file) {
+            dir = canonical.parent().map(PathBuf::from).unwrap_or_default();
+            imports.active.push((canonical, file.display().to_string()));
+        }
+    }
+    Evaluator::new(document, dir, &mut imports).node(vec![])
+}
+
+/// A problem found while evaluating, located at the value it came from.
+///
+/// ```text
+/// $.y does not exist at line 1, column 6 in /etc/app/c.eson
+///   imported from /etc/app/d.eson at line 1, column 6
+///   imported from line 3, column 9
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalError(Box<EvalErrorImpl>);
+
+#[derive(Debug, Clone, PartialEq)]
+struct EvalErrorImpl {
+    message: String,
+    span: Span,
+    file: Option<PathBuf>,
+    imported_from: Vec<(Option<PathBuf>, Span)>,
+}
+
+impl EvalError {
+    pub(crate) fn new(message: impl Into<String>, span: Span) -> Self {
+        EvalError(Box::new(EvalErrorImpl {
+            message: message.into(),
+            span,
+            file: None,
+            imported_from: vec![],
+        }))
+    }
+
+    pub fn message(&self) -> &str {
+        &self.0.message
+    }
+
+    /// Where the problem is, in [`file`](Self::file).
+    pub fn span(&self) -> Span {
+        self.0.span
+    }
+
+    /// The imported file the problem is in, `None` for the evaluated
+    /// document itself.
+    pub fn file(&self) -> Option<&Path> {
+        self.0.file.as_deref()
+    }
+
+    /// The `import(...)` calls that led to [`file`](Self::file), innermost
+    /// first, each with the file it is written in.
+    pub fn imported_from(&self) -> &[(Option<PathBuf>, Span)] {
+        &self.0.imported_from
+    }
+
+    /// The error, raised in the file at `file`, as seen from the
+    /// `import(...)` at `span` in `importer`.
+    fn imported(mut self, file: &Path, importer: Option<PathBuf>, span: Span) -> Self {
+        self.0.file.get_or_insert_with(|| file.to_path_buf());
+        self.0.imported_from.push((importer, span));
+        self
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.0.message, self.0.span.line, self.0.span.column
+        )?;
+        if let Some(file) = &self.0.file {
+            write!(f, " in {}", file.display())?;
+        }
+        for (file, span) in &self.0.imported_from {
+            write!(f, "\n  imported from ")?;
+            if let Some(file) = file {
+                write!(f, "{} at ", file.display())?;
+            }
+            write!(f, "line {}, column {}", span.line, span.column)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// One step of a path from the root of the document.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Step {
+    Key(String),
+    Index(usize),
+    /// The `let` binding of the name in a dict.
+    Binding(String),
+    /// The `let` binding of the name in the document's prelude, only ever
+    /// the first step.
+    Prelude(String),
+}
+
+/// Where one index of a reference leads.
+enum Found {
+    /// A value written in the document.
+    Node(Step),
+    /// A value from a spread or an expression.
+    Value(Value),
+}
+
+/// What a reference refers to.
+enum Referent {
+    /// The value written at the path.
+    Node(Vec<Step>),
+    /// A value of no node: of a variable, a spread or an expression.
+    Value(Value),
+}
+
+/// A variable that is not a document value.
+#[derive(Clone)]
+enum Local {
+    Value(Value),
+    Function(Function),
+}
+
+/// A `fn` binding, with the scope it is written in.
+#[derive(Clone)]
+struct Function {
+    params: Vec<String>,
+    body: Spanned<EsonSegment>,
+    /// The dict or list it is written in.
+    here: Vec<Step>,
+    /// The locals in scope where it is written.
+    locals: Vec<(String, Local)>,
+}
+
+impl Function {
+    fn new(
+        params: &[String],
+        body: &Spanned<EsonSegment>,
+        here: &[Step],
+        locals: Vec<(String, Local)>,
+    ) -> Self {
+        Function {
+            params: params.to_vec(),
+            body: body.clone(),
+            here: here.to_vec(),
+            locals,
+        }
+    }
+}
+
+/// How deep calls of `fn` bindings may nest, which bounds recursion.
+const MAX_CALL_DEPTH: usize = 64;
+
+struct Evaluator<'a> {
+    root: &'a Spanned<EsonSegment>,
+    prelude: &'a IndexMap<Key, Spanned<EsonSegment>>,
+    /// Variables bound in the expression being evaluated, innermost last:
+    /// the bindings of dicts written inside it, which have no path, the
+    /// variables of the `for` items around it and the parameters of the
+    /// `fn` being called.
+    locals: Vec<(String, Local)>,
+    /// How deep the `fn` calls being evaluated are nested.
+    calls: usize,
+    /// Document values already evaluated, by path.
+    done: HashMap<Vec<Step>, Value>,
+    /// The document values being evaluated, outermost first.
+    active: Vec<Vec<Step>>,
+    /// The spreads, `if` entries and `if` or `for` items being merged to find what a
+    /// reference refers to, by the path of their dict or list and position.
+    merging: Vec<(Vec<Step>, usize)>,
+    /// The directory imports are looked up in.
+    dir: PathBuf,
+    imports: &'a mut Imports,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(document: &'a Document, dir: PathBuf, imports: &'a mut Imports) -> Self {
+        Evaluator {
+            root: &document.value,
+            prelude: &document.bindings,
+            locals: vec![],
+            calls: 0,
+            done: HashMap::new(),
+            active: vec![],
+            merging: vec![],
+            dir,
+            imports,
+        }
+    }
+
+    /// The document value at `path`.
+    fn node(&mut self, path: Vec<Step>) -> Result<Value, EvalError> {
+        if let Some(value) = self.done.get(&path) {
+            return Ok(value.clone());
+        }
+        let node = self.lookup(&path);
+        if let Some(start) = self.active.iter().position(|p| *p == path) {
+            let chain: Vec<_> = self.active[start..]
+                .iter()
+                .chain([&path])
+                .map(|p| describe(p))
+                .collect();
+            let message = format!("reference cycle: {}", chain.join(" -> "));
+            return Err(EvalError::new(message, node.span));
+        }
+        self.active.push(path.clone());
+        // the locals of whatever referred to this value are not in its scope
+        let locals = std::mem::take(&mut self.locals);
+        let value = match &node.node {
+            EsonSegment::Dict(entries) => self.dict(entries, &path, |this, key, _| {
+                this.node(child(&path, Step::Key(key.name.clone())))
+            }),
+            EsonSegment::List(items) => self.list(items, &path, |this, i, _| {
+                this.node(child(&path, Step::Index(i)))
+            }),
+            other => self.segment(other, node.span, scope(&path)),
+        };
+        self.locals = locals;
+        self.active.pop();
+        let value = value?;
+        self.done.insert(path, value.clone());
+        Ok(value)
+    }
+
+    /// The document node at `path`, which is known to exist.
+    fn lookup(&self, path: &[Step]) -> &'a Spanned<EsonSegment> {
+        let (root, path) = match path {
+            [Step::Prelude(name), rest @ ..] => (&self.prelude[&Key::binding(name)], rest),
+            _ => (self.root, path),
+        };
+        path.iter()
+            .fold(root, |node, step| match (&node.node, step) {
+                (EsonSegment::Dict(entries), Step::Key(key)) => &entries[&Key::from(key.as_str())],
+                (EsonSegment::Dict(entries), Step::Binding(name)) => &entries[&Key::binding(name)],
+                (EsonSegment::List(items), Step::Index(i)) => &items[*i],
+                _ => unreachable!("paths only lead through dicts and lists"),
+            })
+    }
+
+    /// The value of the variable `name`, from an expression inside `here`.
+    fn var(&mut self, name: &str, span: Span, here: &[Step]) -> Result<Value, EvalError> {
+        let function = || {
+            let message = format!("`{}` is a function, call it as `{}(...)`", name, name);
+            Err(EvalError::new(message, span))
+        };
+        match self.locals.iter().rev().find(|(local, _)| local == name) {
+            Some((_, Local::Value(value))) => return Ok(value.clone()),
+            Some((_, Local::Function(_))) => return function(),
+            None => {}
+        }
+        match self.binding(name, here) {
+            Some((_, node)) if matches!(node.node, EsonSegment::Fn(..)) => function(),
+            Some((path, _)) => self.node(path),
+            None => Err(EvalError::new(format!("unknown variable `{}`", name), span)),
+        }
+    }
+
+    /// The path and value of the innermost `let` binding of `name` around
+    /// `here`.
+    fn binding(&self, name: &str, here: &[Step]) -> Option<(Vec<Step>, &'a Spanned<EsonSegment>)> {
+        let key = Key::binding(name);
+        // a prelude value is not inside the root dict
+        let outermost = usize::from(matches!(here.first(), Some(Step::Prelude(_))));
+        for len in (outermost..=here.len()).rev() {
+            let scope = &here[..len];
+            if let EsonSegment::Dict(entries) = &self.lookup(scope).node {
+                if let Some(node) = entries.get(&key) {
+                    return Some((child(scope, Step::Binding(name.to_string())), node));
+                }
+            }
+        }
+        let node = self.prelude.get(&key)?;
+        Some((vec![Step::Prelude(name.to_string())], node))
+    }
+
+    /// The `fn` a call of `name` from an expression inside `here` calls, if
+    /// the innermost variable of that name is one. Other variables do not
+    /// hide the host's functions.
+    fn function(&self, name: &str, here: &[Step]) -> Option<Function> {
+        if let Some((_, local)) = self.locals.iter().rev().find(|(local, _)| local == name) {
+            return match local {
+                Local::Function(function) => Some(function.clone()),
+                Local::Value(_) => None,
+            };
+        }
+        let (path, node) = self.binding(name, here)?;
+        let EsonSegment::Fn(params, body) = &node.node else {
+            return None;
+        };
+        Some(Function::new(params, body, scope(&path), vec![]))
+    }
+
+    /// Call `function`, bound to `name`, with `args` from an expression
+    /// written at `span`.
+    fn call(
+        &mut self,
+        name: &str,
+        function: Function,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, EvalError> {
+        let expected = function.params.len();
+        if args.len() != expected {
+            let message = format!(
+                "`{}` takes {} argument{}, not {}",
+                name,
+                expected,
+                if expected == 1 { "" } else { "s" },
+                args.len()
+            );
+            return Err(EvalError::new(message, span));
+        }
+        if self.calls == MAX_CALL_DEPTH {
+            let message = format!("`{}` calls nest more than {} deep", name, MAX_CALL_DEPTH);
+            return Err(EvalError::new(message, span));
+        }
+        // the body sees the scope the function is written in, the function
+        // itself and its parameters
+        let mut locals = function.locals.clone();
+        locals.push((name.to_string(), Local::Function(function.clone())));
+        let Function {
+            params, body, here, ..
+        } = function;
+        locals.extend(params.into_iter().zip(args.into_iter().map(Local::Value)));
+        let outer = std::mem::replace(&mut self.locals, locals);
+        self.calls += 1;
+        let value = self.segment(&body.node, body.span, &here);
+        self.calls -= 1;
+        self.locals = outer;
+        value
+    }
+
+    /// The dict `entries` written at `here`, each value given by `value` and
+    /// each spread merged in where it is written: later keys override earlier
+    /// ones, except that a computed key may not repeat another key and a
+    /// `for` may not give a key in more than one round.
+    fn dict(
+        &mut self,
+        entries: &IndexMap<Key, Spanned<EsonSegment>>,
+        here: &[Step],
+        value: impl FnMut(&mut Self, &Key, &Spanned<EsonSegment>) -> Result<Value, EvalError>,
+    ) -> Result<Value, EvalError> {
+        let dict = self.entries(entries, here, &mut HashMap::new(), value)?;
+        Ok(Value::Dict(dict))
+    }
+
+    /// The entries of [`dict`](Self::dict), `keys` holds where the written
+    /// and computed keys so far come from and whether they are computed.
+    fn entries(
+        &mut self,
+        entries: &IndexMap<Key, Spanned<EsonSegment>>,
+        here: &[Step],
+        keys: &mut HashMap<String, (Span, bool)>,
+        mut value: impl FnMut(&mut Self, &Key, &Spanned<EsonSegment>) -> Result<Value, EvalError>,
+    ) -> Result<IndexMap<String, Value>, EvalError> {
+        let mut dict = IndexMap::new();
+        for (key, node) in entries {
+            match key.kind {
+                KeyKind::Entry => {
+                    given(keys, &key.name, key.span, false)?;
+                    dict.insert(key.name.clone(), value(self, key, node)?);
+                }
+                KeyKind::Let => {}
+                _ => {
+                    let spread = self.spread_dict(node, here, keys)?;
+                    dict.extend(spread);
+                }
+            }
+        }
+        Ok(dict)
+    }
+
+    /// The list `items` written at `here`, each item given by `value` and
+    /// each spread and `if` item spliced in where it is written.
+    fn list(
+        &mut self,
+        items: &[Spanned<EsonSegment>],
+        here: &[Step],
+        mut value: impl FnMut(&mut Self, usize, &Spanned<EsonSegment>) -> Result<Value, EvalError>,
+    ) -> Result<Value, EvalError> {
+        let mut list = Vec::new();
+        for (i, node) in items.iter().enumerate() {
+            if !is_merged(&node.node) {
+                list.push(value(self, i, node)?);
+                continue;
+            }
+            let spread = self.spread_list(node, here)?;
+            list.extend(spread);
+        }
+        Ok(Value::List(list))
+    }
+
+    /// The entries of the `...expr`, `if`, `for` or computed entry written in
+    /// the dict at `here`, `keys` holds the keys of [`entries`](Self::entries).
+    fn spread_dict(
+        &mut self,
+        node: &Spanned<EsonSegment>,
+        here: &[Step],
+        keys: &mut HashMap<String, (Span, bool)>,
+    ) -> Result<IndexMap<String, Value>, EvalError> {
+        let inline = |this: &mut Self, _: &Key, value: &Spanned<EsonSegment>| {
+            this.segment(&value.node, value.span, here)
+        };
+        match &node.node {
+            EsonSegment::If(condition, entry) => {
+                if !self.condition(condition, node.span, here)? {
+                    return Ok(IndexMap::new());
+                }
+                self.entries(clause_entry(entry), here, keys, inline)
+            }
+            EsonSegment::For(names, iterable, entry) => {
+                let mut dict = IndexMap::new();
+                for round in self.rounds(names, iterable, node.span, here)? {
+                    let start = self.locals.len();
+                    self.locals.extend(
+                        names
+                            .iter()
+                            .cloned()
+                            .zip(round.into_iter().map(Local::Value)),
+                    );
+                    let entries = self.entries(clause_entry(entry), here, keys, inline);
+                    self.locals.truncate(start);
+                    dict.extend(entries?);
+                }
+                Ok(dict)
+            }
+            EsonSegment::Computed(key, value) => {
+                let name = match self.segment(&key.node, key.span, here)? {
+                    Value::Str(name) => name,
+                    other => {
+                        let message = format!("a key needs a string, not {}", other.type_name());
+                        return Err(EvalError::new(message, key.span));
+                    }
+                };
+                given(keys, &name, key.span, true)?;
+                let value = self.segment(&value.node, value.span, here)?;
+                Ok(IndexMap::from([(name, value)]))
+            }
+            _ => match self.spread(node, here)? {
+                Value::Dict(entries) => Ok(entries),
+                other => {
+                    let message = format!(
+                        "a dict can only spread a dict, not {}{}",
+                        other.type_name(),
+                        self.written_at(node, here)
+                    );
+                    Err(EvalError::new(message, node.span))
+                }
+            },
+        }
+    }
+
+    /// The items of the `...expr`, `if` or `for` item written in the list at
+    /// `here`.
+    fn spread_list(
+        &mut self,
+        node: &Spanned<EsonSegment>,
+        here: &[Step],
+    ) -> Result<Vec<Value>, EvalError> {
+        if let EsonSegment::For(names, iterable, item) = &node.node {
+            let mut items = Vec::new();
+            for round in self.rounds(names, iterable, node.span, here)? {
+                let start = self.locals.len();
+                self.locals.extend(
+                    names
+                        .iter()
+                        .cloned()
+                        .zip(round.into_iter().map(Local::Value)),
+                );
+                let values = if is_merged(&item.node) {
+                    self.spread_list(item, here)
+                } else {
+                    self.segment(&item.node, item.span, here)
+                        .map(|value| vec![value])
+                };
+                self.locals.truncate(start);
+                items.extend(values?);
+            }
+            return Ok(items);
+        }
+        if let EsonSegment::If(condition, item) = &node.node {
+            if !self.condition(condition, node.span, here)? {
+                return Ok(vec![]);
+            }
+            if is_merged(&item.node) {
+                return self.spread_list(item, here);
+            }
+            return Ok(vec![self.segment(&item.node, item.span, here)?]);
+        }
+        match self.spread(node, here)? {
+            Value::List(items) => Ok(items),
+            other => {
+                let message = format!(
+                    "a list can only spread a list, not {}{}",
+                    other.type_name(),
+                    self.written_at(node, here)
+                );
+                Err(EvalError::new(message, node.span))
+            }
+        }
+    }
+
+    /// Whether the `if` written at `span` holds.
+    fn condition(
+        &mut self,
+        chunk: &ExprTokenChunk,
+        span: Span,
+        here: &[Step],
+    ) -> Result<bool, EvalError> {
+        let expr = chunk
+            .to_expr()
+            .map_err(|e| EvalError::new(e.to_string(), span))?;
+        match self.expr(&expr, span, here)? {
+            Value::Bool(b) => Ok(b),
+            other => {
+                let message = format!("`if` needs a bool, not {}", other.type_name());
+                Err(EvalError::new(message, span))
+            }
+        }
+    }
+
+    /// The values the variables `names` of the `for` written at `span` take
+    /// in each round: the items of a list, or the keys of a dict, with their
+    /// index or value as a second variable.
+    fn rounds(
+        &mut self,
+        names: &[String],
+        chunk: &ExprTokenChunk,
+        span: Span,
+        here: &[Step],
+    ) -> Result<Vec<Vec<Value>>, EvalError> {
+        let expr = chunk
+            .to_expr()
+            .map_err(|e| EvalError::new(e.to_string(), span))?;
+        let pair = names.len() == 2;
+        Ok(match self.expr(&expr, span, here)? {
+            Value::List(items) if pair => items
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| vec![Value::Int(i as i64), item])
+                .collect(),
+            Value::List(items) => items.into_iter().map(|item| vec![item]).collect(),
+            Value::Dict(entries) if pair => entries
+                .into_iter()
+                .map(|(key, value)| vec![Value::Str(key), value])
+                .collect(),
+            Value::Dict(entries) => entries
+                .into_keys()
+                .map(|key| vec![Value::Str(key)])
+                .collect(),
+            other => {
+                let message = format!("`for` needs a list or dict, not {}", other.type_name());
+                return Err(EvalError::new(message, span));
+            }
+        })
+    }
+
+    fn spread(&mut self, node: &Spanned<EsonSegment>, here: &[Step]) -> Result<Value, EvalError> {
+        let EsonSegment::Spread(chunk) = &node.node else {
+            unreachable!("only spreads are merged")
+        };
+        let expr = chunk
+            .to_expr()
+            .map_err(|e| EvalError::new(e.to_string(), node.span))?;
+        self.expr(&expr, node.span, here)
+    }
+
+    /// `, written at line L, column C` when the spread `node` is a reference
+    /// to a value written in the document, for errors about that value.
+    fn written_at(&mut self, node: &Spanned<EsonSegment>, here: &[Step]) -> String {
+        let EsonSegment::Spread(chunk) = &node.node else {
+            return String::new();
+        };
+        let [Spanned {
+            node: ExprToken::Ref(pronoun),
+            ..
+        }] = chunk.0.as_slice()
+        else {
+            return String::new();
+        };
+        match self.referent(pronoun, node.span, here) {
+            Ok(Referent::Node(path)) => {
+                let span = self.lookup(&path).span;
+                format!(", written at line {}, column {}", span.line, span.column)
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// A value written at `span`, inside the dict or list at `here`.
+    fn segment(
+        &mut self,
+        segment: &EsonSegment,
+        span: Span,
+        here: &[Step],
+    ) -> Result<Value, EvalError> {
+        Ok(match segment {
+            EsonSegment::Null => Value::Null,
+            EsonSegment::Str(s) => Value::Str(s.clone()),
+            EsonSegment::Template(parts) => {
+                let mut text = String::new();
+                for part in parts {
+                    match part {
+                        TemplatePart::Text(s) => text.push_str(s),
+                        TemplatePart::Expr(chunk, spec) => {
+                            let expr = chunk
+                                .to_expr()
+                                .map_err(|e| EvalError::new(e.to_string(), chunk.span))?;
+                            let value = self.expr(&expr, chunk.span, here)?;
+                            let rendered = render(&value, spec.as_ref())
+                                .map_err(|message| EvalError::new(message, chunk.span))?;
+                            text.push_str(&rendered);
+                        }
+                    }
+                }
+                Value::Str(text)
+            }
+            EsonSegment::Boolean(b) => Value::Bool(*b),
+            EsonSegment::Int(i) => Value::Int(*i),
+            EsonSegment::BigInt(i) => Value::BigInt(i.clone()),
+            EsonSegment::Float(x) => Value::Float(*x),
+            EsonSegment::Decimal(d) => Value::Decimal(d.clone()),
+            EsonSegment::Duration(d) => Value::Duration(*d),
+            EsonSegment::ByteSize(s) => Value::ByteSize(*s),
+            EsonSegment::Temporal(t) => Value::Temporal(*t),
+            EsonSegment::Bytes(b) => Value::Bytes(b.clone()),
+            EsonSegment::List(items) => self.list(items, here, |this, _, item| {
+                this.segment(&item.node, item.span, here)
+            })?,
+            EsonSegment::Dict(entries) => {
+                // a dict written in an expression binds its variables in order
+                let start = self.locals.len();
+                let dict = self.inline_bindings(entries, here).and_then(|_| {
+                    self.dict(entries, here, |this, _, value| {
+                        this.segment(&value.node, value.span, here)
+                    })
+                });
+                self.locals.truncate(start);
+                dict?
+            }
+            EsonSegment::Expr(chunk) => {
+                let expr = chunk
+                    .to_expr()
+                    .map_err(|e| EvalError::new(e.to_string(), span))?;
+                self.expr(&expr, span, here)?
+            }
+            EsonSegment::Spread(_)
+            | EsonSegment::If(..)
+            | EsonSegment::For(..)
+            | EsonSegment::Computed(..) => {
+                unreachable!("spreads, clauses and computed keys are merged by their dict or list")
+            }
+            EsonSegment::Fn(..) => unreachable!("a `fn` binding is only called"),
+            EsonSegment::Error => {
+                return Err(EvalError::new("the document has syntax errors", span));
+            }
+        })
+    }
+
+    fn inline_bindings(
+        &mut self,
+        entries: &IndexMap<Key, Spanned<EsonSegment>>,
+        here: &[Step],
+    ) -> Result<(), EvalError> {
+        for (key, node) in entries {
+            if key.kind == KeyKind::Let {
+                let local = match &node.node {
+                    EsonSegment::Fn(params, body) => {
+                        let locals = self.locals.clone();
+                        Local::Function(Function::new(params, body, here, locals))
+                    }
+                    other => Local::Value(self.segment(other, node.span, here)?),
+                };
+                self.locals.push((key.name.clone(), local));
+            }
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &ExprChunk, span: Span, here: &[Step]) -> Result<Value, EvalError> {
+        let error = |message: String| EvalError::new(message, span);
+        match expr {
+            ExprChunk::Primary(ExprToken::Val(segment)) => self.segment(segment, span, here),
+            ExprChunk::Primary(ExprToken::Ref(pronoun)) => self.reference(pronoun, span, here),
+            ExprChunk::Primary(ExprToken::Var(name)) => self.var(name, span, here),
+            ExprChunk::Primary(ExprToken::FnCall(name, args)) => {
+                let args = self.args(vec![], args, span, here)?;
+                self.apply(name, args, span, here)
+            }
+            ExprChunk::Primary(ExprToken::Group(chunk)) => {
+                let inner = chunk.to_expr().map_err(|e| error(e.to_string()))?;
+                self.expr(&inner, span, here)
+            }
+            ExprChunk::PrefixOp(op, operand) => {
+                let operand = self.expr(operand, span, here)?;
+                ops::unary(op, operand).map_err(error)
+            }
+            ExprChunk::InfixOp(op @ (ExprToken::And | ExprToken::Or), lhs, rhs) => {
+                let stop = matches!(op, ExprToken::Or);
+                for operand in [lhs, rhs] {
+                    match self.expr(operand, span, here)? {
+                        Value::Bool(b) if b == stop => return Ok(Value::Bool(stop)),
+                        Value::Bool(_) => {}
+                        other => {
+                            return Err(error(format!(
+                                "'{}' expects bools, found {}",
+                                ops::symbol(op),
+                                other.type_name()
+                            )))
+                        }
+                    }
+                }
+                Ok(Value::Bool(!stop))
+            }
+            ExprChunk::Ternary(condition, then, otherwise) => {
+                match self.expr(condition, span, here)? {
+                    Value::Bool(true) => self.expr(then, span, here),
+                    Value::Bool(false) => self.expr(otherwise, span, here),
+                    other => Err(error(format!(
+                        "'?' needs a bool, not {}",
+                        other.type_name()
+                    ))),
+                }
+            }
+            ExprChunk::InfixOp(ExprToken::Pipe, lhs, rhs) => {
+                let lhs = self.expr(lhs, span, here)?;
+                match rhs.as_ref() {
+                    ExprChunk::Primary(ExprToken::FnCall(name, args)) => {
+                        let args = self.args(vec![lhs], args, span, here)?;
+                        self.apply(name, args, span, here)
+                    }
+                    ExprChunk::Primary(ExprToken::Var(name)) => {
+                        self.apply(name, vec![lhs], span, here)
+                    }
+                    _ => Err(error(format!("unsupported expression {}", expr))),
+                }
+            }
+            ExprChunk::InfixOp(op, lhs, rhs) => {
+                let lhs = self.expr(lhs, span, here)?;
+                let rhs = self.expr(rhs, span, here)?;
+                ops::binary(op, lhs, rhs).map_err(error)
+            }
+            _ => Err(error(format!("unsupported expression {}", expr))),
+        }
+    }
+
+    /// `values` followed by the values of the argument expressions `args`.
+    fn args(
+        &mut self,
+        mut values: Vec<Value>,
+        args: &[ExprTokenChunk],
+        span: Span,
+        here: &[Step],
+    ) -> Result<Vec<Value>, EvalError> {
+        for arg in args {
+            let arg = arg
+                .to_expr()
+                .map_err(|e| EvalError::new(e.to_string(), span))?;
+            values.push(self.expr(&arg, span, here)?);
+        }
+        Ok(values)
+    }
+
+    /// Call the function `name` with `args`: a `fn` binding in scope, else
+    /// `import` or a built-in function.
+    fn apply(
+        &mut self,
+        name: &str,
+        args: Vec<Value>,
+        span: Span,
+        here: &[Step],
+    ) -> Result<Value, EvalError> {
+        let error = |message: String| EvalError::new(message, span);
+        if let Some(function) = self.function(name, here) {
+            return self.call(name, function, args, span);
+        }
+        match (name, args.as_slice()) {
+            ("import", [Value::Str(path)]) => self.import(path, span),
+            ("import", _) => {
+                let types: Vec<_> = args.iter().map(Value::type_name).collect();
+                Err(error(format!(
+                    "import() does not take ({})",
+                    types.join(", ")
+                )))
+            }
+            _ => functions::call(name, args).map_err(error),
+        }
+    }
+
+    /// The value of the file `import(path)`, written at `span`, refers to.
+    fn import(&mut self, path: &str, span: Span) -> Result<Value, EvalError> {
+        let error = |message: String| EvalError::new(message, span);
+        let file = self
+            .imports
+            .resolve(&self.dir, path)
+            .ok_or_else(|| error(format!("cannot find {:?}", path)))?;
+        let canonical = std::fs::canonicalize(&file)
+            .map_err(|e| error(format!("cannot read {:?}: {}", path, e)))?;
+        if let Some(value) = self.imports.done.get(&canonical) {
+            return Ok(value.clone());
+        }
+        if let Some(cycle) = self.imports.cycle(&canonical, path) {
+            return Err(error(cycle));
+        }
+        let source = std::fs::read_to_string(&canonical)
+            .map_err(|e| error(format!("cannot read {:?}: {}", path, e)))?;
+        let importer = self.imports.active.last().map(|(file, _)| file.clone());
+        let document = parser::root_with(&source, &self.imports.parse).map_err(|e| {
+            let message = format!("expected {}, found {}", e.expected(), e.found());
+            EvalError::new(message, e.span()).imported(&canonical, importer.clone(), span)
+        })?;
+
+        let dir = canonical.parent().map(PathBuf::from).unwrap_or_default();
+        self.imports
+            .active
+            .push((canonical.clone(), path.to_string()));
+        let value = Evaluator::new(&document, dir, self.imports).node(vec![]);
+        self.imports.active.pop();
+        let value = value.map_err(|e| e.imported(&canonical, importer, span))?;
+        self.imports.done.insert(canonical, value.clone());
+        Ok(value)
+    }
+
+    /// Where `index` leads in the dict or list with spreads at `path`: to an
+    /// entry or item written there, or into the value of a spread. Only the
+    /// spreads needed are evaluated, not the whole dict or list, so its own
+    /// entries can refer to each other.
+    fn through_spreads(
+        &mut self,
+        path: &[Step],
+        index: &RefIndex,
+    ) -> Result<Option<Found>, EvalError> {
+        match (&self.lookup(path).node, index) {
+            (EsonSegment::Dict(entries), RefIndex::Str(key)) => {
+                // the last entry with the key wins
+                for (n, (written, node)) in entries.iter().enumerate().rev() {
+                    match written.kind {
+                        KeyKind::Entry if written.name == *key => {
+                            return Ok(Some(Found::Node(Step::Key(key.clone()))));
+                        }
+                        KeyKind::Entry | KeyKind::Let => {}
+                        _ if may_give(&node.node, key) => {
+                            let what = format!("{}.{}", describe(path), key);
+                            let mut merged = self.merging(path, n, node.span, what, |this| {
+                                this.spread_dict(node, path, &mut HashMap::new())
+                            })?;
+                            if let Some(value) = merged.swap_remove(key) {
+                                return Ok(Some(Found::Value(value)));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(None)
+            }
+            (EsonSegment::List(items), RefIndex::Int(index)) => {
+                let Ok(mut i) = usize::try_from(*index) else {
+                    return Ok(None);
+                };
+                for (n, node) in items.iter().enumerate() {
+                    if !is_merged(&node.node) {
+                        if i == 0 {
+                            return Ok(Some(Found::Node(Step::Index(n))));
+                        }
+                        i -= 1;
+                        continue;
+                    }
+                    let what = format!("{}[{}]", describe(path), index);
+                    let mut spliced = self.merging(path, n, node.span, what, |this| {
+                        this.spread_list(node, path)
+                    })?;
+                    if i < spliced.len() {
+                        return Ok(Some(Found::Value(spliced.swap_remove(i))));
+                    }
+                    i -= spliced.len();
+                }
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// `merge` the spread or `if` at position `n` of the dict or list at
+    /// `path`, to find `what` a reference refers to. A reference that needs
+    /// the very spread it is looked for in is a cycle.
+    fn merging<T>(
+        &mut self,
+        path: &[Step],
+        n: usize,
+        span: Span,
+        what: String,
+        merge: impl FnOnce(&mut Self) -> Result<T, EvalError>,
+    ) -> Result<T, EvalError> {
+        let merging = (path.to_vec(), n);
+        if self.merging.contains(&merging) {
+            let message = format!("reference cycle: {} depends on itself", what);
+            return Err(EvalError::new(message, span));
+        }
+        self.merging.push(merging);
+        // like a node, the spread is not in the scope of the reference's locals
+        let locals = std::mem::take(&mut self.locals);
+        let merged = merge(self);
+        self.locals = locals;
+        self.merging.pop();
+        merged
+    }
+
+    /// The value `pronoun` refers to, from an expression inside `here`.
+    fn reference(
+        &mut self,
+        pronoun: &RefPronoun,
+        span: Span,
+        here: &[Step],
+    ) -> Result<Value, EvalError> {
+        match self.referent(pronoun, span, here)? {
+            Referent::Node(path) => self.node(path),
+            Referent::Value(value) => Ok(value),
+        }
+    }
+
+    /// What `pronoun` refers to, from an expression inside `here`.
+    fn referent(
+        &mut self,
+        pronoun: &RefPronoun,
+        span: Span,
+        here: &[Step],
+    ) -> Result<Referent, EvalError> {
+        let missing = |len| {
+            let missing = describe_ref(pronoun, len);
+            EvalError::new(format!("{} does not exist", missing), span)
+        };
+        let (mut path, indices) = match pronoun {
+            // a variable may hold a value of no node, index into the value
+            RefPronoun::Var(name, indices) => {
+                let mut value = self.var(name, span, here)?;
+                for (n, index) in indices.iter().enumerate() {
+                    value = index_value(value, index).ok_or_else(|| missing(n + 1))?;
+                }
+                return Ok(Referent::Value(value));
+            }
+            RefPronoun::Root(indices) => (vec![], indices),
+            RefPronoun::Curr(indices) => (here.to_vec(), indices),
+            RefPronoun::Super(indices) => match here.split_last() {
+                Some((_, parent)) => (parent.to_vec(), indices),
+                None => return Err(EvalError::new("`super` of the root", span)),
+            },
+        };
+        for (n, index) in indices.iter().enumerate() {
+            let node = &self.lookup(&path).node;
+            let found = match (node, index) {
+                _ if has_spread(node) => self.through_spreads(&path, index)?,
+                (EsonSegment::Dict(entries), RefIndex::Str(key)) => entries
+                    .contains_key(&Key::from(key.as_str()))
+                    .then(|| Found::Node(Step::Key(key.clone()))),
+                (EsonSegment::List(items), RefIndex::Int(i)) => usize::try_from(*i)
+                    .ok()
+                    .filter(|i| *i < items.len())
+                    .map(|i| Found::Node(Step::Index(i))),
+                (EsonSegment::Dict(_) | EsonSegment::List(_), _) => None,
+                // an expression, index into what it evaluates to
+                _ => index_value(self.node(path.clone())?, index).map(Found::Value),
+            };
+            match found {
+                Some(Found::Node(step)) => path.push(step),
+                Some(Found::Value(mut value)) => {
+                    for (m, index) in indices.iter().enumerate().skip(n + 1) {
+                        value = index_value(value, index).ok_or_else(|| missing(m + 1))?;
+                    }
+                    return Ok(Referent::Value(value));
+                }
+                None => return Err(missing(n + 1)),
+            }
+        }
+        Ok(Referent::Node(path))
+    }
+}
+
+fn child(path: &[Step], step: Step) -> Vec<Step> {
+    let mut path = path.to_vec();
+    path.push(step);
+    path
+}
+
+/// Whether the dict or list has spreads or `if` entries or items, so its
+/// entries or items are not all written in it.
+fn has_spread(segment: &EsonSegment) -> bool {
+    match segment {
+        EsonSegment::Dict(entries) => entries
+            .keys()
+            .any(|key| !matches!(key.kind, KeyKind::Entry | KeyKind::Let)),
+        EsonSegment::List(items) => items.iter().any(|item| is_merged(&item.node)),
+        _ => false,
+    }
+}
+
+/// Whether the spread, clause or computed entry `node` may give `key`: the
+/// entries an `if` or `for` gives are written out, unless they come from a
+/// spread or have a computed key.
+fn may_give(node: &EsonSegment, key: &str) -> bool {
+    let (EsonSegment::If(_, entry) | EsonSegment::For(_, _, entry)) = node else {
+        return true;
+    };
+    clause_entry(entry)
+        .iter()
+        .any(|(written, node)| match written.kind {
+            KeyKind::Entry => written.name == key,
+            KeyKind::Let => false,
+            _ => may_give(&node.node, key),
+        })
+}
+
+/// Record that the dict being evaluated gets the written or `computed` key
+/// `name` from `span`, unless that repeats a key it may not.
+fn given(
+    keys: &mut HashMap<String, (Span, bool)>,
+    name: &str,
+    span: Span,
+    computed: bool,
+) -> Result<(), EvalError> {
+    match keys.insert(name.to_string(), (span, computed)) {
+        // the entry of a `for` gives its key in each round
+        Some((first, _)) if first.start == span.start => {
+            let message = format!(
+                "duplicate key {:?}, given by more than one round of the `for`",
+                name
+            );
+            Err(EvalError::new(message, span))
+        }
+        Some((first, first_computed)) if computed || first_computed => {
+            let message = format!(
+                "duplicate key {:?}, first defined at line {}, column {}",
+                name, first.line, first.column
+            );
+            Err(EvalError::new(message, span))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The entry of an `if` or `for` entry, as the one-entry dict it is held in.
+fn clause_entry(entry: &Spanned<EsonSegment>) -> &IndexMap<Key, Spanned<EsonSegment>> {
+    let EsonSegment::Dict(entries) = &entry.node else {
+        unreachable!("an `if` or `for` entry holds a dict")
+    };
+    entries
+}
+
+/// Whether a list item is spliced into its list rather than being an item.
+fn is_merged(item: &EsonSegment) -> bool {
+    matches!(
+        item,
+        EsonSegment::Spread(_) | EsonSegment::If(..) | EsonSegment::For(..)
+    )
+}
+
+fn index_value(value: Value, index: &RefIndex) -> Option<Value> {
+    match (value, index) {
+        (Value::Dict(mut entries), RefIndex::Str(key)) => entries.swap_remove(key),
+        (Value::List(mut items), RefIndex::Int(i)) => {
+            let i = usize::try_from(*i).ok().filter(|i| *i < items.len())?;
+            Some(items.swap_remove(i))
+        }
+        _ => None,
+    }
+}
+
+/// The dict or list the value at `path` is written in, or for a value in
+/// the prelude its own path.
+fn scope(path: &[Step]) -> &[Step] {
+    match path {
+        [Step::Prelude(_)] => path,
+        _ => &path[..path.len().saturating_sub(1)],
+    }
+}
+
+/// A path in reference syntax, e.g. `$.server.ports[0]`, or from the
+/// variable it goes through, e.g. `limits.max`.
+fn describe(path: &[Step]) -> String {
+    let start = path
+        .iter()
+        .rposition(|step| matches!(step, Step::Binding(_) | Step::Prelude(_)));
+    let mut out = String::from("$");
+    for step in &path[start.unwrap_or(0)..] {
+        match step {
+            Step::Key(key) => out.push_str(&format!(".{}", key)),
+            Step::Index(i) => out.push_str(&format!("[{}]", i)),
+            Step::Binding(name) | Step::Prelude(name) => out = name.clone(),
+        }
+    }
+    out
+}
+
+/// The first `len` indices of a reference, in reference syntax.
+fn describe_ref(pronoun: &RefPronoun, len: usize) -> String {
+    let (head, indices) = match pronoun {
+        RefPronoun::Var(name, indices) => (name.as_str(), indices),
+        RefPronoun::Root(indices) => ("$", indices),
+        RefPronoun::Curr(indices) => ("self", indices),
+        RefPronoun::Super(indices) => ("super", indices),
+    };
+    let mut out = head.to_string();
+    for index in &indices[..len] {
+        match index {
+            RefIndex::Str(key) => out.push_str(&format!(".{}", key)),
+            RefIndex::Int(i) => out.push_str(&format!("[{}]", i)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use parser::ByteSize;
+
+    use super::*;
+
+    fn eval(src: &str) -> Result<Value, EvalError> {
+        evaluate(&parser::root(src).unwrap())
+    }
+
+    #[test]
+    fn test_references() {
+        let value = eval(
+            r#"{
+                name: "api",
+                server: { host: "localhost", port: 8080, next: ${ self.port + 1 } },
+                url: ${ $.server.host + ":" + str($.server.port) },
+                tags: ["a", ${ super.name }],
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(value.get("url"), Some(&Value::from("localhost:8080")));
+        assert_eq!(
+            value.get("server").unwrap().get("next"),
+            Some(&Value::Int(8081))
+        );
+        assert_eq!(
+            value.get("tags"),
+            Some(&Value::List(vec!["a".into(), "api".into()]))
+        );
+    }
+
+    #[test]
+    fn test_units() {
+        let value = eval(
+            r#"{
+                timeout: 30s,
+                retry: ${ $.timeout + 250ms },
+                slow: ${ $.retry > 30s },
+                buffer: ${ 10MiB * 2 },
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            value.get("retry"),
+            Some(&Value::Duration(Duration::from_millis(30_250)))
+        );
+        assert_eq!(value.get("slow"), Some(&Value::Bool(true)));
+        assert_eq!(
+            value.get("buffer"),
+            Some(&Value::ByteSize(ByteSize(20 << 20)))
+        );
+    }
+
+    #[test]
+    fn test_temporal() {
+        let value = eval(
+            r#"{
+                released: 2024-05-01T12:00:00Z,
+                expires: ${ $.released + 30d },
+                day: ${ date($.released) },
+                expired: ${ $.expires < 2024-06-01T00:00:00Z },
+                year: ${ format($.released, "YYYY") },
+            }"#,
+        )
+        .unwrap();
+        let at = |s: &str| Some(Value::Temporal(s.parse().unwrap()));
+        assert_eq!(value.get("expires").cloned(), at("2024-05-31T12:00:00Z"));
+        assert_eq!(value.get("day").cloned(), at("2024-05-01"));
+        assert_eq!(value.get("expired"), Some(&Value::Bool(true)));
+        assert_eq!(value.get("year"), Some(&Value::from("2024")));
+    }
+
+    #[test]
+    fn test_bytes() {
+        let value = eval(
+            r#"{
+                magic: hex"cafe",
+                header: ${ $.magic + b"\x00\x01" },
+                size: ${ len($.header) },
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            value.get("header"),
+            Some(&Value::Bytes(vec![0xca, 0xfe, 0, 1]))
+        );
+        assert_eq!(value.get("size"), Some(&Value::Int(4)));
+        assert_eq!(value.get("magic").unwrap().to_string(), r#"b64"yv4=""#);
+    }
+
+    #[test]
+    fn test_templates() {
+        let value = eval(
+            r##"{
+                host: "db",
+                port: 5432,
+                url: f"postgres://${ $.host }:${ $.port }/app",
+                tags: f#"${ [1, "a"] } \${x}"#,
+                query: f"""
+                    SELECT * FROM ${ upper($.host) }
+                    """,
+            }"##,
+        )
+        .unwrap();
+        assert_eq!(
+            value.get("url"),
+            Some(&Value::from("postgres://db:5432/app"))
+        );
+        assert_eq!(value.get("tags"), Some(&Value::from(r#"[1, "a"] ${x}"#)));
+        assert_eq!(value.get("query"), Some(&Value::from("SELECT * FROM DB")));
+
+        let value = eval(
+            r##"{
+                port: 80,
+                ratio: 0.256,
+                items: ["a", "b"],
+                line: f#"${ $.port:05 } ${ $.ratio:.2f } [${ "api":>5 }] ${ $.items:join(", ") }"#,
+                json: f#"echo ${ $.items:json }"#,
+                plain: f"${ $.items:join(' & ') }",
+            }"##,
+        )
+        .unwrap();
+        assert_eq!(
+            value.get("line"),
+            Some(&Value::from("00080 0.26 [  api] a, b"))
+        );
+        assert_eq!(value.get("json"), Some(&Value::from(r#"echo ["a","b"]"#)));
+        assert_eq!(value.get("plain"), Some(&Value::from("a & b")));
+        let err = eval(r#"{a: f"x ${ 1:x }", b: f"${ 1.5:x }"}"#).unwrap_err();
+        assert_eq!(err.message(), "format `x` needs an int, not float");
+        assert_eq!((err.span().line, err.span().column), (1, 25));
+        let err = eval("{a: 1,\n b: f\"x ${ $.c }\"}").unwrap_err();
+        assert_eq!(err.message(), "$.c does not exist");
+        assert_eq!((err.span().line, err.span().column), (2, 9));
+    }
+
+    #[test]
+    fn test_spread() {
+        let value = eval(
+            r#"{
+                defaults: { host: "localhost", port: 80, tls: false },
+                server: {
+                    ...$.defaults,
+                    port: 8080,
+                    ...{ tls: true },
+                    url: f"${ self.host }:${ self.port }",
+                },
+                ports: [1, ...[2, 3], ...$.extra, 6],
+                extra: [4, 5],
+                fifth: ${ $.ports[4] },
+                tls: ${ $.server.tls },
+            }"#,
+        )
+        .unwrap();
+        let server = value.get("server").unwrap();
+        assert_eq!(
+            server.to_string(),
+            r#"{"host": "localhost", "port": 8080, "tls": true, "url": "localhost:8080"}"#
+        );
+        let ports = (1..=6).map(Value::Int).collect();
+        assert_eq!(value.get("ports"), Some(&Value::List(ports)));
+        assert_eq!(value.get("fifth"), Some(&Value::Int(5)));
+        assert_eq!(value.get("tls"), Some(&Value::Bool(true)));
+        let value = eval("{ port: 1, ...{ port: 2, tls: true } }").unwrap();
+        assert_eq!(value.to_string(), r#"{"port": 2, "tls": true}"#);
+
+        let err = eval("{a: [1],\n b: {...$.a}}").unwrap_err();
+        assert_eq!(
+            err.message(),
+            "a dict can only spread a dict, not list, written at line 1, column 5"
+        );
+        assert_eq!((err.span().line, err.span().column), (2, 6));
+        let err = eval("[...{a: 1}]").unwrap_err();
+        assert_eq!(err.message(), "a list can only spread a list, not dict");
+        let err = eval("{a: {...$.a}}").unwrap_err();
+        assert_eq!(err.message(), "reference cycle: $.a -> $.a");
+        let err = eval("{a: {...{}}, b: ${ $.a.c }}").unwrap_err();
+        assert_eq!(err.message(), "$.a.c does not exist");
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let value = eval(
+            r#"{
+                env: "dev",
+                if $.env == "dev": debug: true,
+                if $.env != "dev": level: "warn",
+                if self.env == "dev": if true: trace: ${ !$.debug },
+                ports: [80, if $.debug: 8080, if false: 443, if true: ...[1, 2]],
+                port: ${ $.ports[1] },
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            value.to_string(),
+            r#"{"env": "dev", "debug": true, "trace": false, "ports": [80, 8080, 1, 2], "port": 8080}"#
+        );
+
+        let err = eval("{ a: 1, if false: b: 2, c: ${ $.b } }").unwrap_err();
+        assert_eq!(err.message(), "$.b does not exist");
+        let err = eval("{ a: true, if self.a: a: false }").unwrap_err();
+        assert_eq!(err.message(), "reference cycle: $.a depends on itself");
+        let err = eval("{ b: 1, ...{ a: ${ $.a } } }").unwrap_err();
+        assert_eq!(err.message(), "reference cycle: $.a depends on itself");
+        let err = eval("{ a: 1,\n  if $.a: b: 2 }").unwrap_err();
+        assert_eq!(err.message(), "`if` needs a bool, not int");
+        assert_eq!((err.span().line, err.span().column), (2, 3));
+    }
+
+    #[test]
+    fn test_comprehensions() {
+        let value = eval(
+            r#"{
+                regions: [{ name: "eu", enabled: true }, { name: "us", enabled: false }],
+                hosts: [for r in $.regions if r.enabled: { host: f"${ r.name }.example.com" }],
+                ports: { http: 80, https: 443 },
+                listen: [0, for name, port in $.ports: f"${ name }=${ port }"],
+                grid: [for i, row in [[1, 2], [3]]: for x in row: ${ i * 10 + x }],
+                names: [for r in $.regions: ${ r["name"] }, for k in $.ports: ${ k }],
+                second: ${ $.grid[1] },
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            value.to_string(),
+            r#"{"regions": [{"name": "eu", "enabled": true}, {"name": "us", "enabled": false}], "hosts": [{"host": "eu.example.com"}], "ports": {"http": 80, "https": 443}, "listen": [0, "http=80", "https=443"], "grid": [1, 2, 13], "names": ["eu", "us", "http", "https"], "second": 2}"#
+        );
+        // the loop variable shadows a binding, only inside the loop
+        let value = eval("let x = 0; [for x in [1, 2]: ${ x }, ${ x }]").unwrap();
+        assert_eq!(value.to_string(), "[1, 2, 0]");
+
+        let err = eval(
+            "{ n: 1,
+  xs: [for x in $.n: 1] }",
+        )
+        .unwrap_err();
+        assert_eq!(err.message(), "`for` needs a list or dict, not int");
+        assert_eq!((err.span().line, err.span().column), (2, 8));
+        let err = eval("[for r in [{ a: 1 }]: ${ r.name }]").unwrap_err();
+        assert_eq!(err.message(), "r.name does not exist");
+        let err = eval("[for r in [1]: ${ s }]").unwrap_err();
+        assert_eq!(err.message(), "unknown variable `s`");
+    }
+
+    #[test]
+    fn test_dict_comprehensions() {
+        let value = eval(
+            r#"{
+                services: [{ name: "api", port: 80 }, { name: "db", port: 5432, internal: true }],
+                ports: { for s in $.services: [s.name]: ${ s.port } },
+                public: { for s in $.services if s["port"] < 1000: f"${ s.name }_url": f"http://${ s.name }" },
+                flipped: { for k, v in $.ports: [f"p${ v }"]: ${ k } },
+                api: ${ $.ports.api },
+                none: { for x in []: [x]: 1 },
+            }"#,
+        )
+        .unwrap();
+        let Value::Dict(dict) = value else {
+            panic!("expected a dict");
+        };
+        assert_eq!(dict["ports"].to_string(), r#"{"api": 80, "db": 5432}"#);
+        assert_eq!(dict["public"].to_string(), r#"{"api_url": "http://api"}"#);
+        assert_eq!(
+            dict["flipped"].to_string(),
+            r#"{"p80": "api", "p5432": "db"}"#
+        );
+        assert_eq!(dict["api"], Value::Int(80));
+        assert_eq!(dict["none"].to_string(), "{}");
+
+        let err = eval("{ xs: [1, 1],\n  d: { for x in $.xs: [f\"k${ x }\"]: 1 } }").unwrap_err();
+        assert_eq!(
+            err.message(),
+            r#"duplicate key "k1", given by more than one round of the `for`"#
+        );
+        assert_eq!((err.span().line, err.span().column), (2, 23));
+        // a written key is the same in each round
+        let err = eval("{ xs: [1, 2],\n  d: { for x in $.xs: k: ${ x } } }").unwrap_err();
+        assert_eq!(
+            err.message(),
+            r#"duplicate key "k", given by more than one round of the `for`"#
+        );
+        assert_eq!((err.span().line, err.span().column), (2, 23));
+        let value = eval("{ xs: [1], d: { for x in $.xs: k: ${ x } } }").unwrap();
+        assert_eq!(value.get("d").unwrap().to_string(), r#"{"k": 1}"#);
+        let err = eval(r#"{ a: 1, ["a"]: 2 }"#).unwrap_err();
+        assert_eq!(
+            err.message(),
+            r#"duplicate key "a", first defined at line 1, column 3"#
+        );
+        let err = eval("{ [1]: 2 }").unwrap_err();
+        assert_eq!(err.message(), "a key needs a string, not int");
+        assert_eq!(err.span().column, 3);
+    }
+
+    #[test]
+    fn test_bindings() {
+        let value = eval(
+            r#"let env = "prod"; let port = ${ base + 1 }; let base = 8000;
+            {
+                let env = "inner";
+                name: f"api-${ env }",
+                port: ${ port },
+                db: { let port = 5432; url: f"db:${ port }", pool: { size: ${ port + 1 } } },
+                tag: ${ env },
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            value.to_string(),
+            r#"{"name": "api-inner", "port": 8001, "db": {"url": "db:5432", "pool": {"size": 5433}}, "tag": "inner"}"#
+        );
+
+        // a binding can hold a dict, and a dict inside an expression its own bindings
+        let value = eval(
+            r#"let limits = { let max = 10; max: ${ max }, half: ${ max / 2 } };
+            { limits: ${ limits }, ...{ let n = 3; n: ${ n } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            value.to_string(),
+            r#"{"limits": {"max": 10, "half": 5}, "n": 3}"#
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        let value = eval(
+            r#"let domain = "example.com";
+            let svc = fn(name, port) => { url: f"https://${ name }.${ domain }:${ port }", port: ${ port } };
+            let down = fn(n) => [if n > 0: ${ n }, if n > 0: ...down(n - 1)];
+            {
+                let domain = "inner.org";
+                let twice = fn(x) => ${ x * 2 };
+                api: ${ svc("api", twice(40)) },
+                countdown: ${ down(3) },
+                shadow: ${ { let twice = fn(x) => ${ x + 2 }; v: ${ twice(1) } } },
+                upper: ${ upper("host") },
+                loop: [for p in [1, 2]: { let add = fn(x) => ${ x + p }; v: ${ add(10) } }],
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            value.to_string(),
+            r#"{"api": {"url": "https://api.example.com:80", "port": 80}, "countdown": [3, 2, 1], "shadow": {"v": 3}, "upper": "HOST", "loop": [{"v": 11}, {"v": 12}]}"#
+        );
+
+        let err = eval("let f = fn(a, b) => 1; [${ f(1) }]").unwrap_err();
+        assert_eq!(err.message(), "`f` takes 2 arguments, not 1");
+        let err =
+            eval("let f = fn(n) => { a: [if true: ${ f(n + 1) }] };\n[${ f(0) }]").unwrap_err();
+        assert_eq!(err.message(), "`f` calls nest more than 64 deep");
+        assert_eq!(err.span().line, 1);
+        let err = eval("let f = fn() => 1; [${ f }]").unwrap_err();
+        assert_eq!(err.message(), "`f` is a function, call it as `f(...)`");
+        let err = eval("let f = fn(a) => ${ a + b }; [${ f(1) }]").unwrap_err();
+        assert_eq!(err.message(), "unknown variable `b`");
+    }
+
+    #[test]
+    fn test_ternary() {
+        let value = eval(
+            r#"let fact = fn(n) => ${ n <= 1 ? 1 : n * fact(n - 1) };
+            {
+                env: "prod",
+                replicas: ${ $.env == "prod" ? 3 : 1 },
+                tier: ${ $.replicas > 2 ? "large" : $.replicas > 1 ? "medium" : "small" },
+                lazy: ${ true ? "ok" : $.missing },
+                label: f"${ $.replicas > 1 ? $.replicas : 0 :>3 }",
+                fact: ${ fact(5) },
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            value.to_string(),
+            r#"{"env": "prod", "replicas": 3, "tier": "large", "lazy": "ok", "label": "  3", "fact": 120}"#
+        );
+
+        let err = eval("{ a: ${ 1 ? 2 : 3 } }").unwrap_err();
+        assert_eq!(err.message(), "'?' needs a bool, not int");
+    }
+
+    #[test]
+    fn test_pipe() {
+        let value = eval(
+            r#"let wrap = fn(s, left, right) => f"${ left }${ s }${ right }";
+            {
+                name: "api",
+                host: ${ $.name | upper },
+                tag: ${ $.name | wrap("[", "]") | upper },
+                year: ${ date("2024-05-01") | format("%Y") },
+                size: ${ len($.name) == 3 ? "short" : "long" | upper },
+                copyright: f"@${ $.year | upper }",
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            value.to_string(),
+            r#"{"name": "api", "host": "API", "tag": "[API]", "year": "2024", "size": "SHORT", "copyright": "@2024"}"#
+        );
+
+        let err = eval("{ a: ${ 1 | upper } }").unwrap_err();
+        assert_eq!(err.message(), "upper() does not take (int)");
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = eval("{a: ${ $.b }, b: ${ $.a }}").unwrap_err();
+        assert_eq!(err.message(), "reference cycle: $.a -> $.b -> $.a");
+        let err = eval("{a: 1,\n b: ${ $.c.d }}").unwrap_err();
+        assert_eq!(err.message(), "$.c does not exist");
+        assert_eq!((err.span().line, err.span().column), (2, 5));
+        let err = eval("{a: ${ 1s + 1 }}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cannot apply '+' to duration and int at line 1, column 5"
+        );
+        let err = eval("{a: ${ x }}").unwrap_err();
+        assert_eq!(err.message(), "unknown variable `x`");
+        // bindings are not entries, nor visible outside their dict
+        let err = eval("{d: { let x = 1; }, a: ${ $.d.x }}").unwrap_err();
+        assert_eq!(err.message(), "$.d.x does not exist");
+        let err = eval("{d: { let x = 1; }, a: ${ x }}").unwrap_err();
+        assert_eq!(err.message(), "unknown variable `x`");
+        let err =
+            eval("let a = ${ b }; { let b = ${ c }; let c = [${ a }]; x: ${ b } }").unwrap_err();
+        assert_eq!(err.message(), "unknown variable `b`");
+        let err = eval("{ let a = ${ b }; let b = { v: ${ a } }; x: ${ a } }").unwrap_err();
+        assert_eq!(err.message(), "reference cycle: a -> b -> b.v -> a");
+        let err = eval("let a = ${ a + 1 }; [${ a }]").unwrap_err();
+        assert_eq!(err.message(), "reference cycle: a -> a");
+    }
+}
@@ -0,0 +1,162 @@
+//! The built-in functions callable from expressions, e.g. `${ upper(name) }`.
+
+use std::cmp::Ordering;
+use std::fmt::Write;
+
+use chrono::Utc;
+use parser::Temporal;
+
+use crate::ops::compare;
+use crate::value::Value;
+
+/// Call the built-in `name` with already evaluated arguments.
+pub(crate) fn call(name: &str, args: Vec<Value>) -> Result<Value, String> {
+    match (name, args.as_slice()) {
+        ("len", [Value::Str(s)]) => Ok(Value::Int(s.chars().count() as i64)),
+        ("len", [Value::Bytes(b)]) => Ok(Value::Int(b.len() as i64)),
+        ("len", [Value::List(items)]) => Ok(Value::Int(items.len() as i64)),
+        ("len", [Value::Dict(entries)]) => Ok(Value::Int(entries.len() as i64)),
+        ("upper", [Value::Str(s)]) => Ok(Value::Str(s.to_uppercase())),
+        ("lower", [Value::Str(s)]) => Ok(Value::Str(s.to_lowercase())),
+        ("str", [value]) => Ok(Value::Str(value.to_string())),
+        ("seconds", [Value::Duration(d)]) => Ok(Value::Float(d.as_secs_f64())),
+        ("bytes", [Value::ByteSize(s)]) => Ok(Value::from(parser::BigInt::from(s.0))),
+        ("now", []) => Ok(Value::Temporal(Temporal::Zoned(Utc::now().fixed_offset()))),
+        ("date", []) => Ok(Value::Temporal(Temporal::Date(Utc::now().date_naive()))),
+        ("date", [Value::Temporal(Temporal::DateTime(dt))]) => {
+            Ok(Value::Temporal(Temporal::Date(dt.date())))
+        }
+        ("date", [Value::Temporal(Temporal::Zoned(dt))]) => {
+            Ok(Value::Temporal(Temporal::Date(dt.date_naive())))
+        }
+        ("date", [Value::Str(s)]) => s
+            .parse()
+            .map(Value::Temporal)
+            .map_err(|_| format!("{:?} is not an ISO 8601 date, time or date-time", s)),
+        ("format", [Value::Temporal(t), Value::Str(pattern)]) => {
+            format_temporal(t, pattern).map(Value::Str)
+        }
+        ("min", [_, ..]) => extreme(args, Ordering::Less),
+        ("max", [_, ..]) => extreme(args, Ordering::Greater),
+        (
+            "len" | "upper" | "lower" | "str" | "seconds" | "bytes" | "now" | "date" | "format"
+            | "min" | "max",
+            _,
+        ) => {
+            let types: Vec<_> = args.iter().map(Value::type_name).collect();
+            Err(format!("{}() does not take ({})", name, types.join(", ")))
+        }
+        _ => Err(format!("unknown function {}()", name)),
+    }
+}
+
+/// `t` written with a strftime `pattern` such as `%Y-%m-%d`. Patterns
+/// without a `%` use the tokens `YYYY`, `YY`, `MM`, `DD`, `HH`, `mm` and `ss`
+/// instead, so `format(now(), "YYYY")` is the current year.
+pub(crate) fn format_temporal(t: &Temporal, pattern: &str) -> Result<String, String> {
+    let pattern = if pattern.contains('%') {
+        pattern.to_string()
+    } else {
+        strftime(pattern)
+    };
+    let mut out = String::new();
+    let written = match t {
+        Temporal::Date(date) => write!(out, "{}", date.format(&pattern)),
+        Temporal::Time(time) => write!(out, "{}", time.format(&pattern)),
+        Temporal::DateTime(dt) => write!(out, "{}", dt.format(&pattern)),
+        Temporal::Zoned(dt) => write!(out, "{}", dt.format(&pattern)),
+    };
+    written.map(|_| out).map_err(|_| {
+        format!(
+            "cannot format a {} with {:?}",
+            Value::Temporal(*t).type_name(),
+            pattern
+        )
+    })
+}
+
+/// Translate `YYYY-MM-DD` style tokens to strftime.
+fn strftime(pattern: &str) -> String {
+    const TOKENS: [(&str, &str); 7] = [
+        ("YYYY", "%Y"),
+        ("YY", "%y"),
+        ("MM", "%m"),
+        ("DD", "%d"),
+        ("HH", "%H"),
+        ("mm", "%M"),
+        ("ss", "%S"),
+    ];
+    let mut out = String::new();
+    let mut rest = pattern;
+    'outer: while let Some(c) = rest.chars().next() {
+        for (token, spec) in TOKENS {
+            if let Some(after) = rest.strip_prefix(token) {
+                out.push_str(spec);
+                rest = after;
+                continue 'outer;
+            }
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// The smallest (`Less`) or largest (`Greater`) of `args`.
+fn extreme(args: Vec<Value>, wanted: Ordering) -> Result<Value, String> {
+    let mut args = args.into_iter();
+    let mut best = args.next().unwrap_or(Value::Null);
+    for arg in args {
+        match compare(&arg, &best) {
+            Some(ordering) if ordering == wanted => best = arg,
+            Some(_) => {}
+            None => {
+                return Err(format!(
+                    "cannot compare {} and {}",
+                    arg.type_name(),
+                    best.type_name()
+                ))
+            }
+        }
+    }
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_call() {
+        assert_eq!(call("len", vec!["héllo".into()]), Ok(Value::Int(5)));
+        assert_eq!(call("upper", vec!["a".into()]), Ok("A".into()));
+        assert_eq!(call("str", vec![Value::Int(1)]), Ok("1".into()));
+        let (short, long) = (Duration::from_secs(1), Duration::from_secs(60));
+        assert_eq!(
+            call("max", vec![short.into(), long.into()]),
+            Ok(Value::Duration(long))
+        );
+        let noon: Temporal = "2024-05-01T12:00:00Z".parse().unwrap();
+        assert_eq!(
+            call("format", vec![noon.into(), "YYYY/MM/DD HH:mm".into()]),
+            Ok("2024/05/01 12:00".into())
+        );
+        assert_eq!(
+            call("format", vec![noon.into(), "%d %B %Y".into()]),
+            Ok("01 May 2024".into())
+        );
+        let date: Temporal = "2024-05-01".parse().unwrap();
+        assert_eq!(call("date", vec![noon.into()]), Ok(date.into()));
+        assert!(call("format", vec![date.into(), "%H".into()]).is_err());
+        assert_eq!(
+            call("upper", vec![Value::Int(1)]),
+            Err("upper() does not take (int)".to_string())
+        );
+        assert_eq!(
+            call("nope", vec![]),
+            Err("unknown function nope()".to_string())
+        );
+    }
+}
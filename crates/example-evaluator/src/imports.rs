@@ -0,0 +1,217 @@
+//! `import("./base.eson")`: the value of another file, read, parsed and
+//! evaluated on its own.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use parser::ParseOptions;
+
+use crate::value::Value;
+
+/// Where the evaluated document comes from and where its imports are found,
+/// see [`evaluate_with`](crate::evaluate_with).
+#[derive(Debug, Clone, Default)]
+pub struct EvalOptions {
+    /// The file the document was read from. Imports are looked up next to
+    /// it, or in the working directory without one.
+    pub file: Option<PathBuf>,
+    /// Directories to look in, in order, for an import that is not next to
+    /// the importing file. Paths starting with `./` or `../` are only looked
+    /// up next to it.
+    pub search_paths: Vec<PathBuf>,
+    /// How imported files are parsed.
+    pub parse: ParseOptions,
+}
+
+/// The imports of one evaluation, shared by the documents it evaluates.
+pub(crate) struct Imports {
+    pub(crate) search_paths: Vec<PathBuf>,
+    pub(crate) parse: ParseOptions,
+    /// Files already evaluated, by canonical path.
+    pub(crate) done: HashMap<PathBuf, Value>,
+    /// The files being evaluated, outermost first, by canonical path and
+    /// with their path as written.
+    pub(crate) active: Vec<(PathBuf, String)>,
+}
+
+impl Imports {
+    pub(crate) fn new(options: &EvalOptions) -> Self {
+        Imports {
+            search_paths: options.search_paths.clone(),
+            parse: options.parse.clone(),
+            done: HashMap::new(),
+            active: vec![],
+        }
+    }
+
+    /// The file `import(path)` in a file in `dir` refers to, if it exists.
+    pub(crate) fn resolve(&self, dir: &Path, path: &str) -> Option<PathBuf> {
+        let explicit =
+            path.starts_with("./") || path.starts_with("../") || Path::new(path).is_absolute();
+        let search = if explicit {
+            &[][..]
+        } else {
+            &self.search_paths[..]
+        };
+        std::iter::once(dir.join(path))
+            .chain(search.iter().map(|dir| dir.join(path)))
+            .find(|candidate| candidate.is_file())
+    }
+
+    /// `import cycle: a.eson -> ./b.eson -> ./a.eson` if the file at
+    /// `canonical` is being evaluated already.
+    pub(crate) fn cycle(&self, canonical: &Path, path: &str) -> Option<String> {
+        let start = self.active.iter().position(|(p, _)| p == canonical)?;
+        let chain: Vec<_> = self.active[start..]
+            .iter()
+            .map(|(_, written)| written.as_str())
+            .chain([path])
+            .collect();
+        Some(format!("import cycle: {}", chain.join(" -> ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{evaluate_with, Value};
+
+    use super::*;
+
+    /// A temporary directory, removed again when dropped.
+    struct TempDir(PathBuf);
+
+    impl std::ops::Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A fresh directory holding `files`.
+    fn files(name: &str, files: &[(&str, &str)]) -> TempDir {
+        let dir = std::env::temp_dir().join(format!("eson-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, source) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        TempDir(dir)
+    }
+
+    fn eval_file(path: PathBuf, search_paths: Vec<PathBuf>) -> Result<Value, crate::EvalError> {
+        let source = fs::read_to_string(&path).unwrap();
+        let document = parser::root(&source).unwrap();
+        let options = EvalOptions {
+            file: Some(path),
+            search_paths,
+            ..EvalOptions::default()
+        };
+        evaluate_with(&document, &options)
+    }
+
+    #[test]
+    fn test_import() {
+        let dir = files(
+            "import",
+            &[
+                (
+                    "main.eson",
+                    r#"{ ...import("./base.eson"), port: 8080, shared: ${ import("shared.eson") } }"#,
+                ),
+                (
+                    "base.eson",
+                    r#"{ host: "localhost", port: 80, tls: ${ import("lib/tls.eson") } }"#,
+                ),
+                ("lib/tls.eson", r#"[${ import("../ports.eson") }]"#),
+                ("ports.eson", "[443]"),
+                ("lib/shared.eson", r#"{ name: "lib" }"#),
+            ],
+        );
+        let value = eval_file(dir.join("main.eson"), vec![dir.join("lib")]).unwrap();
+        assert_eq!(
+            value.to_string(),
+            r#"{"host": "localhost", "port": 8080, "tls": [[443]], "shared": {"name": "lib"}}"#
+        );
+
+        // one file under two names is evaluated once
+        let dir = files(
+            "cache",
+            &[
+                (
+                    "main.eson",
+                    r#"[${ import("now.eson") }, ${ import("lib/../now.eson") }]"#,
+                ),
+                ("now.eson", "[${ now() }]"),
+                ("lib/.keep", ""),
+            ],
+        );
+        let Value::List(items) = eval_file(dir.join("main.eson"), vec![]).unwrap() else {
+            panic!("expected a list");
+        };
+        assert_eq!(items[0], items[1]);
+
+        // `./` imports are not looked up in the search paths
+        let dir = files(
+            "explicit",
+            &[("main.eson", r#"[${ import("./shared.eson") }]"#)],
+        );
+        let err = eval_file(dir.join("main.eson"), vec![dir.join("lib")]).unwrap_err();
+        assert_eq!(err.message(), r#"cannot find "./shared.eson""#);
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let dir = files(
+            "cycle",
+            &[
+                ("a.eson", r#"{ b: ${ import("./b.eson") } }"#),
+                ("b.eson", "{\n  a: ${ import(\"./a.eson\") } }"),
+                ("c.eson", r#"{ x: ${ $.y } }"#),
+                ("d.eson", r#"{ c: ${ import("./c.eson") } }"#),
+                ("e.eson", "[\n  ${ import(\"./d.eson\") }]"),
+                ("f.eson", r#"[${ import("./bad.eson") }]"#),
+                ("bad.eson", "{ a: }"),
+            ],
+        );
+        let path = |name| fs::canonicalize(dir.join(name)).unwrap();
+        // the cycle is found at the import in b.eson
+        let err = eval_file(dir.join("a.eson"), vec![]).unwrap_err();
+        let cycle = format!(
+            "import cycle: {} -> ./b.eson -> ./a.eson",
+            dir.join("a.eson").display()
+        );
+        assert_eq!(err.message(), cycle);
+        assert_eq!((err.span().line, err.span().column), (2, 6));
+        assert_eq!(err.file(), Some(path("b.eson").as_path()));
+        assert_eq!(err.imported_from().len(), 1);
+        assert_eq!(err.imported_from()[0].0, Some(path("a.eson")));
+        assert_eq!(err.imported_from()[0].1.column, 6);
+
+        let err = eval_file(dir.join("e.eson"), vec![]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "$.y does not exist at line 1, column 6 in {}\n  imported from {} at line 1, column 6\n  imported from {} at line 2, column 3",
+                path("c.eson").display(),
+                path("d.eson").display(),
+                path("e.eson").display()
+            )
+        );
+        let err = eval_file(dir.join("f.eson"), vec![]).unwrap_err();
+        assert_eq!(err.message(), "expected a value, found '}'");
+        assert_eq!(err.span().column, 6);
+        assert_eq!(err.file(), Some(path("bad.eson").as_path()));
+        let err = crate::evaluate(&parser::root(r#"[${ import(1) }]"#).unwrap()).unwrap_err();
+        assert_eq!(err.message(), "import() does not take (int)");
+    }
+}
@@ -0,0 +1,27 @@
+//! An evaluator for parsed eson documents: it computes every `${ ... }`
+//! expression, resolves references and writes the result out as JSON.
+//!
+//! ```
+//! use example_evaluator::{evaluate, to_json, OutputOptions};
+//!
+//! let doc = parser::root(r#"{ port: 8080, next: ${ $.port + 1 } }"#).unwrap();
+//! let value = evaluate(&doc).unwrap();
+//! assert_eq!(to_json(&value, &OutputOptions::default()), r#"{"port":8080,"next":8081}"#);
+//! ```
+
+pub use cbor::to_cbor;
+pub use evaluator::{evaluate, evaluate_with, EvalError};
+pub use imports::EvalOptions;
+pub use msgpack::to_msgpack;
+pub use output::{to_json, ByteSizeFormat, DateTimeFormat, DurationFormat, OutputOptions};
+pub use value::Value;
+
+mod cbor;
+mod evaluator;
+mod functions;
+mod imports;
+mod msgpack;
+mod ops;
+mod output;
+mod render;
+mod value;
@@ -1,5 +1,98 @@
-mod evaluator;
+use std::io::Write;
+use std::process::ExitCode;
 
-fn main() {
-    println!("Hello, world!");
+use example_evaluator::{evaluate_with, to_cbor, to_json, to_msgpack, EvalOptions, OutputOptions};
+
+const USAGE: &str =
+    "usage: example-evaluator [--format json|cbor|msgpack] [-I <dir>]... <file.eson>";
+
+/// `example-evaluator [--format json|cbor|msgpack] [-I <dir>]... <file.eson>`:
+/// evaluate the document and print it as pretty JSON, or write it to stdout
+/// as CBOR or MessagePack. Imports not found next to the importing file are
+/// looked up in the `-I` directories, in order. Unused `let` bindings are
+/// warned about on stderr.
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let mut format = String::from("json");
+    let mut search_paths = vec![];
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next() {
+                Some(f) if ["json", "cbor", "msgpack"].contains(&f.as_str()) => format = f,
+                _ => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-I" => match args.next() {
+                Some(dir) => search_paths.push(dir.into()),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let source = match std::fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let document = match parser::root(&source) {
+        Ok(document) => document,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    for key in parser::unused_bindings(&document) {
+        eprintln!(
+            "{}: warning: unused binding `{}` at line {}, column {}",
+            path, key.name, key.span.line, key.span.column
+        );
+    }
+    let options = EvalOptions {
+        file: Some(path.clone().into()),
+        search_paths,
+        ..EvalOptions::default()
+    };
+    match evaluate_with(&document, &options) {
+        Ok(value) => {
+            let options = OutputOptions {
+                pretty: true,
+                ..OutputOptions::default()
+            };
+            let bytes = match format.as_str() {
+                "cbor" => to_cbor(&value, &options),
+                "msgpack" => to_msgpack(&value, &options),
+                _ => {
+                    println!("{}", to_json(&value, &options));
+                    return ExitCode::SUCCESS;
+                }
+            };
+            match std::io::stdout().write_all(&bytes) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,183 @@
+//! Writing evaluated values out as MessagePack.
+
+use crate::output::{convert, Converted, OutputOptions};
+use crate::value::Value;
+
+/// `value` as a MessagePack object.
+///
+/// Bytes are written as `bin`. MessagePack has no integers beyond 64 bits
+/// and no decimals, so those are written as strings with all their digits.
+/// Durations, byte sizes and date-times follow `options` like
+/// [`to_json`](crate::to_json), `pretty` has no effect.
+pub fn to_msgpack(value: &Value, options: &OutputOptions) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_msgpack(&mut out, value, options);
+    out
+}
+
+fn write_msgpack(out: &mut Vec<u8>, value: &Value, options: &OutputOptions) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(b) => out.push(if *b { 0xc3 } else { 0xc2 }),
+        Value::Int(i) => write_int(out, (*i).into()),
+        Value::BigInt(i) => match i128::try_from(i) {
+            Ok(i) if u64::try_from(i).is_ok() => write_int(out, i),
+            _ => write_str(out, &i.to_string()),
+        },
+        Value::Float(x) => {
+            out.push(0xcb);
+            out.extend_from_slice(&x.to_be_bytes());
+        }
+        Value::Decimal(d) => write_str(out, &d.to_string()),
+        Value::Str(s) => write_str(out, s),
+        Value::Bytes(b) => {
+            write_len(out, b.len(), None, [Some(0xc4), Some(0xc5), Some(0xc6)]);
+            out.extend_from_slice(b);
+        }
+        Value::Duration(_) | Value::ByteSize(_) | Value::Temporal(_) => {
+            match convert(value, options) {
+                Converted::Int(i) => write_int(out, i),
+                Converted::Float(x) => write_msgpack(out, &Value::Float(x), options),
+                Converted::Str(s) => write_str(out, &s),
+            }
+        }
+        Value::List(items) => {
+            write_len(
+                out,
+                items.len(),
+                Some((0x90, 16)),
+                [None, Some(0xdc), Some(0xdd)],
+            );
+            for item in items {
+                write_msgpack(out, item, options);
+            }
+        }
+        Value::Dict(entries) => {
+            write_len(
+                out,
+                entries.len(),
+                Some((0x80, 16)),
+                [None, Some(0xde), Some(0xdf)],
+            );
+            for (key, value) in entries {
+                write_str(out, key);
+                write_msgpack(out, value, options);
+            }
+        }
+    }
+}
+
+/// An integer in `i64::MIN..=u64::MAX`, in the smallest format that holds
+/// it.
+fn write_int(out: &mut Vec<u8>, i: i128) {
+    if let Ok(i) = i8::try_from(i) {
+        if i >= -32 {
+            // positive and negative fixint
+            return out.push(i as u8);
+        }
+    }
+    if i >= 0 {
+        if let Ok(i) = u8::try_from(i) {
+            out.extend_from_slice(&[0xcc, i]);
+        } else if let Ok(i) = u16::try_from(i) {
+            out.push(0xcd);
+            out.extend_from_slice(&i.to_be_bytes());
+        } else if let Ok(i) = u32::try_from(i) {
+            out.push(0xce);
+            out.extend_from_slice(&i.to_be_bytes());
+        } else {
+            out.push(0xcf);
+            out.extend_from_slice(&(i as u64).to_be_bytes());
+        }
+    } else if let Ok(i) = i8::try_from(i) {
+        out.extend_from_slice(&[0xd0, i as u8]);
+    } else if let Ok(i) = i16::try_from(i) {
+        out.push(0xd1);
+        out.extend_from_slice(&i.to_be_bytes());
+    } else if let Ok(i) = i32::try_from(i) {
+        out.push(0xd2);
+        out.extend_from_slice(&i.to_be_bytes());
+    } else {
+        out.push(0xd3);
+        out.extend_from_slice(&(i as i64).to_be_bytes());
+    }
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_len(
+        out,
+        s.len(),
+        Some((0xa0, 32)),
+        [Some(0xd9), Some(0xda), Some(0xdb)],
+    );
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// The type byte and length of a string, bin, array or map: the `fix` form
+/// (marker and exclusive limit) when there is one and `len` fits, else the
+/// smallest of the `sized` 8, 16 and 32 bit forms. Arrays and maps have no
+/// 8 bit form.
+fn write_len(out: &mut Vec<u8>, len: usize, fix: Option<(u8, usize)>, sized: [Option<u8>; 3]) {
+    let [bits8, bits16, bits32] = sized;
+    match (fix, bits8, u8::try_from(len), u16::try_from(len)) {
+        (Some((marker, limit)), ..) if len < limit => out.push(marker | len as u8),
+        (_, Some(marker), Ok(len), _) => out.extend_from_slice(&[marker, len]),
+        (.., Ok(len)) => {
+            out.extend(bits16);
+            out.extend_from_slice(&len.to_be_bytes());
+        }
+        _ => {
+            out.extend(bits32);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+    use parser::ByteSize;
+
+    use super::*;
+
+    fn msgpack(value: Value) -> Vec<u8> {
+        to_msgpack(&value, &OutputOptions::default())
+    }
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!(msgpack(Value::Null), [0xc0]);
+        assert_eq!(msgpack(Value::Bool(false)), [0xc2]);
+        assert_eq!(msgpack(Value::Int(127)), [0x7f]);
+        assert_eq!(msgpack(Value::Int(-32)), [0xe0]);
+        assert_eq!(msgpack(Value::Int(200)), [0xcc, 200]);
+        assert_eq!(msgpack(Value::Int(-33)), [0xd0, 0xdf]);
+        assert_eq!(msgpack(Value::Int(-1000)), [0xd1, 0xfc, 0x18]);
+        assert_eq!(
+            msgpack(Value::BigInt("18446744073709551615".parse().unwrap())),
+            [0xcf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(
+            msgpack(Value::Decimal("0.1".parse().unwrap())),
+            [0xa3, b'0', b'.', b'1']
+        );
+        assert_eq!(msgpack(Value::Bytes(vec![0, 255])), [0xc4, 2, 0, 255]);
+        assert_eq!(
+            msgpack(Value::ByteSize(ByteSize(1 << 16))),
+            [0xce, 0, 1, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_containers() {
+        let value = Value::Dict(IndexMap::from([(
+            "a".to_string(),
+            Value::List(vec![Value::Int(1), Value::from("x".repeat(40))]),
+        )]));
+        let mut expected = vec![0x81, 0xa1, b'a', 0x92, 0x01, 0xd9, 40];
+        expected.extend_from_slice("x".repeat(40).as_bytes());
+        assert_eq!(msgpack(value), expected);
+        let long = Value::List(vec![Value::Null; 20]);
+        assert_eq!(msgpack(long)[..3], [0xdc, 0, 20]);
+    }
+}
@@ -0,0 +1,482 @@
+//! The operators of `${ ... }` expressions.
+//!
+//! Numbers mix freely and widen as needed: `int` to big int on overflow,
+//! anything with a `decimal` to decimal, anything with a `float` to float.
+//! Durations and byte sizes add and subtract among themselves, scale by
+//! numbers and compare with each other. Dates and times move by durations,
+//! and subtracting two of the same kind gives the duration between them.
+
+use std::cmp::Ordering;
+use std::time::Duration;
+
+use bigdecimal::{ToPrimitive, Zero};
+use chrono::TimeDelta;
+use parser::{BigDecimal, BigInt, ByteSize, ExprToken, Temporal};
+
+use crate::value::Value;
+
+/// `lhs op rhs` for the arithmetic and comparison operators, `&&` and `||`
+/// short-circuit and are handled by the evaluator.
+pub(crate) fn binary(op: &ExprToken, lhs: Value, rhs: Value) -> Result<Value, String> {
+    match op {
+        ExprToken::Eq => return Ok(Value::Bool(equal(&lhs, &rhs))),
+        ExprToken::Ne => return Ok(Value::Bool(!equal(&lhs, &rhs))),
+        ExprToken::Lt | ExprToken::Gt | ExprToken::Le | ExprToken::Ge => {
+            let ordering = compare(&lhs, &rhs).ok_or_else(|| mismatch(op, &lhs, &rhs))?;
+            return Ok(Value::Bool(match op {
+                ExprToken::Lt => ordering.is_lt(),
+                ExprToken::Gt => ordering.is_gt(),
+                ExprToken::Le => ordering.is_le(),
+                _ => ordering.is_ge(),
+            }));
+        }
+        _ => {}
+    }
+    if let Some(numbers) = numbers(&lhs, &rhs) {
+        return arithmetic(op, numbers);
+    }
+    match (op, lhs, rhs) {
+        (ExprToken::Plus, Value::Str(a), Value::Str(b)) => Ok(Value::Str(a + &b)),
+        (ExprToken::Plus, Value::Bytes(mut a), Value::Bytes(b)) => {
+            a.extend(b);
+            Ok(Value::Bytes(a))
+        }
+        (ExprToken::Plus, Value::List(mut a), Value::List(b)) => {
+            a.extend(b);
+            Ok(Value::List(a))
+        }
+        (_, Value::Duration(a), Value::Duration(b)) => {
+            let nanos = quantity(op, a.as_nanos(), b.as_nanos(), "duration")?;
+            duration(nanos)
+        }
+        (_, Value::ByteSize(a), Value::ByteSize(b)) => {
+            let bytes = quantity(op, a.0 as u128, b.0 as u128, "byte size")?;
+            byte_size(bytes)
+        }
+        (ExprToken::Mul | ExprToken::Div, Value::Duration(d), n)
+        | (ExprToken::Mul, n, Value::Duration(d))
+            if is_number(&n) =>
+        {
+            duration(scale(op, d.as_nanos(), &n)?)
+        }
+        (ExprToken::Mul | ExprToken::Div, Value::ByteSize(s), n)
+        | (ExprToken::Mul, n, Value::ByteSize(s))
+            if is_number(&n) =>
+        {
+            byte_size(scale(op, s.0 as u128, &n)?)
+        }
+        (ExprToken::Plus | ExprToken::Minus, Value::Temporal(t), Value::Duration(d))
+        | (ExprToken::Plus, Value::Duration(d), Value::Temporal(t)) => {
+            shift(t, op, d).map(Value::Temporal)
+        }
+        (ExprToken::Minus, Value::Temporal(a), Value::Temporal(b))
+            if compare_temporal(&a, &b).is_some() =>
+        {
+            since(a, b).map(Value::Duration)
+        }
+        (op, lhs, rhs) => Err(mismatch(op, &lhs, &rhs)),
+    }
+}
+
+/// `op value` for `!`, `-` and `+`.
+pub(crate) fn unary(op: &ExprToken, value: Value) -> Result<Value, String> {
+    match (op, value) {
+        (ExprToken::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+        (ExprToken::Minus, Value::Int(i)) => Ok(i
+            .checked_neg()
+            .map_or_else(|| Value::BigInt(-BigInt::from(i)), Value::Int)),
+        (ExprToken::Minus, Value::BigInt(i)) => Ok(Value::from(-i)),
+        (ExprToken::Minus, Value::Float(x)) => Ok(Value::Float(-x)),
+        (ExprToken::Minus, Value::Decimal(d)) => Ok(Value::Decimal(-d)),
+        (ExprToken::Plus, value) if is_number(&value) => Ok(value),
+        (op, value) => Err(format!(
+            "cannot apply '{}' to {}",
+            symbol(op),
+            value.type_name()
+        )),
+    }
+}
+
+/// Equality as `==` sees it: numbers compare by value across types, lists
+/// and dicts element by element.
+pub(crate) fn equal(lhs: &Value, rhs: &Value) -> bool {
+    match (lhs, rhs) {
+        (Value::List(a), Value::List(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equal(a, b))
+        }
+        (Value::Dict(a), Value::Dict(b)) => {
+            a.len() == b.len() && a.iter().all(|(k, v)| b.get(k).is_some_and(|w| equal(v, w)))
+        }
+        _ if numbers(lhs, rhs).is_some() => compare(lhs, rhs) == Some(Ordering::Equal),
+        _ => lhs == rhs,
+    }
+}
+
+/// The order of two values of comparable types.
+pub(crate) fn compare(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    if let Some(numbers) = numbers(lhs, rhs) {
+        return match numbers {
+            Numbers::Int(a, b) => Some(a.cmp(&b)),
+            Numbers::Big(a, b) => Some(a.cmp(&b)),
+            Numbers::Decimal(a, b) => Some(a.cmp(&b)),
+            Numbers::Float(a, b) => a.partial_cmp(&b),
+        };
+    }
+    match (lhs, rhs) {
+        (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+        (Value::Duration(a), Value::Duration(b)) => Some(a.cmp(b)),
+        (Value::ByteSize(a), Value::ByteSize(b)) => Some(a.cmp(b)),
+        (Value::Temporal(a), Value::Temporal(b)) => compare_temporal(a, b),
+        _ => None,
+    }
+}
+
+/// Dates, times and date-times only compare with their own kind, instants
+/// (with an offset) by the moment they denote.
+fn compare_temporal(lhs: &Temporal, rhs: &Temporal) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Temporal::Date(a), Temporal::Date(b)) => Some(a.cmp(b)),
+        (Temporal::Time(a), Temporal::Time(b)) => Some(a.cmp(b)),
+        (Temporal::DateTime(a), Temporal::DateTime(b)) => Some(a.cmp(b)),
+        (Temporal::Zoned(a), Temporal::Zoned(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// Two numbers converted to their common type.
+enum Numbers {
+    Int(i64, i64),
+    Big(BigInt, BigInt),
+    Decimal(BigDecimal, BigDecimal),
+    Float(f64, f64),
+}
+
+fn is_number(value: &Value) -> bool {
+    rank(value).is_some()
+}
+
+fn rank(value: &Value) -> Option<u8> {
+    match value {
+        Value::Int(_) => Some(0),
+        Value::BigInt(_) => Some(1),
+        Value::Decimal(_) => Some(2),
+        Value::Float(_) => Some(3),
+        _ => None,
+    }
+}
+
+fn numbers(lhs: &Value, rhs: &Value) -> Option<Numbers> {
+    Some(match rank(lhs)?.max(rank(rhs)?) {
+        0 => Numbers::Int(lhs.as_i64()?, rhs.as_i64()?),
+        1 => Numbers::Big(to_big(lhs)?, to_big(rhs)?),
+        2 => Numbers::Decimal(to_decimal(lhs)?, to_decimal(rhs)?),
+        _ => Numbers::Float(to_f64(lhs)?, to_f64(rhs)?),
+    })
+}
+
+pub(crate) fn to_big(value: &Value) -> Option<BigInt> {
+    match value {
+        Value::Int(i) => Some(BigInt::from(*i)),
+        Value::BigInt(i) => Some(i.clone()),
+        _ => None,
+    }
+}
+
+pub(crate) fn to_decimal(value: &Value) -> Option<BigDecimal> {
+    match value {
+        Value::Decimal(d) => Some(d.clone()),
+        other => to_big(other).map(BigDecimal::from),
+    }
+}
+
+pub(crate) fn to_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Int(i) => Some(*i as f64),
+        Value::BigInt(i) => i.to_f64(),
+        Value::Decimal(d) => d.to_f64(),
+        Value::Float(x) => Some(*x),
+        _ => None,
+    }
+}
+
+fn arithmetic(op: &ExprToken, numbers: Numbers) -> Result<Value, String> {
+    let division_by_zero = || "division by zero".to_string();
+    match numbers {
+        Numbers::Int(a, b) => {
+            let exact = match op {
+                ExprToken::Plus => a.checked_add(b),
+                ExprToken::Minus => a.checked_sub(b),
+                ExprToken::Mul => a.checked_mul(b),
+                ExprToken::Div | ExprToken::Mod if b == 0 => return Err(division_by_zero()),
+                // `7 / 2` is 3.5, not 3, and `i64::MIN / -1` is a big int
+                ExprToken::Div if a.checked_rem(b).is_some_and(|r| r != 0) => {
+                    return Ok(Value::Float(a as f64 / b as f64))
+                }
+                ExprToken::Div => a.checked_div(b),
+                ExprToken::Mod => a.checked_rem(b),
+                _ => return Err(format!("cannot apply '{}' to int and int", symbol(op))),
+            };
+            match exact {
+                Some(i) => Ok(Value::Int(i)),
+                None => arithmetic(op, Numbers::Big(BigInt::from(a), BigInt::from(b))),
+            }
+        }
+        Numbers::Big(a, b) => match op {
+            ExprToken::Plus => Ok(Value::from(a + b)),
+            ExprToken::Minus => Ok(Value::from(a - b)),
+            ExprToken::Mul => Ok(Value::from(a * b)),
+            ExprToken::Div | ExprToken::Mod if b.is_zero() => Err(division_by_zero()),
+            ExprToken::Div if !(&a % &b).is_zero() => Ok(Value::Float(
+                a.to_f64().unwrap_or(f64::NAN) / b.to_f64().unwrap_or(f64::NAN),
+            )),
+            ExprToken::Div => Ok(Value::from(a / b)),
+            ExprToken::Mod => Ok(Value::from(a % b)),
+            _ => Err(format!("cannot apply '{}' to int and int", symbol(op))),
+        },
+        Numbers::Decimal(a, b) => match op {
+            ExprToken::Plus => Ok(Value::Decimal(a + b)),
+            ExprToken::Minus => Ok(Value::Decimal(a - b)),
+            ExprToken::Mul => Ok(Value::Decimal(a * b)),
+            ExprToken::Div | ExprToken::Mod if b.is_zero() => Err(division_by_zero()),
+            ExprToken::Div => Ok(Value::Decimal(a / b)),
+            ExprToken::Mod => Ok(Value::Decimal(a % b)),
+            _ => Err(format!("cannot apply '{}' to decimals", symbol(op))),
+        },
+        Numbers::Float(a, b) => match op {
+            ExprToken::Plus => Ok(Value::Float(a + b)),
+            ExprToken::Minus => Ok(Value::Float(a - b)),
+            ExprToken::Mul => Ok(Value::Float(a * b)),
+            ExprToken::Div => Ok(Value::Float(a / b)),
+            ExprToken::Mod => Ok(Value::Float(a % b)),
+            _ => Err(format!("cannot apply '{}' to floats", symbol(op))),
+        },
+    }
+}
+
+/// `a op b` for two amounts of the same unit (nanoseconds or bytes).
+fn quantity(op: &ExprToken, a: u128, b: u128, what: &str) -> Result<u128, String> {
+    match op {
+        ExprToken::Plus => a.checked_add(b).ok_or_else(|| format!("{} overflow", what)),
+        ExprToken::Minus => a
+            .checked_sub(b)
+            .ok_or_else(|| format!("a {} cannot be negative", what)),
+        ExprToken::Mod if b == 0 => Err("division by zero".to_string()),
+        ExprToken::Mod => Ok(a % b),
+        _ => Err(format!("cannot apply '{}' to {}s", symbol(op), what)),
+    }
+}
+
+/// `amount * factor` or `amount / factor`, rounded to a whole amount.
+fn scale(op: &ExprToken, amount: u128, factor: &Value) -> Result<u128, String> {
+    if let Value::Int(i) = factor {
+        let i = u128::try_from(*i).map_err(|_| "cannot scale by a negative number".to_string())?;
+        return match op {
+            ExprToken::Div if i == 0 => Err("division by zero".to_string()),
+            ExprToken::Div => Ok(amount / i),
+            _ => amount.checked_mul(i).ok_or_else(|| "overflow".to_string()),
+        };
+    }
+    let factor = to_f64(factor).unwrap_or(f64::NAN);
+    if factor.is_nan() || factor < 0.0 {
+        return Err("cannot scale by a negative number".to_string());
+    }
+    let scaled = match op {
+        ExprToken::Div if factor == 0.0 => return Err("division by zero".to_string()),
+        ExprToken::Div => amount as f64 / factor,
+        _ => amount as f64 * factor,
+    };
+    if scaled.is_finite() {
+        Ok(scaled.round() as u128)
+    } else {
+        Err("overflow".to_string())
+    }
+}
+
+/// `t + d` or `t - d`, times of day wrap around midnight.
+fn shift(t: Temporal, op: &ExprToken, d: Duration) -> Result<Temporal, String> {
+    let delta = TimeDelta::from_std(d).map_err(|_| "duration overflow")?;
+    let delta = if matches!(op, ExprToken::Minus) {
+        -delta
+    } else {
+        delta
+    };
+    let shifted = match t {
+        Temporal::Date(_) if !d.as_secs().is_multiple_of(86_400) || d.subsec_nanos() != 0 => {
+            return Err("a date can only move by whole days".to_string());
+        }
+        Temporal::Date(date) => date.checked_add_signed(delta).map(Temporal::Date),
+        Temporal::Time(time) => Some(Temporal::Time(time.overflowing_add_signed(delta).0)),
+        Temporal::DateTime(dt) => dt.checked_add_signed(delta).map(Temporal::DateTime),
+        Temporal::Zoned(dt) => dt.checked_add_signed(delta).map(Temporal::Zoned),
+    };
+    shifted.ok_or_else(|| "date out of range".to_string())
+}
+
+/// The duration from `b` to `a`, which must not be earlier.
+fn since(a: Temporal, b: Temporal) -> Result<Duration, String> {
+    let delta = match (a, b) {
+        (Temporal::Date(a), Temporal::Date(b)) => a - b,
+        (Temporal::Time(a), Temporal::Time(b)) => a - b,
+        (Temporal::DateTime(a), Temporal::DateTime(b)) => a - b,
+        (Temporal::Zoned(a), Temporal::Zoned(b)) => a - b,
+        _ => unreachable!("only called for the same kind"),
+    };
+    delta
+        .to_std()
+        .map_err(|_| format!("{} is before {}, a duration cannot be negative", a, b))
+}
+
+fn duration(nanos: u128) -> Result<Value, String> {
+    let secs = u64::try_from(nanos / 1_000_000_000).map_err(|_| "duration overflow")?;
+    Ok(Value::Duration(Duration::new(
+        secs,
+        (nanos % 1_000_000_000) as u32,
+    )))
+}
+
+fn byte_size(bytes: u128) -> Result<Value, String> {
+    let bytes = u64::try_from(bytes).map_err(|_| "byte size overflow")?;
+    Ok(Value::ByteSize(ByteSize(bytes)))
+}
+
+fn mismatch(op: &ExprToken, lhs: &Value, rhs: &Value) -> String {
+    format!(
+        "cannot apply '{}' to {} and {}",
+        symbol(op),
+        lhs.type_name(),
+        rhs.type_name()
+    )
+}
+
+pub(crate) fn symbol(op: &ExprToken) -> &'static str {
+    match op {
+        ExprToken::Eq => "==",
+        ExprToken::Ne => "!=",
+        ExprToken::Le => "<=",
+        ExprToken::Ge => ">=",
+        ExprToken::And => "&&",
+        ExprToken::Or => "||",
+        ExprToken::Not => "!",
+        ExprToken::Gt => ">",
+        ExprToken::Lt => "<",
+        ExprToken::Plus => "+",
+        ExprToken::Minus => "-",
+        ExprToken::Mul => "*",
+        ExprToken::Div => "/",
+        ExprToken::Mod => "%",
+        ExprToken::Pipe => "|",
+        ExprToken::Q => "?",
+        ExprToken::COLON => ":",
+        _ => "?",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Value {
+        Value::Duration(Duration::from_secs(s))
+    }
+
+    #[test]
+    fn test_numbers() {
+        let add = |a, b| binary(&ExprToken::Plus, a, b).unwrap();
+        assert_eq!(add(Value::Int(1), Value::Int(2)), Value::Int(3));
+        assert_eq!(add(Value::Int(1), Value::Float(0.5)), Value::Float(1.5));
+        assert_eq!(
+            add(Value::Int(i64::MAX), Value::Int(1)),
+            Value::BigInt(BigInt::from(i64::MAX) + 1)
+        );
+        let div = |a, b| binary(&ExprToken::Div, a, b);
+        assert_eq!(div(Value::Int(6), Value::Int(3)), Ok(Value::Int(2)));
+        assert_eq!(div(Value::Int(7), Value::Int(2)), Ok(Value::Float(3.5)));
+        assert_eq!(
+            div(Value::Int(7), Value::Int(0)),
+            Err("division by zero".to_string())
+        );
+        assert_eq!(
+            div(Value::Int(i64::MIN), Value::Int(-1)),
+            Ok(Value::BigInt(-BigInt::from(i64::MIN)))
+        );
+        assert_eq!(
+            binary(&ExprToken::Mod, Value::Int(i64::MIN), Value::Int(-1)),
+            Ok(Value::Int(0))
+        );
+        assert!(equal(&Value::Int(1), &Value::Float(1.0)));
+        assert!(!equal(&Value::Int(1), &Value::Str("1".into())));
+    }
+
+    #[test]
+    fn test_units() {
+        let op = |op, a, b| binary(&op, a, b);
+        assert_eq!(op(ExprToken::Plus, secs(30), secs(60)), Ok(secs(90)));
+        assert_eq!(op(ExprToken::Minus, secs(90), secs(60)), Ok(secs(30)));
+        assert!(op(ExprToken::Minus, secs(1), secs(2)).is_err());
+        assert_eq!(op(ExprToken::Mul, secs(30), Value::Int(3)), Ok(secs(90)));
+        assert_eq!(
+            op(ExprToken::Mul, Value::Float(1.5), secs(60)),
+            Ok(secs(90))
+        );
+        assert_eq!(op(ExprToken::Div, secs(90), Value::Int(3)), Ok(secs(30)));
+        assert_eq!(op(ExprToken::Lt, secs(30), secs(60)), Ok(Value::Bool(true)));
+        assert_eq!(
+            op(ExprToken::Plus, secs(1), Value::Int(1)),
+            Err("cannot apply '+' to duration and int".to_string())
+        );
+        let mib = Value::ByteSize(ByteSize(1 << 20));
+        let kib = Value::ByteSize(ByteSize(1 << 10));
+        assert_eq!(
+            op(ExprToken::Plus, mib.clone(), kib.clone()),
+            Ok(Value::ByteSize(ByteSize((1 << 20) + (1 << 10))))
+        );
+        assert_eq!(
+            op(ExprToken::Ge, mib.clone(), kib.clone()),
+            Ok(Value::Bool(true))
+        );
+        assert!(op(ExprToken::Lt, mib, secs(1)).is_err());
+    }
+
+    #[test]
+    fn test_temporal() {
+        let at = |s: &str| Value::Temporal(s.parse().unwrap());
+        let op = |op, a, b| binary(&op, a, b);
+        assert_eq!(
+            op(ExprToken::Plus, at("2024-05-01T23:30:00Z"), secs(3600)),
+            Ok(at("2024-05-02T00:30:00Z"))
+        );
+        assert_eq!(
+            op(
+                ExprToken::Minus,
+                at("2024-05-01T12:00:00Z"),
+                at("2024-05-01T12:00:00+02:00")
+            ),
+            Ok(secs(7200))
+        );
+        assert!(op(ExprToken::Minus, at("2024-05-01"), at("2024-05-02")).is_err());
+        assert_eq!(
+            op(ExprToken::Plus, secs(86_400), at("2024-02-28")),
+            Ok(at("2024-02-29"))
+        );
+        assert_eq!(
+            op(ExprToken::Plus, at("2024-02-28"), secs(3600)),
+            Err("a date can only move by whole days".to_string())
+        );
+        assert_eq!(
+            op(ExprToken::Plus, at("23:00"), secs(7200)),
+            Ok(at("01:00"))
+        );
+        assert_eq!(
+            op(
+                ExprToken::Lt,
+                at("2024-05-01T12:00:00Z"),
+                at("2024-05-01T13:00:00+02:00")
+            ),
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            op(ExprToken::Lt, at("2024-05-01"), at("2024-05-01T00:00:00Z")),
+            Err("cannot apply '<' to date and datetime".to_string())
+        );
+    }
+}
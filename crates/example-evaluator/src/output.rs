@@ -0,0 +1,284 @@
+//! Writing evaluated values out as JSON, and the options shared with the
+//! CBOR and MessagePack writers.
+
+use std::fmt::Write;
+
+use chrono::{NaiveTime, Timelike};
+use parser::{encode_base64, Temporal};
+
+use crate::functions::format_temporal;
+use crate::value::Value;
+
+/// How values without a JSON counterpart are written, see [`to_json`]. CBOR
+/// and MessagePack writers use the same options.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputOptions {
+    pub durations: DurationFormat,
+    pub byte_sizes: ByteSizeFormat,
+    pub date_times: DateTimeFormat,
+    /// Indent nested lists and dicts by two spaces per level.
+    pub pretty: bool,
+}
+
+/// How a duration is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DurationFormat {
+    /// A number of seconds, `90` or `0.25`.
+    #[default]
+    Seconds,
+    /// A number of milliseconds, `90000` or `250`.
+    Milliseconds,
+    /// A string in literal syntax, `"1m30s"` or `"250ms"`.
+    String,
+}
+
+/// How a byte size is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ByteSizeFormat {
+    /// A number of bytes, `10485760`.
+    #[default]
+    Bytes,
+    /// A string in literal syntax, `"10MiB"`.
+    String,
+}
+
+/// How dates, times and date-times are written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DateTimeFormat {
+    /// An ISO 8601 string, `"2024-05-01T12:00:00Z"` or `"2024-05-01"`.
+    #[default]
+    Iso8601,
+    /// Seconds since the Unix epoch. Date-times without an offset count as
+    /// UTC, dates as their midnight and times as seconds since midnight.
+    UnixSeconds,
+    /// A string formatted with a strftime pattern such as `"%d/%m/%Y"`, or
+    /// ISO 8601 for values the pattern does not fit (a time for `"%Y"`).
+    Custom(String),
+}
+
+/// A duration, byte size or date-time as the number or string `options`
+/// ask for.
+pub(crate) enum Converted {
+    Int(i128),
+    Float(f64),
+    Str(String),
+}
+
+pub(crate) fn convert(value: &Value, options: &OutputOptions) -> Converted {
+    match value {
+        Value::Duration(d) => match options.durations {
+            DurationFormat::Seconds if d.subsec_nanos() == 0 => Converted::Int(d.as_secs().into()),
+            DurationFormat::Seconds => Converted::Float(d.as_secs_f64()),
+            DurationFormat::Milliseconds if d.as_nanos() % 1_000_000 == 0 => {
+                Converted::Int(d.as_millis() as i128)
+            }
+            DurationFormat::Milliseconds => Converted::Float(d.as_nanos() as f64 / 1_000_000.0),
+            DurationFormat::String => Converted::Str(value.to_string()),
+        },
+        Value::ByteSize(s) => match options.byte_sizes {
+            ByteSizeFormat::Bytes => Converted::Int(s.0.into()),
+            ByteSizeFormat::String => Converted::Str(s.to_string()),
+        },
+        Value::Temporal(t) => match &options.date_times {
+            DateTimeFormat::Iso8601 => Converted::Str(t.to_string()),
+            DateTimeFormat::UnixSeconds => {
+                let (secs, nanos) = match t {
+                    Temporal::Date(date) => {
+                        (date.and_time(NaiveTime::MIN).and_utc().timestamp(), 0)
+                    }
+                    Temporal::Time(time) => {
+                        (time.num_seconds_from_midnight() as i64, time.nanosecond())
+                    }
+                    Temporal::DateTime(dt) => (dt.and_utc().timestamp(), dt.nanosecond()),
+                    Temporal::Zoned(dt) => (dt.timestamp(), dt.nanosecond()),
+                };
+                if nanos == 0 {
+                    Converted::Int(secs.into())
+                } else {
+                    Converted::Float(secs as f64 + nanos as f64 / 1e9)
+                }
+            }
+            DateTimeFormat::Custom(pattern) => {
+                Converted::Str(format_temporal(t, pattern).unwrap_or_else(|_| t.to_string()))
+            }
+        },
+        _ => unreachable!("only durations, byte sizes and date-times are converted"),
+    }
+}
+
+/// `value` as a JSON text.
+///
+/// Big integers and decimals are written with all their digits, bytes as a
+/// base64 string, `NaN` and the infinities, which JSON cannot express, as
+/// `null`.
+pub fn to_json(value: &Value, options: &OutputOptions) -> String {
+    let mut out = String::new();
+    write_json(&mut out, value, options, 0);
+    out
+}
+
+fn write_json(out: &mut String, value: &Value, options: &OutputOptions, depth: usize) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => write!(out, "{}", b).unwrap(),
+        Value::Int(i) => write!(out, "{}", i).unwrap(),
+        Value::BigInt(i) => write!(out, "{}", i).unwrap(),
+        Value::Float(x) if x.is_finite() => write!(out, "{:?}", x).unwrap(),
+        Value::Float(_) => out.push_str("null"),
+        Value::Decimal(d) => write!(out, "{}", d).unwrap(),
+        Value::Str(s) => write_string(out, s),
+        Value::Duration(_) | Value::ByteSize(_) | Value::Temporal(_) => {
+            match convert(value, options) {
+                Converted::Int(i) => write!(out, "{}", i).unwrap(),
+                Converted::Float(x) => write!(out, "{}", x).unwrap(),
+                Converted::Str(s) => write_string(out, &s),
+            }
+        }
+        Value::Bytes(b) => write_string(out, &encode_base64(b)),
+        Value::List(items) => write_seq(out, '[', ']', items.len(), options, depth, |out, i| {
+            write_json(out, &items[i], options, depth + 1)
+        }),
+        Value::Dict(entries) => {
+            write_seq(out, '{', '}', entries.len(), options, depth, |out, i| {
+                let (key, value) = entries.get_index(i).unwrap();
+                write_string(out, key);
+                out.push_str(if options.pretty { ": " } else { ":" });
+                write_json(out, value, options, depth + 1)
+            })
+        }
+    }
+}
+
+fn write_seq(
+    out: &mut String,
+    open: char,
+    close: char,
+    len: usize,
+    options: &OutputOptions,
+    depth: usize,
+    mut item: impl FnMut(&mut String, usize),
+) {
+    out.push(open);
+    for i in 0..len {
+        if i > 0 {
+            out.push(',');
+        }
+        if options.pretty {
+            out.push('\n');
+            out.push_str(&"  ".repeat(depth + 1));
+        }
+        item(out, i);
+    }
+    if options.pretty && len > 0 {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    }
+    out.push(close);
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use indexmap::IndexMap;
+    use parser::ByteSize;
+
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        let value = Value::Dict(IndexMap::from([
+            ("name".to_string(), Value::from("a \"b\"\n")),
+            (
+                "list".to_string(),
+                Value::List(vec![Value::Int(1), Value::Float(0.5), Value::Null]),
+            ),
+            ("nan".to_string(), Value::Float(f64::NAN)),
+        ]));
+        let options = OutputOptions::default();
+        assert_eq!(
+            to_json(&value, &options),
+            r#"{"name":"a \"b\"\n","list":[1,0.5,null],"nan":null}"#
+        );
+        let pretty = OutputOptions {
+            pretty: true,
+            ..OutputOptions::default()
+        };
+        assert_eq!(
+            to_json(
+                &Value::List(vec![Value::Int(1), Value::List(vec![])]),
+                &pretty
+            ),
+            "[\n  1,\n  []\n]"
+        );
+    }
+
+    #[test]
+    fn test_units() {
+        let timeout = Value::Duration(Duration::from_millis(1500));
+        let size = Value::ByteSize(ByteSize(10 << 20));
+        let options = OutputOptions::default();
+        assert_eq!(to_json(&timeout, &options), "1.5");
+        assert_eq!(
+            to_json(&Value::Duration(Duration::from_secs(30)), &options),
+            "30"
+        );
+        assert_eq!(to_json(&size, &options), "10485760");
+        let options = OutputOptions {
+            durations: DurationFormat::Milliseconds,
+            ..OutputOptions::default()
+        };
+        assert_eq!(to_json(&timeout, &options), "1500");
+        let options = OutputOptions {
+            durations: DurationFormat::String,
+            byte_sizes: ByteSizeFormat::String,
+            ..OutputOptions::default()
+        };
+        assert_eq!(to_json(&timeout, &options), r#""1s500ms""#);
+        assert_eq!(to_json(&size, &options), r#""10MiB""#);
+    }
+
+    #[test]
+    fn test_bytes() {
+        let value = Value::List(vec![Value::Bytes(vec![0, 255]), Value::Bytes(vec![])]);
+        assert_eq!(to_json(&value, &OutputOptions::default()), r#"["AP8=",""]"#);
+    }
+
+    #[test]
+    fn test_temporal() {
+        let at = |s: &str| Value::Temporal(s.parse().unwrap());
+        let noon = at("2024-05-01T12:00:00+02:00");
+        let options = OutputOptions::default();
+        assert_eq!(to_json(&noon, &options), r#""2024-05-01T12:00:00+02:00""#);
+        assert_eq!(to_json(&at("2024-05-01"), &options), r#""2024-05-01""#);
+        let options = OutputOptions {
+            date_times: DateTimeFormat::UnixSeconds,
+            ..OutputOptions::default()
+        };
+        assert_eq!(to_json(&noon, &options), "1714557600");
+        assert_eq!(to_json(&at("2024-05-01"), &options), "1714521600");
+        assert_eq!(to_json(&at("01:00:00.5"), &options), "3600.5");
+        let options = OutputOptions {
+            date_times: DateTimeFormat::Custom("%d/%m/%Y".to_string()),
+            ..OutputOptions::default()
+        };
+        assert_eq!(to_json(&noon, &options), r#""01/05/2024""#);
+        assert_eq!(to_json(&at("12:30"), &options), r#""12:30:00""#);
+    }
+}
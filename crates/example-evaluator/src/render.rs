@@ -0,0 +1,240 @@
+//! Rendering interpolated values into format strings, `f"${ port:05 }"`.
+
+use bigdecimal::RoundingMode;
+use parser::{Align, FormatSpec, NumberFormat, StandardSpec};
+
+use crate::ops::{to_big, to_decimal, to_f64};
+use crate::output::{to_json, OutputOptions};
+use crate::value::Value;
+
+/// `value` as text, the way `spec` asks for, or as [`Value`]'s `Display`
+/// without one.
+pub(crate) fn render(value: &Value, spec: Option<&FormatSpec>) -> Result<String, String> {
+    match spec {
+        None => Ok(value.to_string()),
+        Some(FormatSpec::Json) => Ok(to_json(value, &OutputOptions::default())),
+        Some(FormatSpec::Join(separator)) => match value {
+            Value::List(items) => Ok(items
+                .iter()
+                .map(Value::to_string)
+                .collect::<Vec<_>>()
+                .join(separator)),
+            other => Err(format!("join() needs a list, not {}", other.type_name())),
+        },
+        Some(FormatSpec::Standard(spec)) => standard(value, spec),
+    }
+}
+
+fn standard(value: &Value, spec: &StandardSpec) -> Result<String, String> {
+    let number = matches!(
+        value,
+        Value::Int(_) | Value::BigInt(_) | Value::Float(_) | Value::Decimal(_)
+    );
+    let (negative, body) = if number {
+        number_text(value, spec)?
+    } else if let Some(format) = spec.format {
+        return Err(format!(
+            "format `{}` needs a number, not {}",
+            letter(format),
+            value.type_name()
+        ));
+    } else if spec.sign {
+        return Err(format!("`+` needs a number, not {}", value.type_name()));
+    } else {
+        let text = value.to_string();
+        match spec.precision {
+            Some(precision) => (false, text.chars().take(precision).collect()),
+            None => (false, text),
+        }
+    };
+    let sign = match (negative, spec.sign) {
+        (true, _) => "-",
+        (false, true) => "+",
+        (false, false) => "",
+    };
+
+    let len = sign.chars().count() + body.chars().count();
+    let padding = spec.width.unwrap_or(0).saturating_sub(len);
+    if spec.zero && number && spec.align.is_none() {
+        // zeros go between the sign and the digits
+        return Ok(format!("{}{}{}", sign, "0".repeat(padding), body));
+    }
+    let fill = spec
+        .fill
+        .unwrap_or(if spec.zero { '0' } else { ' ' })
+        .to_string();
+    let align = spec
+        .align
+        .unwrap_or(if number { Align::Right } else { Align::Left });
+    let (before, after) = match align {
+        Align::Left => (0, padding),
+        Align::Right => (padding, 0),
+        Align::Center => (padding / 2, padding - padding / 2),
+    };
+    Ok(format!(
+        "{}{}{}{}",
+        fill.repeat(before),
+        sign,
+        body,
+        fill.repeat(after)
+    ))
+}
+
+/// Whether the number is negative, and its digits without the sign.
+fn number_text(value: &Value, spec: &StandardSpec) -> Result<(bool, String), String> {
+    let format = match (spec.format, spec.precision) {
+        (None, Some(_)) => Some(NumberFormat::Fixed),
+        (format, _) => format,
+    };
+    let text = match format {
+        None => value.to_string(),
+        Some(NumberFormat::Fixed) => {
+            let precision = spec.precision.unwrap_or(6);
+            match value {
+                Value::Float(x) => format!("{:.*}", precision, x),
+                other => to_decimal(other)
+                    .unwrap()
+                    .with_scale_round(precision as i64, RoundingMode::HalfEven)
+                    .to_string(),
+            }
+        }
+        Some(NumberFormat::Exponent) => {
+            let x = to_f64(value).unwrap();
+            match spec.precision {
+                Some(precision) => format!("{:.*e}", precision, x),
+                None => format!("{:e}", x),
+            }
+        }
+        Some(radix) => {
+            let Some(i) = to_big(value) else {
+                return Err(format!(
+                    "format `{}` needs an int, not {}",
+                    letter(radix),
+                    value.type_name()
+                ));
+            };
+            match radix {
+                NumberFormat::Hex => i.to_str_radix(16),
+                NumberFormat::UpperHex => i.to_str_radix(16).to_uppercase(),
+                NumberFormat::Octal => i.to_str_radix(8),
+                _ => i.to_str_radix(2),
+            }
+        }
+    };
+    Ok(match text.strip_prefix('-') {
+        Some(digits) => (true, digits.to_string()),
+        None => (false, text),
+    })
+}
+
+fn letter(format: NumberFormat) -> char {
+    match format {
+        NumberFormat::Fixed => 'f',
+        NumberFormat::Exponent => 'e',
+        NumberFormat::Hex => 'x',
+        NumberFormat::UpperHex => 'X',
+        NumberFormat::Octal => 'o',
+        NumberFormat::Binary => 'b',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+
+    use super::*;
+
+    fn spec(align: Option<Align>, fill: Option<char>, width: usize) -> FormatSpec {
+        FormatSpec::Standard(StandardSpec {
+            align,
+            fill,
+            width: Some(width),
+            ..StandardSpec::default()
+        })
+    }
+
+    fn with(f: impl FnOnce(&mut StandardSpec)) -> FormatSpec {
+        let mut spec = StandardSpec::default();
+        f(&mut spec);
+        FormatSpec::Standard(spec)
+    }
+
+    #[test]
+    fn test_padding() {
+        let name = Value::from("api");
+        assert_eq!(render(&name, Some(&spec(None, None, 6))).unwrap(), "api   ");
+        let right = spec(Some(Align::Right), None, 6);
+        assert_eq!(render(&name, Some(&right)).unwrap(), "   api");
+        let center = spec(Some(Align::Center), Some('*'), 8);
+        assert_eq!(render(&name, Some(&center)).unwrap(), "**api***");
+        assert_eq!(
+            render(&Value::Int(42), Some(&spec(None, None, 5))).unwrap(),
+            "   42"
+        );
+        let zero = with(|s| {
+            s.zero = true;
+            s.width = Some(5);
+        });
+        assert_eq!(render(&Value::Int(80), Some(&zero)).unwrap(), "00080");
+        assert_eq!(render(&Value::Int(-8), Some(&zero)).unwrap(), "-0008");
+        assert_eq!(render(&name, Some(&spec(None, None, 2))).unwrap(), "api");
+    }
+
+    #[test]
+    fn test_numbers() {
+        let fixed = with(|s| {
+            s.precision = Some(2);
+            s.format = Some(NumberFormat::Fixed);
+        });
+        assert_eq!(
+            render(&Value::Float(1.23456), Some(&fixed)).unwrap(),
+            "1.23"
+        );
+        assert_eq!(render(&Value::Int(3), Some(&fixed)).unwrap(), "3.00");
+        assert_eq!(
+            render(&Value::Decimal("2.675".parse().unwrap()), Some(&fixed)).unwrap(),
+            "2.68"
+        );
+        let signed = with(|s| s.sign = true);
+        assert_eq!(render(&Value::Int(5), Some(&signed)).unwrap(), "+5");
+        assert_eq!(render(&Value::Int(-5), Some(&signed)).unwrap(), "-5");
+        let hex = with(|s| s.format = Some(NumberFormat::UpperHex));
+        assert_eq!(render(&Value::Int(255), Some(&hex)).unwrap(), "FF");
+        let exp = with(|s| {
+            s.precision = Some(1);
+            s.format = Some(NumberFormat::Exponent);
+        });
+        assert_eq!(render(&Value::Int(1500), Some(&exp)).unwrap(), "1.5e3");
+        let truncated = with(|s| s.precision = Some(2));
+        assert_eq!(render(&Value::from("abc"), Some(&truncated)).unwrap(), "ab");
+
+        assert_eq!(
+            render(&Value::Float(1.5), Some(&hex)).unwrap_err(),
+            "format `X` needs an int, not float"
+        );
+        assert_eq!(
+            render(&Value::from("a"), Some(&fixed)).unwrap_err(),
+            "format `f` needs a number, not string"
+        );
+    }
+
+    #[test]
+    fn test_json_join() {
+        let items = Value::List(vec![Value::from("a"), Value::Int(1)]);
+        let join = FormatSpec::Join(", ".to_string());
+        assert_eq!(render(&items, Some(&join)).unwrap(), "a, 1");
+        assert_eq!(
+            render(&Value::Int(1), Some(&join)).unwrap_err(),
+            "join() needs a list, not int"
+        );
+        let dict = Value::Dict(IndexMap::from([("k".to_string(), Value::from("v"))]));
+        assert_eq!(
+            render(&dict, Some(&FormatSpec::Json)).unwrap(),
+            r#"{"k":"v"}"#
+        );
+        assert_eq!(
+            render(&Value::from("a\"b"), Some(&FormatSpec::Json)).unwrap(),
+            r#""a\"b""#
+        );
+    }
+}
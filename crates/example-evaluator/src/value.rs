@@ -0,0 +1,184 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use indexmap::IndexMap;
+use parser::{encode_base64, format_duration, BigDecimal, BigInt, ByteSize, Temporal};
+
+/// A fully evaluated value: every expression computed, every reference
+/// replaced by what it points to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    BigInt(BigInt),
+    Float(f64),
+    Decimal(BigDecimal),
+    Str(String),
+    Duration(Duration),
+    ByteSize(ByteSize),
+    Temporal(Temporal),
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    Dict(IndexMap<String, Value>),
+}
+
+impl Value {
+    /// The name of the value's type, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "bool",
+            Value::Int(_) | Value::BigInt(_) => "int",
+            Value::Float(_) => "float",
+            Value::Decimal(_) => "decimal",
+            Value::Str(_) => "string",
+            Value::Duration(_) => "duration",
+            Value::ByteSize(_) => "byte size",
+            Value::Temporal(Temporal::Date(_)) => "date",
+            Value::Temporal(Temporal::Time(_)) => "time",
+            Value::Temporal(Temporal::DateTime(_) | Temporal::Zoned(_)) => "datetime",
+            Value::Bytes(_) => "bytes",
+            Value::List(_) => "list",
+            Value::Dict(_) => "dict",
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// The entry `key` of a dict.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Dict(entries) => entries.get(key),
+            _ => None,
+        }
+    }
+}
+
+/// The value in eson syntax: strings are quoted inside lists and dicts but
+/// not at the top, so `str(x)` of a string is the string itself.
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::BigInt(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Duration(d) => write!(f, "{}", format_duration(d)),
+            Value::ByteSize(s) => write!(f, "{}", s),
+            Value::Temporal(t) => write!(f, "{}", t),
+            Value::Bytes(b) => write!(f, "b64\"{}\"", encode_base64(b)),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_nested(f, item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Dict(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}: ", key)?;
+                    write_nested(f, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_nested(f: &mut Formatter<'_>, value: &Value) -> std::fmt::Result {
+    match value {
+        Value::Str(s) => write!(f, "{:?}", s),
+        other => write!(f, "{}", other),
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Float(x)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl From<BigInt> for Value {
+    /// An `Int` when it fits in an `i64`.
+    fn from(i: BigInt) -> Self {
+        i64::try_from(&i).map_or(Value::BigInt(i), Value::Int)
+    }
+}
+
+impl From<Duration> for Value {
+    fn from(d: Duration) -> Self {
+        Value::Duration(d)
+    }
+}
+
+impl From<Temporal> for Value {
+    fn from(t: Temporal) -> Self {
+        Value::Temporal(t)
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(b: Vec<u8>) -> Self {
+        Value::Bytes(b)
+    }
+}
+
+impl From<ByteSize> for Value {
+    fn from(s: ByteSize) -> Self {
+        Value::ByteSize(s)
+    }
+}
@@ -15,14 +15,16 @@ use nom::sequence::{delimited, preceded, terminated};
 
 use crate::{eson_literal, EsonLiteralSegment, sp};
 use crate::legal_id;
+use crate::span::{span_between, Span};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Annotation {
     pub name: String,
     pub value: Option<Vec<EsonLiteralSegment>>,
+    pub span: Span,
 }
 
-fn annotation(input: &str) -> nom::IResult<&str, Annotation, VerboseError<&str>> {
+pub(crate) fn annotation(input: &str) -> nom::IResult<&str, Annotation, VerboseError<&str>> {
     let (remaining, _) = tag("@")(input)?;
     let (remaining, name) = terminated(legal_id, sp_without_br0)(remaining)?;
     let (remaining, value) = opt(preceded(
@@ -38,6 +40,7 @@ fn annotation(input: &str) -> nom::IResult<&str, Annotation, VerboseError<&str>>
         Annotation {
             name: name.to_string(),
             value,
+            span: span_between(input, remaining),
         },
     ))
 }
@@ -79,6 +82,7 @@ mod tests {
                 Annotation {
                     name: "foo".to_string(),
                     value: None,
+                    span: Span::default(),
                 }
             ))
         );
@@ -90,6 +94,7 @@ mod tests {
                 Annotation {
                     name: "foo".to_string(),
                     value: None,
+                    span: Span::default(),
                 }
             ))
         );
@@ -106,6 +111,7 @@ mod tests {
             vec![Annotation {
                 name: "foo".to_string(),
                 value: None,
+                span: Span::default(),
             }]
         );
 
@@ -125,10 +131,12 @@ mod tests {
                 Annotation {
                     name: "foo".to_string(),
                     value: None,
+                    span: Span::default(),
                 },
                 Annotation {
                     name: "bar".to_string(),
                     value: None,
+                    span: Span::default(),
                 },
             ]
         );
@@ -147,6 +155,7 @@ mod tests {
                 vec![Annotation {
                     name: "foo".to_string(),
                     value: None,
+                    span: Span::default(),
                 }]
             ))
         );
@@ -162,6 +171,7 @@ mod tests {
                 Vec::<Annotation>::from(vec![Annotation {
                     name: "world".to_string(),
                     value: None,
+                    span: Span::default(),
                 }])
             ))
         );
@@ -176,6 +186,7 @@ mod tests {
                 Annotation {
                     name: "DEF".to_string(),
                     value: None,
+                    span: Span::default(),
                 }
             ))
         );
@@ -187,6 +198,7 @@ mod tests {
                 Vec::<Annotation>::from(vec![Annotation {
                     name: "DEF".to_string(),
                     value: None,
+                    span: Span::default(),
                 }])
             ))
         );
@@ -229,10 +241,12 @@ mod tests {
                     Annotation {
                         name: "ABC".to_string(),
                         value: None,
+                        span: Span::default(),
                     },
                     Annotation {
                         name: "DEF".to_string(),
                         value: None,
+                        span: Span::default(),
                     },
                 ]
             ))
@@ -255,10 +269,12 @@ mod tests {
                     Annotation {
                         name: "some".to_string(),
                         value: None,
+                        span: Span::default(),
                     },
                     Annotation {
                         name: "some1".to_string(),
                         value: Some(vec![EsonLiteralSegment::Str("value".to_string())]),
+                        span: Span::default(),
                     },
                     Annotation {
                         name: "some2".to_string(),
@@ -266,6 +282,7 @@ mod tests {
                             EsonLiteralSegment::Str("value".to_string()),
                             EsonLiteralSegment::Str("value2".to_string()),
                         ]),
+                        span: Span::default(),
                     },
                 ]
             ))
@@ -281,6 +298,7 @@ mod tests {
                 Annotation {
                     name: "DEF".to_string(),
                     value: None,
+                    span: Span::default(),
                 }
             ))
         );
@@ -295,6 +313,7 @@ mod tests {
                 Annotation {
                     name: "some".to_string(),
                     value: None,
+                    span: Span::default(),
                 }
             ))
         );
@@ -305,6 +324,7 @@ mod tests {
                 Annotation {
                     name: "some".to_string(),
                     value: Some(vec![]),
+                    span: Span::default(),
                 }
             ))
         );
@@ -319,6 +339,7 @@ mod tests {
                         EsonLiteralSegment::Int(2),
                         EsonLiteralSegment::Int(3),
                     ]),
+                    span: Span::default(),
                 }
             ))
         );
@@ -334,6 +355,7 @@ mod tests {
                         EsonLiteralSegment::Int(3),
                         EsonLiteralSegment::Int(4),
                     ]),
+                    span: Span::default(),
                 }
             ))
         );
@@ -347,6 +369,7 @@ mod tests {
                         EsonLiteralSegment::Str("foo".to_string()),
                         EsonLiteralSegment::Str("bar".to_string()),
                     ]),
+                    span: Span::default(),
                 }
             ))
         );
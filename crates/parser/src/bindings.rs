@@ -0,0 +1,180 @@
+//! Finding `let` bindings that nothing refers to.
+
+use indexmap::IndexMap;
+
+use crate::span::Spanned;
+use crate::{
+    Document, EsonSegment, ExprToken, ExprTokenChunk, Key, KeyKind, RefPronoun, TemplatePart,
+};
+
+/// The bindings of `document` that no variable refers to, in source order.
+///
+/// A variable refers to the innermost binding of its name around it, the
+/// way the evaluator resolves it, so a shadowed binding that is never used
+/// itself is reported.
+pub fn unused_bindings(document: &Document) -> Vec<&Key> {
+    let mut walker = Walker::default();
+    let start = walker.enter(&document.bindings);
+    walker.segment(&document.value.node);
+    walker.leave(start);
+    walker.unused.sort_by_key(|key| key.span.start);
+    walker.unused
+}
+
+#[derive(Default)]
+struct Walker<'a> {
+    /// The names in scope, innermost last, with the binding they come from
+    /// (none for a loop variable) and whether they are used.
+    scopes: Vec<(&'a str, Option<&'a Key>, bool)>,
+    unused: Vec<&'a Key>,
+}
+
+impl<'a> Walker<'a> {
+    /// Bring the bindings of `entries` into scope and walk the values they
+    /// are visible from, except the ones of a document's root value.
+    fn enter(&mut self, entries: &'a IndexMap<Key, Spanned<EsonSegment>>) -> usize {
+        let start = self.scopes.len();
+        self.scopes.extend(
+            entries
+                .keys()
+                .filter(|key| key.kind == KeyKind::Let)
+                .map(|key| (key.name.as_str(), Some(key), false)),
+        );
+        for value in entries.values() {
+            self.segment(&value.node);
+        }
+        start
+    }
+
+    fn leave(&mut self, start: usize) {
+        for (_, key, used) in self.scopes.drain(start..) {
+            if let (Some(key), false) = (key, used) {
+                self.unused.push(key);
+            }
+        }
+    }
+
+    fn segment(&mut self, segment: &'a EsonSegment) {
+        match segment {
+            EsonSegment::Dict(entries) => {
+                let start = self.enter(entries);
+                self.leave(start);
+            }
+            EsonSegment::List(items) => {
+                for item in items {
+                    self.segment(&item.node);
+                }
+            }
+            EsonSegment::Template(parts) => {
+                for part in parts {
+                    if let TemplatePart::Expr(chunk, _) = part {
+                        self.chunk(&chunk.node);
+                    }
+                }
+            }
+            EsonSegment::Expr(chunk) | EsonSegment::Spread(chunk) => self.chunk(chunk),
+            EsonSegment::If(condition, item) => {
+                self.chunk(condition);
+                self.segment(&item.node);
+            }
+            EsonSegment::Computed(key, value) => {
+                self.segment(&key.node);
+                self.segment(&value.node);
+            }
+            EsonSegment::For(names, iterable, item) => {
+                self.chunk(iterable);
+                self.parameters(names, item);
+            }
+            EsonSegment::Fn(params, body) => self.parameters(params, body),
+            _ => {}
+        }
+    }
+
+    /// Walk `body` with the loop variables or function parameters `names`
+    /// in scope.
+    fn parameters(&mut self, names: &'a [String], body: &'a Spanned<EsonSegment>) {
+        let start = self.scopes.len();
+        self.scopes
+            .extend(names.iter().map(|name| (name.as_str(), None, false)));
+        self.segment(&body.node);
+        self.scopes.truncate(start);
+    }
+
+    fn use_name(&mut self, name: &str) {
+        if let Some((_, _, used)) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find(|(scoped, _, _)| *scoped == name)
+        {
+            *used = true;
+        }
+    }
+
+    fn chunk(&mut self, chunk: &'a ExprTokenChunk) {
+        for token in &chunk.0 {
+            match &token.node {
+                ExprToken::Var(name) | ExprToken::Ref(RefPronoun::Var(name, _)) => {
+                    self.use_name(name)
+                }
+                ExprToken::Group(chunk) => self.chunk(chunk),
+                ExprToken::Val(segment) => self.segment(segment),
+                ExprToken::FnCall(name, args) => {
+                    // a call may be of a `fn` binding
+                    self.use_name(name);
+                    for arg in args {
+                        self.chunk(arg);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::root;
+
+    use super::*;
+
+    fn unused(src: &str) -> Vec<String> {
+        let document = root(src).unwrap();
+        unused_bindings(&document)
+            .into_iter()
+            .map(|key| format!("{}@{}", key.name, key.span.column))
+            .collect()
+    }
+
+    #[test]
+    fn test_unused_bindings() {
+        assert!(unused(r#"let a = 1; { x: ${ a } }"#).is_empty());
+        assert_eq!(
+            unused(r#"let a = 1; let b = ${ a }; { x: 1 }"#),
+            vec!["b@16"]
+        );
+        // the inner `a` shadows the outer one for `x`
+        assert_eq!(
+            unused(r#"let a = 1; { d: { let a = 2; x: ${ a } }, y: f"${ a }" }"#),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            unused(r#"let a = 1; { d: { let a = 2; x: ${ a } } }"#),
+            vec!["a@5"]
+        );
+        assert_eq!(
+            unused(r#"{ let a = 1; let b = 2; x: [${ upper(b) }] }"#),
+            vec!["a@7"]
+        );
+        assert!(unused(r#"let dev = true; let v = 1; [if dev: ${ v }]"#).is_empty());
+        // the loop variable shadows the binding
+        assert_eq!(
+            unused(r#"let r = 1; let rs = [2]; [for r in rs: ${ r.x }]"#),
+            vec!["r@5"]
+        );
+        assert_eq!(
+            unused(r#"let a = 1; let f = fn(a, b) => ${ a }; let g = fn() => 1; [${ f(1, 2) }]"#),
+            vec!["a@5", "g@44"]
+        );
+    }
+}
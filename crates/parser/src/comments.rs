@@ -1,21 +1,71 @@
-use nom::bytes::complete::{tag, take_until, take_while};
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_till, take_while};
 use nom::character::complete::{char as ch, multispace0};
-use nom::error::VerboseError;
-use nom::IResult;
+use nom::combinator::opt;
+use nom::error::{ContextError, ErrorKind, ParseError, VerboseError};
 use nom::sequence::{preceded, terminated};
+use nom::{Err, IResult};
+
+use crate::session;
 
 fn sp(input: &str) -> IResult<&str, &str, VerboseError<&str>> {
-    let chars = " \t\r\n";
+    let chars = " \t";
     take_while(move |c| chars.contains(c))(input)
 }
 
+/// A comment, returning its text without the delimiters and surrounding blanks.
+///
+/// `// line` and `/* block */` comments are always available, `# line`
+/// comments only with [`ParseOptions::hash_comments`](crate::ParseOptions).
+/// Line comments end at `\n`, `\r\n` or the end of input.
 pub(crate) fn comment(input: &str) -> IResult<&str, &str, VerboseError<&str>> {
-    let (remaining, _) = preceded(multispace0, tag("//"))(input)?;
-    preceded(sp, terminated(take_until("\n"), ch('\n')))(remaining)
+    let (remaining, _) = multispace0(input)?;
+    alt((line_comment, block_comment))(remaining)
+}
+
+fn line_comment(input: &str) -> IResult<&str, &str, VerboseError<&str>> {
+    let (remaining, _) = if session::options().hash_comments {
+        alt((tag("//"), tag("#")))(input)?
+    } else {
+        tag("//")(input)?
+    };
+    let (remaining, text) =
+        preceded(sp, terminated(take_till(|c| c == '\n'), opt(ch('\n'))))(remaining)?;
+    Ok((remaining, text.trim_end()))
+}
+
+fn block_comment(input: &str) -> IResult<&str, &str, VerboseError<&str>> {
+    let (body, _) = tag("/*")(input)?;
+    let nested = session::options().nested_comments;
+    let mut depth = 1;
+    let mut rest = body;
+    while depth > 0 {
+        if let Some(remaining) = rest.strip_prefix("*/") {
+            depth -= 1;
+            rest = remaining;
+        } else if let Some(remaining) = rest.strip_prefix("/*").filter(|_| nested) {
+            depth += 1;
+            rest = remaining;
+        } else if let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+        } else {
+            let err = VerboseError::from_error_kind(rest, ErrorKind::TakeUntil);
+            return Err(Err::Failure(VerboseError::add_context(
+                input,
+                "block_comment",
+                err,
+            )));
+        }
+    }
+    let text = &body[..body.len() - rest.len() - 2];
+    Ok((rest, text.trim()))
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::session::with_session;
+    use crate::ParseOptions;
+
     use super::*;
 
     #[test]
@@ -30,4 +80,46 @@ mod tests {
             Ok(("        @world", "hello"))
         );
     }
+
+    #[test]
+    fn test_comment_line_endings() {
+        assert_eq!(comment("// hello"), Ok(("", "hello")));
+        assert_eq!(comment("//"), Ok(("", "")));
+        assert_eq!(comment("// hello\r\nworld"), Ok(("world", "hello")));
+    }
+
+    #[test]
+    fn test_block_comment() {
+        assert_eq!(comment("/* hello */world"), Ok(("world", "hello")));
+        assert_eq!(comment("/**/"), Ok(("", "")));
+        assert_eq!(comment("/* a\n * b\n */ x"), Ok((" x", "a\n * b")));
+        assert_eq!(comment("/* a /* b */ c */"), Ok((" c */", "a /* b")));
+        assert!(matches!(comment("/* hello"), Err(Err::Failure(_))));
+    }
+
+    #[test]
+    fn test_nested_block_comment() {
+        let options = ParseOptions {
+            nested_comments: true,
+            ..ParseOptions::default()
+        };
+        let src = "/* a /* b */ c */x";
+        with_session(src, &options, false, || {
+            assert_eq!(comment(src), Ok(("x", "a /* b */ c")));
+            assert!(comment("/* a /* b */").is_err());
+        });
+    }
+
+    #[test]
+    fn test_hash_comment() {
+        assert!(comment("# hello\n").is_err());
+        let options = ParseOptions {
+            hash_comments: true,
+            ..ParseOptions::default()
+        };
+        with_session("", &options, false, || {
+            assert_eq!(comment("# hello\nworld"), Ok(("world", "hello")));
+            assert_eq!(comment("// hello"), Ok(("", "hello")));
+        });
+    }
 }
@@ -0,0 +1,738 @@
+//! A lossless view of a document: every byte of the source, whitespace and
+//! comments included, belongs to exactly one node, so printing the tree gives
+//! back the input unchanged.
+//!
+//! ```
+//! let src = "{\n    // the port\n    port: 8080, // default\n}\n";
+//! let doc = parser::cst::parse(src).unwrap();
+//! assert_eq!(doc.to_string(), src);
+//!
+//! let entry = doc.value.as_dict().unwrap().get("port").unwrap();
+//! assert_eq!(entry.key.leading.comments().collect::<Vec<_>>(), vec!["// the port"]);
+//! assert_eq!(entry.trailing.comments().collect::<Vec<_>>(), vec!["// default"]);
+//! ```
+//!
+//! Trivia (whitespace and comments) in front of a node is its `leading`
+//! trivia. What follows an entry or list item on the same line, up to the line
+//! break, is that entry's `trailing` trivia.
+
+use std::fmt::{Display, Formatter};
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, multispace1};
+use nom::combinator::{map, recognize};
+use nom::error::VerboseError;
+use nom::sequence::tuple;
+use nom::IResult;
+
+use crate::annotation::annotation;
+use crate::comments::comment;
+use crate::dict::{computed_entry, for_entry, function, if_entry};
+use crate::expr::legal_id;
+use crate::expr_token::{keyword, parse_condition, parse_for, parse_spread};
+use crate::list::item;
+use crate::span::{span_between, Span};
+use crate::string::{parse_string, parse_template};
+use crate::{root_with, session, Error, KeyKind, ParseOptions};
+
+pub use edit::{EditError, Raw, ToSource};
+
+mod edit;
+
+/// A parsed document, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub annotations: Vec<Annotation>,
+    /// The `let` prelude.
+    pub bindings: Vec<Entry>,
+    pub value: Value,
+    /// Everything after the root value.
+    pub trailing: Trivia,
+}
+
+/// An annotation such as `@tag("a", 1)`, kept as written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub leading: Trivia,
+    pub name: String,
+    pub raw: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Value {
+    pub leading: Trivia,
+    pub kind: ValueKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueKind {
+    /// A string, number, boolean, null, `${ ... }` expression, `fn` bound by
+    /// `let`, or a spread, `if`, `for` or computed item or entry, as written.
+    Scalar(String),
+    Dict(Dict),
+    List(List),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dict {
+    pub entries: Vec<Entry>,
+    /// The trivia before the closing `}`.
+    pub close: Trivia,
+}
+
+/// `@annotations key: value,` in a dict, or a `let name = value;` binding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub annotations: Vec<Annotation>,
+    pub key: Key,
+    /// The trivia before the `:`, or the `=` of a binding.
+    pub colon: Trivia,
+    pub value: Value,
+    /// The trivia before the `,`, if the entry has one. A binding always
+    /// has one, before its `;`.
+    pub comma: Option<Trivia>,
+    pub trailing: Trivia,
+}
+
+/// A dict key, quoted or bare.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Key {
+    pub leading: Trivia,
+    pub name: String,
+    pub raw: String,
+    pub span: Span,
+    /// The key of a `...expr`, `if`, `for` or `[expr]: value` entry is empty,
+    /// the entry has no `:` and its value is the whole entry as written. The raw
+    /// key of a binding is `let` and its name.
+    pub kind: KeyKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct List {
+    pub items: Vec<Item>,
+    /// The trivia before the closing `]`.
+    pub close: Trivia,
+}
+
+/// `value,` in a list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub value: Value,
+    /// The trivia before the `,`, if the item has one.
+    pub comma: Option<Trivia>,
+    pub trailing: Trivia,
+}
+
+/// Whitespace and comments, kept verbatim.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trivia(pub Vec<TriviaPiece>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TriviaPiece {
+    Whitespace(String),
+    /// `// ...` or `# ...`, without the line break.
+    LineComment(String),
+    /// `/* ... */`.
+    BlockComment(String),
+}
+
+impl Trivia {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The comments, delimiters included.
+    pub fn comments(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(|piece| match piece {
+            TriviaPiece::Whitespace(_) => None,
+            TriviaPiece::LineComment(text) | TriviaPiece::BlockComment(text) => Some(text.as_str()),
+        })
+    }
+}
+
+impl Value {
+    pub fn as_dict(&self) -> Option<&Dict> {
+        match &self.kind {
+            ValueKind::Dict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&List> {
+        match &self.kind {
+            ValueKind::List(list) => Some(list),
+            _ => None,
+        }
+    }
+}
+
+impl Dict {
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.key.kind == KeyKind::Entry && entry.key.name == name)
+    }
+}
+
+/// Parse `input` into a lossless tree, see [`parse_with`].
+pub fn parse(input: &str) -> Result<Document, Error> {
+    parse_with(input, &ParseOptions::default())
+}
+
+/// Parse `input` into a lossless tree. The input must be a valid document,
+/// errors are the ones [`root_with`] reports.
+pub fn parse_with(input: &str, options: &ParseOptions) -> Result<Document, Error> {
+    root_with(input, options)?;
+    session::with_session(input, options, false, || {
+        document(input).map(|(_, doc)| doc).map_err(session::error)
+    })
+    .0
+}
+
+fn document(input: &str) -> IResult<&str, Document, VerboseError<&str>> {
+    let (mut input, mut leading) = trivia(input, true);
+    let mut annotations = vec![];
+    while input.starts_with('@') {
+        let (remaining, parsed) = cst_annotation(input, leading)?;
+        annotations.push(parsed);
+        (input, leading) = trivia(remaining, true);
+    }
+    let mut bindings = vec![];
+    while let Some((remaining, parsed)) = binding(input, &leading)? {
+        bindings.push(parsed);
+        (input, leading) = trivia(remaining, true);
+    }
+    let (input, value) = value(input, leading)?;
+    let (input, trailing) = trivia(input, true);
+    Ok((
+        input,
+        Document {
+            annotations,
+            bindings,
+            value,
+            trailing,
+        },
+    ))
+}
+
+fn cst_annotation(input: &str, leading: Trivia) -> IResult<&str, Annotation, VerboseError<&str>> {
+    let (remaining, parsed) = annotation(input)?;
+    let consumed = &input[..input.len() - remaining.len()];
+    let raw = consumed.trim_end_matches([' ', '\t', '\r']);
+    Ok((
+        &input[raw.len()..],
+        Annotation {
+            leading,
+            name: parsed.name,
+            raw: raw.to_string(),
+            span: parsed.span,
+        },
+    ))
+}
+
+fn value(input: &str, leading: Trivia) -> IResult<&str, Value, VerboseError<&str>> {
+    let (remaining, kind) = if input.starts_with('{') {
+        map(dict, ValueKind::Dict)(input)?
+    } else if input.starts_with('[') {
+        map(list, ValueKind::List)(input)?
+    } else {
+        return scalar(input, leading, item);
+    };
+    Ok((
+        remaining,
+        Value {
+            leading,
+            kind,
+            span: span_between(input, remaining),
+        },
+    ))
+}
+
+/// What `parser` accepts, as written.
+fn scalar<'a, O>(
+    input: &'a str,
+    leading: Trivia,
+    mut parser: impl FnMut(&'a str) -> IResult<&'a str, O, VerboseError<&'a str>>,
+) -> IResult<&'a str, Value, VerboseError<&'a str>> {
+    let (remaining, _) = parser(input)?;
+    let raw = input[..input.len() - remaining.len()].trim_end();
+    let remaining = &input[raw.len()..];
+    Ok((
+        remaining,
+        Value {
+            leading,
+            kind: ValueKind::Scalar(raw.to_string()),
+            span: span_between(input, remaining),
+        },
+    ))
+}
+
+fn dict(input: &str) -> IResult<&str, Dict, VerboseError<&str>> {
+    let (mut input, _) = char('{')(input)?;
+    let mut entries = vec![];
+    loop {
+        let (remaining, mut leading) = trivia(input, true);
+        if let Ok((remaining, _)) = char::<_, VerboseError<&str>>('}')(remaining) {
+            return Ok((
+                remaining,
+                Dict {
+                    entries,
+                    close: leading,
+                },
+            ));
+        }
+        if let Some((remaining, parsed)) = binding(remaining, &leading)? {
+            entries.push(parsed);
+            input = remaining;
+            continue;
+        }
+        let merged = if remaining.starts_with("...") {
+            Some((
+                KeyKind::Spread,
+                scalar(remaining, Trivia::default(), parse_spread)?,
+            ))
+        } else if parse_condition(remaining).is_ok() {
+            Some((KeyKind::If, scalar(remaining, Trivia::default(), if_entry)?))
+        } else if parse_for(remaining).is_ok() {
+            Some((
+                KeyKind::For,
+                scalar(remaining, Trivia::default(), for_entry)?,
+            ))
+        } else if remaining.starts_with('[') || parse_template(remaining).is_ok() {
+            Some((
+                KeyKind::Computed,
+                scalar(remaining, Trivia::default(), computed_entry)?,
+            ))
+        } else {
+            None
+        };
+        if let Some((kind, (remaining, value))) = merged {
+            let key = Key {
+                leading,
+                name: String::new(),
+                raw: String::new(),
+                span: value.span,
+                kind,
+            };
+            let (remaining, comma, trailing) = separator(remaining);
+            entries.push(Entry {
+                annotations: vec![],
+                key,
+                colon: Trivia::default(),
+                value,
+                comma,
+                trailing,
+            });
+            input = remaining;
+            continue;
+        }
+        let mut rest = remaining;
+        let mut annotations = vec![];
+        while rest.starts_with('@') {
+            let (remaining, parsed) = cst_annotation(rest, leading)?;
+            annotations.push(parsed);
+            (rest, leading) = trivia(remaining, true);
+        }
+        let (remaining, raw) =
+            recognize(alt((map(parse_string, |_| ()), map(legal_id, |_| ()))))(rest)?;
+        let (_, name) = alt((parse_string, map(legal_id, String::from)))(raw)?;
+        let key = Key {
+            leading,
+            name,
+            raw: raw.to_string(),
+            span: span_between(rest, remaining),
+            kind: KeyKind::Entry,
+        };
+        let (remaining, colon) = trivia(remaining, true);
+        let (remaining, _) = char(':')(remaining)?;
+        let (remaining, leading) = trivia(remaining, true);
+        let (remaining, value) = value(remaining, leading)?;
+        let (remaining, comma, trailing) = separator(remaining);
+        entries.push(Entry {
+            annotations,
+            key,
+            colon,
+            value,
+            comma,
+            trailing,
+        });
+        input = remaining;
+    }
+}
+
+/// `let name = value;`, if `input` starts with a binding.
+fn binding<'a>(
+    input: &'a str,
+    leading: &Trivia,
+) -> Result<Option<(&'a str, Entry)>, nom::Err<VerboseError<&'a str>>> {
+    let parsed: IResult<&str, _, VerboseError<&str>> =
+        tuple((tag("let"), multispace1, legal_id))(input);
+    let Ok((remaining, (_, _, name))) = parsed else {
+        return Ok(None);
+    };
+    let key = Key {
+        leading: leading.clone(),
+        name: name.to_string(),
+        raw: input[..input.len() - remaining.len()].to_string(),
+        span: span_between(input, remaining),
+        kind: KeyKind::Let,
+    };
+    let (remaining, colon) = trivia(remaining, true);
+    let (remaining, _) = char('=')(remaining)?;
+    let (remaining, leading) = trivia(remaining, true);
+    let (remaining, value) = if keyword("fn")(remaining).is_ok() {
+        scalar(remaining, leading, function)?
+    } else {
+        value(remaining, leading)?
+    };
+    let (remaining, before) = trivia(remaining, true);
+    let (remaining, _) = char(';')(remaining)?;
+    let (remaining, trailing) = trivia(remaining, false);
+    let entry = Entry {
+        annotations: vec![],
+        key,
+        colon,
+        value,
+        comma: Some(before),
+        trailing,
+    };
+    Ok(Some((remaining, entry)))
+}
+
+fn list(input: &str) -> IResult<&str, List, VerboseError<&str>> {
+    let (mut input, _) = char('[')(input)?;
+    let mut items = vec![];
+    loop {
+        let (remaining, leading) = trivia(input, true);
+        if let Ok((remaining, _)) = char::<_, VerboseError<&str>>(']')(remaining) {
+            return Ok((
+                remaining,
+                List {
+                    items,
+                    close: leading,
+                },
+            ));
+        }
+        let (remaining, value) = value(remaining, leading)?;
+        let (remaining, comma, trailing) = separator(remaining);
+        items.push(Item {
+            value,
+            comma,
+            trailing,
+        });
+        input = remaining;
+    }
+}
+
+/// The optional `,` after an entry or item, with the trivia before it and
+/// the trivia after it up to the end of the line.
+fn separator(input: &str) -> (&str, Option<Trivia>, Trivia) {
+    let (remaining, before) = trivia(input, true);
+    match remaining.strip_prefix(',') {
+        Some(remaining) => {
+            let (remaining, trailing) = trivia(remaining, false);
+            (remaining, Some(before), trailing)
+        }
+        None => {
+            let (remaining, trailing) = trivia(input, false);
+            (remaining, None, trailing)
+        }
+    }
+}
+
+/// Whitespace and comments at the start of `input`, stopping at the first
+/// line break unless `multiline`.
+fn trivia(mut input: &str, multiline: bool) -> (&str, Trivia) {
+    let hash_comments = session::options().hash_comments;
+    let mut pieces = vec![];
+    loop {
+        let blank = input
+            .find(|c: char| !(c == ' ' || c == '\t' || multiline && (c == '\r' || c == '\n')))
+            .unwrap_or(input.len());
+        if blank > 0 {
+            pieces.push(TriviaPiece::Whitespace(input[..blank].to_string()));
+            input = &input[blank..];
+        } else if input.starts_with("//") || hash_comments && input.starts_with('#') {
+            let end = input.find('\n').unwrap_or(input.len());
+            let text = input[..end].strip_suffix('\r').unwrap_or(&input[..end]);
+            pieces.push(TriviaPiece::LineComment(text.to_string()));
+            input = &input[text.len()..];
+        } else if input.starts_with("/*") {
+            match comment(input) {
+                Ok((remaining, _)) => {
+                    let text = &input[..input.len() - remaining.len()];
+                    pieces.push(TriviaPiece::BlockComment(text.to_string()));
+                    input = remaining;
+                }
+                Err(_) => break,
+            }
+        } else {
+            break;
+        }
+    }
+    (input, Trivia(pieces))
+}
+
+impl Display for Document {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for annotation in &self.annotations {
+            write!(f, "{}", annotation)?;
+        }
+        for binding in &self.bindings {
+            write!(f, "{}", binding)?;
+        }
+        write!(f, "{}{}", self.value, self.trailing)
+    }
+}
+
+impl Display for Annotation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.leading, self.raw)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.leading)?;
+        match &self.kind {
+            ValueKind::Scalar(raw) => write!(f, "{}", raw),
+            ValueKind::Dict(dict) => write!(f, "{}", dict),
+            ValueKind::List(list) => write!(f, "{}", list),
+        }
+    }
+}
+
+impl Display for Dict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{")?;
+        for entry in &self.entries {
+            write!(f, "{}", entry)?;
+        }
+        write!(f, "{}}}", self.close)
+    }
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for annotation in &self.annotations {
+            write!(f, "{}", annotation)?;
+        }
+        match self.key.kind {
+            KeyKind::Spread | KeyKind::If | KeyKind::For | KeyKind::Computed => {
+                write!(f, "{}{}", self.key, self.value)?
+            }
+            KeyKind::Let => write!(f, "{}{}={}", self.key, self.colon, self.value)?,
+            KeyKind::Entry => write!(f, "{}{}:{}", self.key, self.colon, self.value)?,
+        }
+        let separator = if self.key.kind == KeyKind::Let {
+            ';'
+        } else {
+            ','
+        };
+        if let Some(comma) = &self.comma {
+            write!(f, "{}{}", comma, separator)?;
+        }
+        write!(f, "{}", self.trailing)
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.leading, self.raw)
+    }
+}
+
+impl Display for List {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[")?;
+        for item in &self.items {
+            write!(f, "{}", item)?;
+        }
+        write!(f, "{}]", self.close)
+    }
+}
+
+impl Display for Item {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.value)?;
+        if let Some(comma) = &self.comma {
+            write!(f, "{},", comma)?;
+        }
+        write!(f, "{}", self.trailing)
+    }
+}
+
+impl Display for Trivia {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for piece in &self.0 {
+            match piece {
+                TriviaPiece::Whitespace(text)
+                | TriviaPiece::LineComment(text)
+                | TriviaPiece::BlockComment(text) => write!(f, "{}", text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let sources = [
+            "{}",
+            "  [ ]  \n",
+            "{\"a\":1,b:[1,2,],}",
+            "@license(\"Apache-2.0\")\n@example\n{\n    // This is a comment\n    \"name\": \"Eson\",\n}\n",
+            "{\r\n  \"a\": r\"raw\", // one\r\n  /* two */ @tag(1, 2)  b /* c */ : ${ x + 1 } , /* d */\r\n}",
+            "/* head */ [\n  1,\n  {\"k\": [true, null, 1.5e3]}\n  // last\n] // end",
+            "{\"s\": f\"hi ${name}\", \"t\": \"a\\\"b\"}",
+        ];
+        for src in sources {
+            assert_eq!(parse(src).unwrap().to_string(), src);
+        }
+    }
+
+    #[test]
+    fn test_trivia_attachment() {
+        let src = "// doc\n{\n  // leading\n  @a \"k\": 1, // trailing\n  \"l\": [\n    2 /* x */\n  ]\n  /* close */\n}";
+        let doc = parse(src).unwrap();
+        assert_eq!(
+            doc.value.leading.comments().collect::<Vec<_>>(),
+            vec!["// doc"]
+        );
+
+        let dict = doc.value.as_dict().unwrap();
+        let k = dict.get("k").unwrap();
+        assert_eq!(k.annotations[0].name, "a");
+        assert_eq!(
+            k.annotations[0].leading.comments().collect::<Vec<_>>(),
+            vec!["// leading"]
+        );
+        assert_eq!(k.key.raw, "\"k\"");
+        assert_eq!(k.value.kind, ValueKind::Scalar("1".to_string()));
+        assert_eq!(
+            k.trailing.comments().collect::<Vec<_>>(),
+            vec!["// trailing"]
+        );
+
+        let l = dict.get("l").unwrap();
+        assert!(l.comma.is_none());
+        let item = &l.value.as_list().unwrap().items[0];
+        assert_eq!(
+            item.trailing.comments().collect::<Vec<_>>(),
+            vec!["/* x */"]
+        );
+        assert_eq!(
+            dict.close.comments().collect::<Vec<_>>(),
+            vec!["/* close */"]
+        );
+
+        assert_eq!((k.key.span.line, k.key.span.column), (4, 6));
+    }
+
+    #[test]
+    fn test_spread() {
+        let src = "{\n  ...$.base, // base\n  \"\": 1,\n  ... $.more\n}";
+        let doc = parse(src).unwrap();
+        assert_eq!(doc.to_string(), src);
+        let dict = doc.value.as_dict().unwrap();
+        assert_eq!(dict.entries[0].key.kind, KeyKind::Spread);
+        assert_eq!(
+            dict.entries[0].value.kind,
+            ValueKind::Scalar("...$.base".to_string())
+        );
+        assert_eq!(
+            dict.get("").unwrap().value.kind,
+            ValueKind::Scalar("1".to_string())
+        );
+
+        let src = "[ ...$.a , 1 ]";
+        assert_eq!(parse(src).unwrap().to_string(), src);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let src = "{\n  if $.debug: level: \"trace\", // noisy\n  if: 1,\n  list: [80, if $.tls : 443 ],\n}";
+        let doc = parse(src).unwrap();
+        assert_eq!(doc.to_string(), src);
+        let dict = doc.value.as_dict().unwrap();
+        assert_eq!(dict.entries[0].key.kind, KeyKind::If);
+        assert_eq!(
+            dict.entries[0].value.kind,
+            ValueKind::Scalar("if $.debug: level: \"trace\"".to_string())
+        );
+        assert_eq!(
+            dict.entries[0].trailing.comments().collect::<Vec<_>>(),
+            vec!["// noisy"]
+        );
+        assert!(dict.get("if").is_some());
+        let list = dict.get("list").unwrap().value.as_list().unwrap();
+        assert_eq!(
+            list.items[1].value.kind,
+            ValueKind::Scalar("if $.tls : 443".to_string())
+        );
+    }
+
+    #[test]
+    fn test_comprehensions() {
+        let src = "{\n  for s in $.services: [s.name]: ${ s.port }, // ports\n  f\"${ $.env }-url\" : 1,\n  for: [for x in $.xs: ${ x }],\n}";
+        let doc = parse(src).unwrap();
+        assert_eq!(doc.to_string(), src);
+        let dict = doc.value.as_dict().unwrap();
+        assert_eq!(dict.entries[0].key.kind, KeyKind::For);
+        assert_eq!(
+            dict.entries[0].trailing.comments().collect::<Vec<_>>(),
+            vec!["// ports"]
+        );
+        assert_eq!(dict.entries[1].key.kind, KeyKind::Computed);
+        assert_eq!(
+            dict.entries[1].value.kind,
+            ValueKind::Scalar("f\"${ $.env }-url\" : 1".to_string())
+        );
+        assert!(dict.get("for").is_some());
+    }
+
+    #[test]
+    fn test_bindings() {
+        let src = "@a\n// the port\nlet port = 80; /* ok */\nlet  host =\"h\" ;\n{\n  let x = ${ port };\n  let: ${ x },\n}\n";
+        let doc = parse(src).unwrap();
+        assert_eq!(doc.to_string(), src);
+        assert_eq!(doc.bindings.len(), 2);
+        let host = &doc.bindings[1].key;
+        assert_eq!(
+            (host.name.as_str(), host.raw.as_str()),
+            ("host", "let  host")
+        );
+        assert_eq!(
+            doc.bindings[0].trailing.comments().collect::<Vec<_>>(),
+            vec!["/* ok */"]
+        );
+        let dict = doc.value.as_dict().unwrap();
+        assert_eq!(dict.entries[0].key.kind, KeyKind::Let);
+        assert!(dict.get("x").is_none());
+        assert_eq!(
+            dict.get("let").unwrap().value.kind,
+            ValueKind::Scalar("${ x }".to_string())
+        );
+
+        let src = "let f = fn(a) => {\n  x: ${ a }, // twice\n} ; /* ok */\n[${ f(1) }]";
+        let doc = parse(src).unwrap();
+        assert_eq!(doc.to_string(), src);
+        assert_eq!(
+            doc.bindings[0].value.kind,
+            ValueKind::Scalar("fn(a) => {\n  x: ${ a }, // twice\n}".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid() {
+        let err = parse("{\"a\" 1}").unwrap_err();
+        assert_eq!(err.expected(), "':'");
+    }
+}
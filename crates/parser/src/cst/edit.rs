@@ -0,0 +1,777 @@
+//! Editing a [`Document`] in place. Paths use the reference syntax of
+//! expressions, `$` being the root value: `$.server.port`, `$.hosts[0]`,
+//! `$["key with spaces"]`.
+//!
+//! Only the nodes an edit touches change, comments and formatting everywhere
+//! else are written back as they were read. New entries and items copy the
+//! layout of their siblings.
+
+use std::fmt::{Display, Formatter};
+
+use nom::combinator::all_consuming;
+
+use super::{
+    trivia, value, Annotation, Dict, Document, Entry, Item, Key, List, Trivia, TriviaPiece, Value,
+    ValueKind,
+};
+use crate::annotation::annotation;
+use crate::expr::legal_id;
+use crate::expr_token::{reference, ExprToken, RefIndex, RefPronoun};
+use crate::span::Span;
+use crate::{parse_value, session, BigDecimal, BigInt, Error, KeyKind, ParseOptions};
+
+/// Why an edit was rejected, the document is left unchanged.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditError {
+    /// The path is not a `$...` reference.
+    InvalidPath(String),
+    /// Nothing exists at the path.
+    NotFound(String),
+    /// The value at the path is not a dict or not a list, as the edit requires.
+    WrongType {
+        path: String,
+        expected: &'static str,
+    },
+    /// The dict at `path` has the key already, see [`Document::set`] to
+    /// replace its value.
+    KeyExists { path: String, key: String },
+    /// The new value is not valid eson.
+    InvalidValue(Error),
+    /// The annotation is not a single valid `@name(...)`.
+    InvalidAnnotation(String),
+}
+
+impl Display for EditError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EditError::InvalidPath(path) => write!(f, "invalid path `{}`", path),
+            EditError::NotFound(path) => write!(f, "nothing at `{}`", path),
+            EditError::WrongType { path, expected } => {
+                write!(f, "the value at `{}` is not {}", path, expected)
+            }
+            EditError::KeyExists { path, key } => {
+                write!(f, "the dict at `{}` has the key {:?} already", path, key)
+            }
+            EditError::InvalidValue(err) => write!(f, "invalid value: {}", err),
+            EditError::InvalidAnnotation(text) => write!(f, "invalid annotation `{}`", text),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// A Rust value that can be written into a document.
+pub trait ToSource {
+    /// The eson source text of the value.
+    fn to_source(&self) -> String;
+}
+
+/// Eson source text written as is, e.g. `Raw("{\"a\": [1, 2]}")` or `Raw("${ a + 1 }")`.
+#[derive(Debug, Clone, Copy)]
+pub struct Raw<'a>(pub &'a str);
+
+impl ToSource for Raw<'_> {
+    fn to_source(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+impl ToSource for str {
+    fn to_source(&self) -> String {
+        let mut quoted = String::with_capacity(self.len() + 2);
+        quoted.push('"');
+        for c in self.chars() {
+            match c {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\t' => quoted.push_str("\\t"),
+                c if c.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
+    }
+}
+
+impl ToSource for String {
+    fn to_source(&self) -> String {
+        self.as_str().to_source()
+    }
+}
+
+impl ToSource for bool {
+    fn to_source(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ToSource for f64 {
+    fn to_source(&self) -> String {
+        match self {
+            f if f.is_nan() => "NaN".to_string(),
+            f if f.is_infinite() && *f > 0.0 => "Infinity".to_string(),
+            f if f.is_infinite() => "-Infinity".to_string(),
+            f => format!("{:?}", f),
+        }
+    }
+}
+
+impl<T: ToSource + ?Sized> ToSource for &T {
+    fn to_source(&self) -> String {
+        (**self).to_source()
+    }
+}
+
+macro_rules! int_to_source {
+    ($($t:ty),*) => {
+        $(impl ToSource for $t {
+            fn to_source(&self) -> String {
+                self.to_string()
+            }
+        })*
+    };
+}
+
+int_to_source!(i8, i16, i32, i64, u8, u16, u32, u64, isize, usize, BigInt, BigDecimal);
+
+impl Document {
+    /// The value at `path`.
+    pub fn get(&self, path: &str) -> Result<&Value, EditError> {
+        let mut current = &self.value;
+        for segment in parse_path(path)? {
+            current = match (&current.kind, &segment) {
+                (ValueKind::Dict(dict), RefIndex::Str(name)) => dict.get(name).map(|e| &e.value),
+                (ValueKind::List(list), RefIndex::Int(i)) => {
+                    list.items.get(*i as usize).map(|item| &item.value)
+                }
+                _ => None,
+            }
+            .ok_or_else(|| EditError::NotFound(path.to_string()))?;
+        }
+        Ok(current)
+    }
+
+    /// Set the value at `path`, adding the last key if the dict lacks it.
+    ///
+    /// ```
+    /// use parser::cst;
+    ///
+    /// let mut doc = cst::parse("{\n    // keep me\n    \"port\": 80,\n}").unwrap();
+    /// doc.set("$.port", 8080).unwrap();
+    /// doc.set("$.host", "localhost").unwrap();
+    /// assert_eq!(
+    ///     doc.to_string(),
+    ///     "{\n    // keep me\n    \"port\": 8080,\n    \"host\": \"localhost\",\n}"
+    /// );
+    /// ```
+    pub fn set(&mut self, path: &str, value: impl ToSource) -> Result<(), EditError> {
+        let new = parse_source(&value.to_source())?;
+        let (parent, last) = self.parent_mut(path)?;
+        match (&mut parent.kind, last) {
+            (ValueKind::Dict(dict), RefIndex::Str(name)) => match dict.get_mut(&name) {
+                Some(entry) => entry.value.kind = new.kind,
+                None => dict.push(&name, new),
+            },
+            (ValueKind::List(list), RefIndex::Int(i)) => match list.items.get_mut(i as usize) {
+                Some(item) => item.value.kind = new.kind,
+                None => return Err(EditError::NotFound(path.to_string())),
+            },
+            (ValueKind::List(_), _) => return Err(EditError::NotFound(path.to_string())),
+            _ => return Err(wrong_type(path, "a dict")),
+        }
+        Ok(())
+    }
+
+    /// Add `key: value` at the end of the dict at `path`, which must not
+    /// have `key` yet.
+    pub fn insert(&mut self, path: &str, key: &str, value: impl ToSource) -> Result<(), EditError> {
+        let new = parse_source(&value.to_source())?;
+        let dict = self.dict_mut(path)?;
+        if dict.get_mut(key).is_some() {
+            return Err(EditError::KeyExists {
+                path: path.to_string(),
+                key: key.to_string(),
+            });
+        }
+        dict.push(key, new);
+        Ok(())
+    }
+
+    /// Remove the dict entry or list item at `path`, together with its comments.
+    pub fn remove(&mut self, path: &str) -> Result<(), EditError> {
+        let (parent, last) = self.parent_mut(path)?;
+        let removed = match (&mut parent.kind, last) {
+            (ValueKind::Dict(dict), RefIndex::Str(name)) => dict.remove(&name),
+            (ValueKind::List(list), RefIndex::Int(i)) => list.remove(i as usize),
+            _ => false,
+        };
+        match removed {
+            true => Ok(()),
+            false => Err(EditError::NotFound(path.to_string())),
+        }
+    }
+
+    /// Add `value` at the end of the list at `path`.
+    pub fn push(&mut self, path: &str, value: impl ToSource) -> Result<(), EditError> {
+        let len = self.list_mut(path)?.items.len();
+        self.insert_item(path, len, value)
+    }
+
+    /// Insert `value` at `index` in the list at `path`, shifting later items.
+    pub fn insert_item(
+        &mut self,
+        path: &str,
+        index: usize,
+        value: impl ToSource,
+    ) -> Result<(), EditError> {
+        let new = parse_source(&value.to_source())?;
+        let list = self.list_mut(path)?;
+        if index > list.items.len() {
+            return Err(EditError::NotFound(format!("{}[{}]", path, index)));
+        }
+        list.insert(index, new);
+        Ok(())
+    }
+
+    /// Add an annotation such as `@deprecated` or `@range(1, 10)` after the
+    /// annotations of the dict entry at `path`, or of the document when
+    /// `path` is `$`.
+    pub fn annotate(&mut self, path: &str, text: &str) -> Result<(), EditError> {
+        let mut new = parse_annotation(text)?;
+        if parse_path(path)?.is_empty() {
+            if self.annotations.is_empty() {
+                new.leading = std::mem::take(&mut self.value.leading);
+                self.value.leading = separator(&new.leading, "\n");
+            } else {
+                new.leading = separator(&self.value.leading, "\n");
+            }
+            self.annotations.push(new);
+            return Ok(());
+        }
+        let (parent, last) = self.parent_mut(path)?;
+        let entry = match (&mut parent.kind, last) {
+            (ValueKind::Dict(dict), RefIndex::Str(name)) => dict.get_mut(&name),
+            (ValueKind::List(_), RefIndex::Int(_)) => return Err(wrong_type(path, "a dict entry")),
+            _ => None,
+        }
+        .ok_or_else(|| EditError::NotFound(path.to_string()))?;
+        if entry.annotations.is_empty() {
+            new.leading = std::mem::take(&mut entry.key.leading);
+            entry.key.leading = separator(&new.leading, " ");
+        } else {
+            new.leading = separator(&entry.key.leading, " ");
+        }
+        entry.annotations.push(new);
+        Ok(())
+    }
+
+    fn value_mut(&mut self, path: &str, segments: &[RefIndex]) -> Result<&mut Value, EditError> {
+        let mut current = &mut self.value;
+        for segment in segments {
+            current = match (&mut current.kind, segment) {
+                (ValueKind::Dict(dict), RefIndex::Str(name)) => {
+                    dict.get_mut(name).map(|e| &mut e.value)
+                }
+                (ValueKind::List(list), RefIndex::Int(i)) => {
+                    list.items.get_mut(*i as usize).map(|item| &mut item.value)
+                }
+                _ => None,
+            }
+            .ok_or_else(|| EditError::NotFound(path.to_string()))?;
+        }
+        Ok(current)
+    }
+
+    /// The value holding the last segment of `path`, and that segment.
+    fn parent_mut(&mut self, path: &str) -> Result<(&mut Value, RefIndex), EditError> {
+        let mut segments = parse_path(path)?;
+        let last = segments
+            .pop()
+            .ok_or_else(|| EditError::InvalidPath(path.to_string()))?;
+        Ok((self.value_mut(path, &segments)?, last))
+    }
+
+    fn dict_mut(&mut self, path: &str) -> Result<&mut Dict, EditError> {
+        match &mut self.value_mut(path, &parse_path(path)?)?.kind {
+            ValueKind::Dict(dict) => Ok(dict),
+            _ => Err(wrong_type(path, "a dict")),
+        }
+    }
+
+    fn list_mut(&mut self, path: &str) -> Result<&mut List, EditError> {
+        match &mut self.value_mut(path, &parse_path(path)?)?.kind {
+            ValueKind::List(list) => Ok(list),
+            _ => Err(wrong_type(path, "a list")),
+        }
+    }
+}
+
+impl Dict {
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Entry> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.key.kind == KeyKind::Entry && entry.key.name == name)
+    }
+
+    fn push(&mut self, name: &str, mut value: Value) {
+        let quoted = match self.entries.last() {
+            Some(last) => last.key.raw.starts_with('"') || legal_id(name) != Ok(("", name)),
+            None => true,
+        };
+        let raw = if quoted {
+            name.to_source()
+        } else {
+            name.to_string()
+        };
+        value.leading = whitespace(" ");
+        let (leading, trailing) = match self.entries.last_mut() {
+            Some(last) => (
+                separator(first_leading(last), " "),
+                closing(&mut last.trailing),
+            ),
+            None => (first_line_break(&self.close), Trivia::default()),
+        };
+        let comma = self.terminate_last();
+        self.entries.push(Entry {
+            annotations: vec![],
+            key: Key {
+                leading,
+                name: name.to_string(),
+                raw,
+                span: Span::default(),
+                kind: KeyKind::Entry,
+            },
+            colon: Trivia::default(),
+            value,
+            comma,
+            trailing,
+        });
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        let Some(index) = self
+            .entries
+            .iter()
+            .position(|entry| entry.key.kind == KeyKind::Entry && entry.key.name == name)
+        else {
+            return false;
+        };
+        let mut removed = self.entries.remove(index);
+        if let (0, Some(next)) = (index, self.entries.first_mut()) {
+            take_first(first_leading_mut(next), first_leading(&removed));
+        }
+        if index == self.entries.len() {
+            if let Some(last) = self.entries.last_mut() {
+                take_last(&mut last.trailing, &mut removed.trailing);
+                if removed.comma.is_none() && last.key.kind != KeyKind::Let {
+                    last.comma = None;
+                }
+            }
+        }
+        true
+    }
+
+    /// Give the last entry a `,` so another one can follow, returns the
+    /// separator the new last entry should end with. A binding already ends
+    /// with its `;`.
+    fn terminate_last(&mut self) -> Option<Trivia> {
+        let last = self
+            .entries
+            .last_mut()
+            .filter(|last| last.key.kind != KeyKind::Let)?;
+        match last.comma {
+            Some(_) => Some(Trivia::default()),
+            None => {
+                last.comma = Some(Trivia::default());
+                None
+            }
+        }
+    }
+}
+
+impl List {
+    fn insert(&mut self, index: usize, mut value: Value) {
+        if index < self.items.len() {
+            let next = &mut self.items[index].value;
+            if index == 0 {
+                value.leading = std::mem::take(&mut next.leading);
+                next.leading = separator(&value.leading, " ");
+            } else {
+                value.leading = separator(&next.leading, " ");
+            }
+            let item = Item {
+                value,
+                comma: Some(Trivia::default()),
+                trailing: Trivia::default(),
+            };
+            self.items.insert(index, item);
+            return;
+        }
+        let mut trailing = Trivia::default();
+        let comma = match self.items.last_mut() {
+            Some(last) => {
+                value.leading = separator(&last.value.leading, " ");
+                trailing = closing(&mut last.trailing);
+                match last.comma {
+                    Some(_) => Some(Trivia::default()),
+                    None => {
+                        last.comma = Some(Trivia::default());
+                        None
+                    }
+                }
+            }
+            None => {
+                value.leading = first_line_break(&self.close);
+                None
+            }
+        };
+        self.items.push(Item {
+            value,
+            comma,
+            trailing,
+        });
+    }
+
+    fn remove(&mut self, index: usize) -> bool {
+        if index >= self.items.len() {
+            return false;
+        }
+        let mut removed = self.items.remove(index);
+        if let (0, Some(next)) = (index, self.items.first_mut()) {
+            take_first(&mut next.value.leading, &removed.value.leading);
+        }
+        if index == self.items.len() {
+            if let Some(last) = self.items.last_mut() {
+                take_last(&mut last.trailing, &mut removed.trailing);
+                if removed.comma.is_none() {
+                    last.comma = None;
+                }
+            }
+        }
+        true
+    }
+}
+
+fn parse_path(path: &str) -> Result<Vec<RefIndex>, EditError> {
+    match all_consuming(reference)(path.trim()) {
+        Ok((_, ExprToken::Ref(RefPronoun::Root(segments)))) => Ok(segments),
+        _ => Err(EditError::InvalidPath(path.to_string())),
+    }
+}
+
+fn parse_source(source: &str) -> Result<Value, EditError> {
+    parse_value(source).map_err(EditError::InvalidValue)?;
+    let options = ParseOptions::default();
+    let (parsed, _) = session::with_session(source, &options, false, || {
+        let (rest, leading) = trivia(source, true);
+        value(rest, leading).map(|(_, value)| value)
+    });
+    parsed.map_err(|e| EditError::InvalidValue(session::error(e)))
+}
+
+fn parse_annotation(text: &str) -> Result<Annotation, EditError> {
+    let text = text.trim();
+    match all_consuming(annotation)(text) {
+        Ok((_, parsed)) => Ok(Annotation {
+            leading: Trivia::default(),
+            name: parsed.name,
+            raw: text.to_string(),
+            span: Span::default(),
+        }),
+        Err(_) => Err(EditError::InvalidAnnotation(text.to_string())),
+    }
+}
+
+fn wrong_type(path: &str, expected: &'static str) -> EditError {
+    EditError::WrongType {
+        path: path.to_string(),
+        expected,
+    }
+}
+
+fn whitespace(text: &str) -> Trivia {
+    Trivia(vec![TriviaPiece::Whitespace(text.to_string())])
+}
+
+/// The trivia in front of the first token of `entry`.
+fn first_leading(entry: &Entry) -> &Trivia {
+    entry
+        .annotations
+        .first()
+        .map_or(&entry.key.leading, |annotation| &annotation.leading)
+}
+
+fn first_leading_mut(entry: &mut Entry) -> &mut Trivia {
+    match entry.annotations.first_mut() {
+        Some(annotation) => &mut annotation.leading,
+        None => &mut entry.key.leading,
+    }
+}
+
+/// Lay out the node led by `leading` as the first one of its container, in
+/// place of a removed node led by `removed`. Comments stay with the removed
+/// node.
+fn take_first(leading: &mut Trivia, removed: &Trivia) {
+    if removed.comments().next().is_none() {
+        *leading = removed.clone();
+    }
+}
+
+/// Lay out the node ending with `trailing` as the last one of its
+/// container, in place of a removed node ending with `removed`.
+fn take_last(trailing: &mut Trivia, removed: &mut Trivia) {
+    if trailing.comments().next().is_none() {
+        *trailing = closing(removed);
+    }
+}
+
+/// The blanks after the last node of a container, before its closing
+/// bracket on the same line, taken so that a node added after it ends with
+/// them instead. A trailing comment stays with its node.
+fn closing(trailing: &mut Trivia) -> Trivia {
+    if trailing.comments().next().is_some() {
+        return Trivia::default();
+    }
+    std::mem::take(trailing)
+}
+
+/// The line break and indentation a sibling of a node led by `leading` gets,
+/// or a single space on a single line.
+fn line_break(leading: &Trivia) -> Trivia {
+    let text = leading.to_string();
+    match text.rfind('\n') {
+        Some(i) => {
+            let indent = text[i + 1..]
+                .chars()
+                .take_while(|c| *c == ' ' || *c == '\t');
+            whitespace(&format!("\n{}", indent.collect::<String>()))
+        }
+        None if text.is_empty() => Trivia::default(),
+        None => whitespace(" "),
+    }
+}
+
+/// The leading trivia of the first node in a container closed after `close`,
+/// one indentation step deeper than the closing bracket.
+fn first_line_break(close: &Trivia) -> Trivia {
+    let text = close.to_string();
+    match text.rfind('\n') {
+        Some(i) => whitespace(&format!("\n    {}", &text[i + 1..])),
+        None => Trivia::default(),
+    }
+}
+
+/// Like [`line_break`], but `otherwise` where nodes are not separated at all.
+fn separator(leading: &Trivia, otherwise: &str) -> Trivia {
+    match line_break(leading) {
+        trivia if trivia.is_empty() => whitespace(otherwise),
+        trivia => trivia,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cst::parse;
+
+    use super::*;
+
+    const SRC: &str = r#"@config
+{
+    // the server
+    "server": {
+        "host": "0.0.0.0", // all interfaces
+        "port": 80,
+    },
+    /* replicas per region */
+    "regions": ["eu", "us"],
+    "version": "1.0.0"
+}
+"#;
+
+    #[test]
+    fn test_set() {
+        let mut doc = parse(SRC).unwrap();
+        doc.set("$.server.port", 8080).unwrap();
+        doc.set("$.version", "1.1.0").unwrap();
+        doc.set("$.regions[1]", "ap").unwrap();
+        doc.set("$.server.debug", true).unwrap();
+        doc.set("$.limits", Raw("{\"cpu\": 1.5}")).unwrap();
+        assert_eq!(
+            doc.to_string(),
+            r#"@config
+{
+    // the server
+    "server": {
+        "host": "0.0.0.0", // all interfaces
+        "port": 8080,
+        "debug": true,
+    },
+    /* replicas per region */
+    "regions": ["eu", "ap"],
+    "version": "1.1.0",
+    "limits": {"cpu": 1.5}
+}
+"#
+        );
+        assert_eq!(
+            doc.get("$.server.port").unwrap().kind,
+            ValueKind::Scalar("8080".to_string())
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut doc = parse(SRC).unwrap();
+        doc.remove("$.version").unwrap();
+        doc.remove("$.server.host").unwrap();
+        doc.remove("$.regions[0]").unwrap();
+        assert_eq!(
+            doc.to_string(),
+            r#"@config
+{
+    // the server
+    "server": {
+        "port": 80,
+    },
+    /* replicas per region */
+    "regions": ["us"]
+}
+"#
+        );
+        assert_eq!(
+            doc.remove("$.missing"),
+            Err(EditError::NotFound("$.missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_list_items() {
+        let mut doc = parse("{\"a\": [1], \"b\": [\n  1,\n  2,\n], \"c\": []}").unwrap();
+        doc.push("$.a", 2).unwrap();
+        doc.insert_item("$.a", 0, 0).unwrap();
+        doc.push("$.b", 3).unwrap();
+        doc.insert_item("$.b", 1, 5).unwrap();
+        doc.push("$.c", "x").unwrap();
+        assert_eq!(
+            doc.to_string(),
+            "{\"a\": [0, 1, 2], \"b\": [\n  1,\n  5,\n  2,\n  3,\n], \"c\": [\"x\"]}"
+        );
+        assert_eq!(
+            doc.insert_item("$.c", 5, 1),
+            Err(EditError::NotFound("$.c[5]".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut doc = parse("{\"a\": {}, \"b\": {\n  }, c: {x: 1}}").unwrap();
+        doc.insert("$.a", "k", 1).unwrap();
+        doc.insert("$.b", "k", 1).unwrap();
+        doc.insert("$.c", "y", 2).unwrap();
+        doc.insert("$.c", "not bare", 3).unwrap();
+        assert_eq!(
+            doc.to_string(),
+            "{\"a\": {\"k\": 1}, \"b\": {\n      \"k\": 1\n  }, c: {x: 1, y: 2, \"not bare\": 3}}"
+        );
+        assert_eq!(
+            doc.insert("$.c", "x", 5),
+            Err(EditError::KeyExists {
+                path: "$.c".to_string(),
+                key: "x".to_string()
+            })
+        );
+        assert!(doc.to_string().contains("{x: 1, y: 2"));
+
+        // bindings keep their `;`
+        let mut doc = parse("{let a = 1;}").unwrap();
+        doc.insert("$", "c", 3).unwrap();
+        doc.insert("$", "d", 4).unwrap();
+        assert_eq!(doc.to_string(), "{let a = 1; c: 3, d: 4}");
+        doc.remove("$.d").unwrap();
+        doc.remove("$.c").unwrap();
+        assert_eq!(doc.to_string(), "{let a = 1;}");
+    }
+
+    #[test]
+    fn test_single_line() {
+        let mut doc = parse("{ a: 1, b: [ 1, 2 ], c: 3 }").unwrap();
+        doc.set("$.d", 4).unwrap();
+        doc.push("$.b", 3).unwrap();
+        assert_eq!(doc.to_string(), "{ a: 1, b: [ 1, 2, 3 ], c: 3, d: 4 }");
+        doc.remove("$.a").unwrap();
+        doc.remove("$.b[0]").unwrap();
+        assert_eq!(doc.to_string(), "{ b: [ 2, 3 ], c: 3, d: 4 }");
+        doc.remove("$.d").unwrap();
+        assert_eq!(doc.to_string(), "{ b: [ 2, 3 ], c: 3 }");
+
+        let mut doc = parse("{ let x = 1; }").unwrap();
+        doc.set("$.c", 3).unwrap();
+        assert_eq!(doc.to_string(), "{ let x = 1; c: 3 }");
+    }
+
+    #[test]
+    fn test_annotate() {
+        let mut doc = parse(SRC).unwrap();
+        doc.annotate("$", "@version(2)").unwrap();
+        doc.annotate("$.version", "@deprecated").unwrap();
+        doc.annotate("$.version", "@since(\"1.0\")").unwrap();
+        let out = doc.to_string();
+        assert!(out.starts_with("@config\n@version(2)\n{"));
+        assert!(
+            out.contains("\n    @deprecated\n    @since(\"1.0\")\n    \"version\": \"1.0.0\"\n")
+        );
+        assert!(crate::root(&out).is_ok());
+
+        assert!(matches!(
+            doc.annotate("$", "deprecated"),
+            Err(EditError::InvalidAnnotation(_))
+        ));
+        assert!(matches!(
+            doc.annotate("$.regions[0]", "@x"),
+            Err(EditError::WrongType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut doc = parse(SRC).unwrap();
+        assert_eq!(
+            doc.set("server.port", 1),
+            Err(EditError::InvalidPath("server.port".to_string()))
+        );
+        assert!(matches!(
+            doc.set("$.version", Raw("{")),
+            Err(EditError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            doc.push("$.server", 1),
+            Err(EditError::WrongType { .. })
+        ));
+        assert!(matches!(doc.set("$.a.b", 1), Err(EditError::NotFound(_))));
+        assert_eq!(doc.to_string(), SRC);
+    }
+
+    #[test]
+    fn test_to_source() {
+        assert_eq!("a\"b\\\n\u{1}".to_source(), r#""a\"b\\\n\u{1}""#);
+        assert_eq!(1.5f64.to_source(), "1.5");
+        assert_eq!(2.0f64.to_source(), "2.0");
+        assert_eq!((-3i32).to_source(), "-3");
+        assert_eq!(f64::INFINITY.to_source(), "Infinity");
+        assert_eq!(f64::NEG_INFINITY.to_source(), "-Infinity");
+        assert_eq!(f64::NAN.to_source(), "NaN");
+        let mut doc = parse("{}").unwrap();
+        doc.set("$.f", f64::NEG_INFINITY).unwrap();
+        assert_eq!(doc.to_string(), "{\"f\": -Infinity}");
+        let mut doc = parse("{}").unwrap();
+        doc.set("$.s", "a\"b\\\n\u{1}").unwrap();
+        let value = crate::parse_value(&doc.get("$.s").unwrap().to_string()).unwrap();
+        assert_eq!(
+            value.node,
+            crate::EsonSegment::Str("a\"b\\\n\u{1}".to_string())
+        );
+    }
+}
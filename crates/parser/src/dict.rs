@@ -1,28 +1,73 @@
-use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 
+use indexmap::map::MutableKeys;
+use indexmap::IndexMap;
+
 use nom::branch::alt;
-use nom::character::complete::char;
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, multispace1};
 use nom::combinator::{cut, map, opt};
 use nom::error::{context, VerboseError};
 use nom::IResult;
-use nom::multi::separated_list0;
-use nom::sequence::{preceded, separated_pair, terminated, tuple};
+use nom::multi::{many0, separated_list0};
+use nom::sequence::{delimited, pair, preceded, separated_pair, terminated, tuple};
 
 use crate::{Annotation, eson, eson_literal, EsonLiteralSegment, EsonSegment, sp};
 use crate::annotation::parse_annotations;
+use crate::error::Error;
 use crate::expr::legal_id;
-use crate::string::parse_string;
+use crate::expr_token::{expr_token_set, keyword, parse_condition, parse_for, parse_spread};
+use crate::options::DuplicateKeys;
+use crate::session;
+use crate::span::{span_between, spanned, Span, Spanned};
+use crate::string::{parse_string, parse_template};
+use crate::util::{skip_to_sync, skip_until};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Key {
     pub name: String,
     pub annotation: Option<Vec<Annotation>>,
+    pub span: Span,
+    pub kind: KeyKind,
+}
+
+/// What a dict entry is. Keys of different kinds never match, even with the
+/// same name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum KeyKind {
+    /// `key: value`
+    #[default]
+    Entry,
+    /// `...expr`, its value is the [`EsonSegment::Spread`] to merge into the
+    /// dict. The name of a spread only tells it apart from the other spreads.
+    Spread,
+    /// `let name = value;`, a binding for the expressions in the dict.
+    Let,
+    /// `if cond: key: value`, its value is the [`EsonSegment::If`] holding
+    /// the entry as a one-entry dict. Named like a spread.
+    If,
+    /// `for x in xs: key: value`, its value is the [`EsonSegment::For`]
+    /// holding the entry as a one-entry dict. Named like a spread.
+    For,
+    /// `[expr]: value` or `f"...": value`, its value is the
+    /// [`EsonSegment::Computed`] holding the key and the value. Named like a
+    /// spread, the key is only known once evaluated.
+    Computed,
+}
+
+impl Key {
+    /// The key of the binding `name`, for looking it up.
+    pub fn binding(name: &str) -> Self {
+        Key {
+            kind: KeyKind::Let,
+            ..Key::from(name)
+        }
+    }
 }
 
 impl PartialEq for Key {
     fn eq(&self, other: &Self) -> bool {
-        self.name == other.name
+        self.name == other.name && self.kind == other.kind
     }
 }
 
@@ -31,6 +76,7 @@ impl Eq for Key {}
 impl Hash for Key {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.name.hash(state);
+        self.kind.hash(state);
     }
 }
 
@@ -39,6 +85,8 @@ impl From<&str> for Key {
         Key {
             name: String::from(s),
             annotation: None,
+            span: Span::default(),
+            kind: KeyKind::Entry,
         }
     }
 }
@@ -48,6 +96,8 @@ impl From<String> for Key {
         Key {
             name: s,
             annotation: None,
+            span: Span::default(),
+            kind: KeyKind::Entry,
         }
     }
 }
@@ -60,50 +110,356 @@ impl From<Key> for String {
 
 fn key(i: &str) -> IResult<&str, Key, VerboseError<&str>> {
     let (remaining, annotation) = opt(parse_annotations)(i)?;
-    let (remaining, name) =
-        preceded(sp, alt((parse_string, map(legal_id, |s| String::from(s)))))(remaining)?;
-    Ok((remaining, Key { name, annotation }))
+    let (remaining, Spanned { node: name, span }) = preceded(
+        sp,
+        spanned(alt((parse_string, map(legal_id, String::from)))),
+    )(remaining)?;
+    Ok((
+        remaining,
+        Key {
+            name,
+            annotation,
+            span,
+            kind: KeyKind::Entry,
+        },
+    ))
+}
+
+/// `...expr`, keyed by a [`KeyKind::Spread`] key.
+fn spread_entry(i: &str) -> IResult<&str, (Key, Spanned<EsonSegment>), VerboseError<&str>> {
+    let (remaining, Spanned { node, span }) =
+        preceded(sp, spanned(map(parse_spread, EsonSegment::Spread)))(i)?;
+    Ok((remaining, merged(KeyKind::Spread, node, span)))
+}
+
+/// `if cond: key: value`, keyed by a [`KeyKind::If`] key.
+pub(crate) fn if_entry(
+    i: &str,
+) -> IResult<&str, (Key, Spanned<EsonSegment>), VerboseError<&str>> {
+    let (remaining, Spanned { node: (condition, entry), span }) =
+        preceded(sp, spanned(pair(parse_condition, cut(clause_body))))(i)?;
+    let value = EsonSegment::If(condition, Box::new(entry));
+    Ok((remaining, merged(KeyKind::If, value, span)))
+}
+
+/// `for x in xs: key: value`, keyed by a [`KeyKind::For`] key.
+pub(crate) fn for_entry(
+    i: &str,
+) -> IResult<&str, (Key, Spanned<EsonSegment>), VerboseError<&str>> {
+    let (remaining, Spanned { node: ((names, iterable), entry), span }) =
+        preceded(sp, spanned(pair(parse_for, cut(clause_body))))(i)?;
+    let value = EsonSegment::For(names, iterable, Box::new(entry));
+    Ok((remaining, merged(KeyKind::For, value, span)))
+}
+
+/// What follows the clause of an `if` or `for` entry: another clause, or `:`
+/// and the entry, as a one-entry dict.
+fn clause_body(i: &str) -> IResult<&str, Spanned<EsonSegment>, VerboseError<&str>> {
+    let (remaining, Spanned { node: (key, value), span }) = context(
+        "clause_body",
+        preceded(
+            sp,
+            spanned(alt((
+                if_entry,
+                for_entry,
+                preceded(pair(char(':'), sp), key_value),
+            ))),
+        ),
+    )(i)?;
+    let mut entry = IndexMap::new();
+    insert_entry(&mut entry, key, value).map_err(|e| session::fail(i, e))?;
+    Ok((remaining, Spanned::new(EsonSegment::Dict(entry), span)))
+}
+
+/// `[expr]: value` or `f"...": value`, keyed by a [`KeyKind::Computed`] key
+/// spanning the written key.
+pub(crate) fn computed_entry(
+    i: &str,
+) -> IResult<&str, (Key, Spanned<EsonSegment>), VerboseError<&str>> {
+    let computed_key = alt((
+        map(
+            delimited(
+                pair(char('['), sp),
+                cut(expr_token_set),
+                cut(pair(sp, char(']'))),
+            ),
+            EsonSegment::Expr,
+        ),
+        map(parse_template, EsonSegment::Template),
+    ));
+    let (remaining, Spanned { node: (name, value), span }) = preceded(
+        sp,
+        spanned(separated_pair(
+            spanned(computed_key),
+            cut(tuple((sp, char(':'), sp))),
+            spanned(eson),
+        )),
+    )(i)?;
+    let key_span = name.span;
+    let (mut key, value) = merged(
+        KeyKind::Computed,
+        EsonSegment::Computed(Box::new(name), Box::new(value)),
+        span,
+    );
+    key.span = key_span;
+    Ok((remaining, (key, value)))
+}
+
+/// The key and value of an entry merged into its dict when evaluated.
+fn merged(kind: KeyKind, value: EsonSegment, span: Span) -> (Key, Spanned<EsonSegment>) {
+    let key = Key {
+        name: String::new(),
+        annotation: None,
+        span,
+        kind,
+    };
+    (key, Spanned::new(value, span))
 }
 
-pub fn parse_dict(i: &str) -> IResult<&str, HashMap<Key, EsonSegment>, VerboseError<&str>> {
-    fn key_value(i: &str) -> IResult<&str, (Key, EsonSegment), VerboseError<&str>> {
-        separated_pair(key, cut(preceded(sp, char(':'))), eson)(i)
+fn key_value(i: &str) -> IResult<&str, (Key, Spanned<EsonSegment>), VerboseError<&str>> {
+    alt((
+        spread_entry,
+        if_entry,
+        for_entry,
+        computed_entry,
+        separated_pair(key, cut(preceded(sp, char(':'))), cut(preceded(sp, spanned(eson)))),
+    ))(i)
+}
+
+/// `let name = value;`, keyed by a [`KeyKind::Let`] key.
+fn binding(i: &str) -> IResult<&str, (Key, Spanned<EsonSegment>), VerboseError<&str>> {
+    // `let` only starts a binding when a name follows, `let: 1` is an entry
+    let (name_at, _) = tuple((sp, tag("let"), multispace1))(i)?;
+    let (remaining, Spanned { node: name, span }) = spanned(legal_id)(name_at)?;
+    if ["self", "super", "true", "false", "null", "let", "if", "for", "in", "fn"].contains(&name) {
+        let error = session::error_at(name_at, "a binding name", vec!["binding"]);
+        return Err(session::fail(name_at, error));
     }
+    let (remaining, value) = context(
+        "binding",
+        cut(delimited(
+            pair(sp, char('=')),
+            preceded(sp, spanned(alt((function, eson)))),
+            pair(sp, char(';')),
+        )),
+    )(remaining)?;
+    let key = Key {
+        name: name.to_string(),
+        annotation: None,
+        span,
+        kind: KeyKind::Let,
+    };
+    Ok((remaining, (key, value)))
+}
+
+/// `fn(a, b) => value`, only ever bound by `let`.
+pub(crate) fn function(i: &str) -> IResult<&str, EsonSegment, VerboseError<&str>> {
+    let param = |i| map(legal_id, String::from)(i);
     context(
+        "function",
+        map(
+            preceded(
+                tuple((keyword("fn"), sp, char('('))),
+                cut(pair(
+                    delimited(
+                        sp,
+                        separated_list0(tuple((sp, char(','), sp)), param),
+                        tuple((sp, opt(char(',')), sp, char(')'))),
+                    ),
+                    preceded(tuple((sp, tag("=>"), sp)), spanned(eson)),
+                )),
+            ),
+            |(params, body)| EsonSegment::Fn(params, Box::new(body)),
+        ),
+    )(i)
+}
+
+/// The `let` block written before the root value.
+pub(crate) fn parse_bindings(
+    i: &str,
+) -> IResult<&str, IndexMap<Key, Spanned<EsonSegment>>, VerboseError<&str>> {
+    let (remaining, bindings) = many0(binding)(i)?;
+    let mut map = IndexMap::new();
+    for (k, v) in bindings {
+        insert_entry(&mut map, k, v).map_err(|e| session::fail(i, e))?;
+    }
+    Ok((remaining, map))
+}
+
+/// A `let` block in recovery mode: a broken binding is reported and skipped
+/// up to its `;`, or up to the next `}` or `]`, or past the next `,` where
+/// the entries start.
+pub(crate) fn recover_bindings<'a>(
+    mut input: &'a str,
+    map: &mut IndexMap<Key, Spanned<EsonSegment>>,
+) -> &'a str {
+    loop {
+        match binding(input) {
+            Ok((remaining, (key, value))) => {
+                if let Err(e) = insert_entry(map, key, value) {
+                    session::report(e);
+                }
+                input = remaining;
+            }
+            Err(nom::Err::Failure(e)) => {
+                session::report(session::error(nom::Err::Failure(e)));
+                let rest = skip_until(input, &[';', ',', '}', ']']);
+                match rest.strip_prefix(';') {
+                    Some(rest) => input = rest,
+                    None => return rest.strip_prefix(',').unwrap_or(rest),
+                }
+            }
+            Err(_) => return input,
+        }
+    }
+}
+
+/// Add an entry to `map` following the duplicate key policy of the document.
+///
+/// Under `DuplicateKeys::Error` a duplicate is returned as an error and `map`
+/// is left untouched. Spreads, clauses and computed keys are never
+/// duplicates, each is named after its position, and bindings are always
+/// unique.
+fn insert_entry<V>(map: &mut IndexMap<Key, V>, mut key: Key, value: V) -> Result<(), Error> {
+    if !matches!(key.kind, KeyKind::Entry | KeyKind::Let) {
+        key.name = map.len().to_string();
+    }
+    let Some((_, first, slot)) = map.get_full_mut2(&key) else {
+        map.insert(key, value);
+        return Ok(());
+    };
+    if key.kind == KeyKind::Let {
+        return Err(session::duplicate_binding(&key.name, key.span, first.span));
+    }
+    match session::options().duplicate_keys {
+        DuplicateKeys::Error => Err(session::duplicate_key(&key.name, key.span, first.span)),
+        DuplicateKeys::LastWins => {
+            *first = key;
+            *slot = value;
+            Ok(())
+        }
+        DuplicateKeys::FirstWins => Ok(()),
+    }
+}
+
+pub(crate) fn parse_dict(
+    i: &str,
+) -> IResult<&str, IndexMap<Key, Spanned<EsonSegment>>, VerboseError<&str>> {
+    if session::recovering() {
+        return parse_dict_recovering(i);
+    }
+    let (remaining, (bindings, entries)) = context(
         "parse_dict",
         preceded(
             context("dict_head", preceded(sp, char('{'))),
             cut(terminated(
                 context(
                     "dict_body",
-                    map(
+                    pair(
+                        many0(binding),
                         separated_list0(preceded(sp, char(',')), key_value),
-                        |tuple_vec| tuple_vec.into_iter().map(|(k, v)| (k, v)).collect(),
                     ),
                 ),
                 context("dict_tail", tuple((sp, opt(char(',')), sp, char('}')))),
             )),
         ),
-    )(i)
+    )(i)?;
+    let mut map = IndexMap::new();
+    for (k, v) in bindings.into_iter().chain(entries) {
+        insert_entry(&mut map, k, v).map_err(|e| session::fail(i, e))?;
+    }
+    Ok((remaining, map))
+}
+
+/// `parse_dict` for recovery mode: a broken entry is reported and skipped up to
+/// the next `,` or `}`, a broken value is kept as `EsonSegment::Error`.
+fn parse_dict_recovering(
+    i: &str,
+) -> IResult<&str, IndexMap<Key, Spanned<EsonSegment>>, VerboseError<&str>> {
+    let (mut input, _) = context("parse_dict", context("dict_head", preceded(sp, char('{'))))(i)?;
+    let mut map = IndexMap::new();
+    input = recover_bindings(input, &mut map);
+    loop {
+        (input, _) = sp(input)?;
+        if let Ok((remaining, _)) = char::<_, VerboseError<&str>>('}')(input) {
+            return Ok((remaining, map));
+        }
+        if input.is_empty() || input.starts_with(']') {
+            session::report(session::error_at(input, "'}'", vec!["parse_dict", "dict_tail"]));
+            return Ok((input, map));
+        }
+
+        let merged = alt((spread_entry, if_entry, for_entry, computed_entry));
+        input = match context("dict_body", merged)(input) {
+            Ok((remaining, (key, value))) => {
+                if let Err(e) = insert_entry(&mut map, key, value) {
+                    session::report(e);
+                }
+                remaining
+            }
+            Err(nom::Err::Error(_)) => match context("dict_body", key)(input) {
+                Ok((remaining, key)) => {
+                    let (remaining, _) = sp(remaining)?;
+                    match preceded(
+                        context("dict_body", preceded(char(':'), sp)),
+                        spanned(eson),
+                    )(remaining)
+                    {
+                        Ok((remaining, value)) => {
+                            if let Err(e) = insert_entry(&mut map, key, value) {
+                                session::report(e);
+                            }
+                            remaining
+                        }
+                        Err(e) => {
+                            session::report(session::error(e));
+                            let (value_start, _) = opt(preceded(char(':'), sp))(remaining)?;
+                            let remaining = skip_to_sync(value_start);
+                            let span = span_between(value_start, remaining);
+                            let value = Spanned::new(EsonSegment::Error, span);
+                            if let Err(e) = insert_entry(&mut map, key, value) {
+                                session::report(e);
+                            }
+                            remaining
+                        }
+                    }
+                }
+                Err(e) => {
+                    session::report(session::error(e));
+                    skip_to_sync(input)
+                }
+            },
+            Err(e) => {
+                session::report(session::error(e));
+                skip_to_sync(input)
+            }
+        };
+
+        (input, _) = sp(input)?;
+        if let Ok((remaining, _)) = char::<_, VerboseError<&str>>(',')(input) {
+            input = remaining;
+        } else if !(input.is_empty() || input.starts_with(['}', ']'])) {
+            // most likely a forgotten comma, carry on with the next entry
+            let context = vec!["parse_dict", "dict_tail"];
+            session::report(session::error_at(input, "',' or '}'", context));
+        }
+    }
 }
 
-pub fn parse_literal_dict(
+pub(crate) fn parse_literal_dict(
     i: &str,
-) -> IResult<&str, HashMap<Key, EsonLiteralSegment>, VerboseError<&str>> {
+) -> IResult<&str, IndexMap<Key, EsonLiteralSegment>, VerboseError<&str>> {
     fn key_literal_value(i: &str) -> IResult<&str, (Key, EsonLiteralSegment), VerboseError<&str>> {
-        separated_pair(key, cut(preceded(sp, char(':'))), eson_literal)(i)
+        separated_pair(key, cut(preceded(sp, char(':'))), cut(eson_literal))(i)
     }
-    context(
+    let (remaining, entries) = context(
         "parse_dict_literal",
         preceded(
             context("dict_literal_head", preceded(sp, char('{'))),
             cut(terminated(
                 context(
                     "dict_literal_body",
-                    map(
-                        separated_list0(preceded(sp, char(',')), key_literal_value),
-                        |tuple_vec| tuple_vec.into_iter().map(|(k, v)| (k, v)).collect(),
-                    ),
+                    separated_list0(preceded(sp, char(',')), key_literal_value),
                 ),
                 context(
                     "dict_literal_tail",
@@ -111,7 +467,12 @@ pub fn parse_literal_dict(
                 ),
             )),
         ),
-    )(i)
+    )(i)?;
+    let mut map = IndexMap::new();
+    for (k, v) in entries {
+        insert_entry(&mut map, k, v).map_err(|e| session::fail(i, e))?;
+    }
+    Ok((remaining, map))
 }
 
 #[cfg(test)]
@@ -127,6 +488,8 @@ mod tests {
                 Key {
                     name: String::from("foo"),
                     annotation: None,
+                    span: Span::default(),
+                    kind: KeyKind::Entry,
                 }
             ))
         );
@@ -139,7 +502,10 @@ mod tests {
                     annotation: Some(vec![Annotation {
                         name: String::from("bar"),
                         value: None,
+                        span: Span::default(),
                     }]),
+                    span: Span::default(),
+                    kind: KeyKind::Entry,
                 }
             ))
         );
@@ -152,7 +518,10 @@ mod tests {
                     annotation: Some(vec![Annotation {
                         name: String::from("bar"),
                         value: Some(vec![]),
+                        span: Span::default(),
                     }]),
+                    span: Span::default(),
+                    kind: KeyKind::Entry,
                 }
             ))
         );
@@ -165,7 +534,10 @@ mod tests {
                     annotation: Some(vec![Annotation {
                         name: String::from("bar"),
                         value: Some(vec![EsonLiteralSegment::Int(1)]),
+                        span: Span::default(),
                     }]),
+                    span: Span::default(),
+                    kind: KeyKind::Entry,
                 }
             ))
         );
@@ -178,7 +550,10 @@ mod tests {
                     annotation: Some(vec![Annotation {
                         name: String::from("bar"),
                         value: Some(vec![EsonLiteralSegment::Int(1), EsonLiteralSegment::Int(2)]),
+                        span: Span::default(),
                     }]),
+                    span: Span::default(),
+                    kind: KeyKind::Entry,
                 }
             ))
         );
@@ -195,7 +570,10 @@ mod tests {
                             EsonLiteralSegment::Int(2),
                             EsonLiteralSegment::Int(3),
                         ]),
+                        span: Span::default(),
                     }]),
+                    span: Span::default(),
+                    kind: KeyKind::Entry,
                 }
             ))
         );
@@ -244,7 +622,7 @@ mod tests {
 
     #[test]
     fn test_parse_dict() {
-        assert_eq!(parse_dict("{}"), Ok(("", HashMap::new())));
+        assert_eq!(parse_dict("{}"), Ok(("", IndexMap::new())));
         assert_eq!(
             parse_dict("{foo: 1}"),
             Ok((
@@ -253,8 +631,10 @@ mod tests {
                     Key {
                         name: String::from("foo"),
                         annotation: None,
+                        span: Span::default(),
+                        kind: KeyKind::Entry,
                     },
-                    EsonSegment::Int(1)
+                    EsonSegment::Int(1).into()
                 )]
                     .into_iter()
                     .collect()
@@ -269,15 +649,19 @@ mod tests {
                         Key {
                             name: String::from("foo"),
                             annotation: None,
+                            span: Span::default(),
+                            kind: KeyKind::Entry,
                         },
-                        EsonSegment::Int(1)
+                        EsonSegment::Int(1).into()
                     ),
                     (
                         Key {
                             name: String::from("bar"),
                             annotation: None,
+                            span: Span::default(),
+                            kind: KeyKind::Entry,
                         },
-                        EsonSegment::Int(2)
+                        EsonSegment::Int(2).into()
                     ),
                 ]
                     .into_iter()
@@ -293,15 +677,19 @@ mod tests {
                         Key {
                             name: String::from("foo"),
                             annotation: None,
+                            span: Span::default(),
+                            kind: KeyKind::Entry,
                         },
-                        EsonSegment::Int(1)
+                        EsonSegment::Int(1).into()
                     ),
                     (
                         Key {
                             name: String::from("bar"),
                             annotation: None,
+                            span: Span::default(),
+                            kind: KeyKind::Entry,
                         },
-                        EsonSegment::Int(2)
+                        EsonSegment::Int(2).into()
                     ),
                 ]
                     .into_iter()
@@ -0,0 +1,372 @@
+use std::fmt::{Display, Formatter};
+
+use nom::error::{ErrorKind as NomErrorKind, VerboseError, VerboseErrorKind};
+use nom::Err;
+
+use crate::span::Span;
+
+/// A syntax error, located in the source it was raised from.
+///
+/// ```text
+/// expected ':', found '1' at line 2, column 12
+///   in root > parse_dict > dict_body
+///  2 |     "port" 1
+///    |            ^
+/// ```
+///
+/// Problems that are not plain syntax errors carry an [`ErrorKind`]:
+///
+/// ```text
+/// duplicate key "port" at line 3, column 5, first defined at line 2, column 5
+///  3 |     "port": 2,
+///    |     ^
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error(Box<ErrorImpl>);
+
+/// What went wrong, beyond the `expected`/`found` description.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The input does not match the grammar.
+    Syntax,
+    /// A key appears twice in the same dict, `first` is where it was first defined.
+    DuplicateKey { name: String, first: Span },
+    /// A name is bound twice in the same `let` block, `first` is where it was
+    /// first bound.
+    DuplicateBinding { name: String, first: Span },
+    /// A date or time literal that is well-formed but names no real moment,
+    /// like `2024-02-30T00:00:00`; the span covers the whole literal.
+    InvalidDate,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ErrorImpl {
+    kind: ErrorKind,
+    span: Span,
+    expected: String,
+    found: String,
+    context: Vec<&'static str>,
+    snippet: String,
+}
+
+impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        &self.0.kind
+    }
+
+    /// Where the offending input starts, `end` covers the token shown in `found`.
+    pub fn span(&self) -> Span {
+        self.0.span
+    }
+
+    pub fn line(&self) -> usize {
+        self.0.span.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.0.span.column
+    }
+
+    /// What the parser was looking for, e.g. `':'` or `a value`.
+    pub fn expected(&self) -> &str {
+        &self.0.expected
+    }
+
+    /// What it found instead, e.g. `'1'` or `end of input`.
+    pub fn found(&self) -> &str {
+        &self.0.found
+    }
+
+    /// The `context(...)` names active at the error, outermost first.
+    pub fn context(&self) -> &[&'static str] {
+        &self.0.context
+    }
+
+    /// The offending source line followed by a caret line.
+    pub fn snippet(&self) -> &str {
+        &self.0.snippet
+    }
+
+    /// Convert a nom error raised on `source`, `offset_of` locates the
+    /// inputs recorded in the error within `source`.
+    pub(crate) fn from_nom(
+        source: &str,
+        err: Err<VerboseError<&str>>,
+        offset_of: impl Fn(&str) -> Option<usize>,
+    ) -> Self {
+        match err {
+            Err::Error(e) | Err::Failure(e) => Error::from_verbose(source, &e, offset_of),
+            Err::Incomplete(_) => Error::at(source, source.len(), "more input".to_string(), vec![]),
+        }
+    }
+
+    fn from_verbose(
+        source: &str,
+        err: &VerboseError<&str>,
+        offset_of: impl Fn(&str) -> Option<usize>,
+    ) -> Self {
+        let (input, kind) = match err.errors.first() {
+            Some((input, kind)) => (*input, kind),
+            None => return Error::at(source, 0, "a value".to_string(), vec![]),
+        };
+        let context: Vec<&'static str> = err
+            .errors
+            .iter()
+            .rev()
+            .filter_map(|(_, kind)| match kind {
+                VerboseErrorKind::Context(ctx) => Some(*ctx),
+                _ => None,
+            })
+            .collect();
+        // a failed `alt` only reports its last branch, describe the whole choice
+        // by the context wrapping the `alt` instead
+        let choice = err
+            .errors
+            .iter()
+            .position(|(i, kind)| {
+                *kind == VerboseErrorKind::Nom(NomErrorKind::Alt) && i.len() == input.len()
+            })
+            .and_then(|alt| {
+                err.errors[alt..].iter().find_map(|(_, kind)| match kind {
+                    VerboseErrorKind::Context(ctx) => Some(describe_context(ctx)),
+                    _ => None,
+                })
+            });
+        let expected = match (kind, choice) {
+            (VerboseErrorKind::Char(_), Some(choice)) => choice.to_string(),
+            (VerboseErrorKind::Char(c), None) => format!("'{}'", c),
+            (VerboseErrorKind::Context(ctx), _) => describe_context(ctx).to_string(),
+            (VerboseErrorKind::Nom(NomErrorKind::Eof), _) => {
+                describe_kind(NomErrorKind::Eof).to_string()
+            }
+            (VerboseErrorKind::Nom(kind), _) => choice
+                .or_else(|| context.last().map(|ctx| describe_context(ctx)))
+                .unwrap_or_else(|| describe_kind(*kind))
+                .to_string(),
+        };
+        let offset = offset_of(input).unwrap_or(0);
+        Error::at(source, offset, expected, context)
+    }
+
+    pub(crate) fn at(
+        source: &str,
+        offset: usize,
+        expected: String,
+        context: Vec<&'static str>,
+    ) -> Self {
+        let rest = &source[offset..];
+        let token = next_token(rest);
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = rest.find('\n').map_or(source.len(), |i| offset + i);
+        let line = source[..offset].matches('\n').count() + 1;
+        let column = source[line_start..offset].chars().count() + 1;
+
+        let gutter = line.to_string();
+        let text = source[line_start..line_end].trim_end_matches('\r');
+        let snippet = format!(
+            "{} | {}\n{} | {}^",
+            gutter,
+            text,
+            " ".repeat(gutter.len()),
+            " ".repeat(column - 1)
+        );
+
+        Error(Box::new(ErrorImpl {
+            kind: ErrorKind::Syntax,
+            span: Span {
+                start: offset,
+                end: offset + token.len(),
+                line,
+                column,
+            },
+            expected,
+            found: if token.is_empty() {
+                "end of input".to_string()
+            } else {
+                format!("'{}'", token)
+            },
+            context,
+            snippet,
+        }))
+    }
+
+    /// A duplicate of the key `name` written at `span`, first defined at `first`.
+    pub(crate) fn duplicate_key(source: &str, span: Span, name: &str, first: Span) -> Self {
+        let mut error = Error::at(source, span.start, "a unique key".to_string(), vec![]);
+        error.0.span.end = span.end;
+        error.0.found = format!("{:?}", name);
+        error.0.kind = ErrorKind::DuplicateKey {
+            name: name.to_string(),
+            first,
+        };
+        error
+    }
+
+    /// A second binding of `name` at `span` in a `let` block, first bound at
+    /// `first`.
+    pub(crate) fn duplicate_binding(source: &str, span: Span, name: &str, first: Span) -> Self {
+        let mut error = Error::at(source, span.start, "a new name".to_string(), vec![]);
+        error.0.span.end = span.end;
+        error.0.found = format!("`{}`", name);
+        error.0.kind = ErrorKind::DuplicateBinding {
+            name: name.to_string(),
+            first,
+        };
+        error
+    }
+
+    /// The date or time literal at `span`, which names no real moment.
+    pub(crate) fn invalid_date(source: &str, span: Span) -> Self {
+        let mut error = Error::at(source, span.start, "a valid date".to_string(), vec![]);
+        error.0.span.end = span.end;
+        error.0.found = format!("'{}'", &source[span.start..span.end]);
+        error.0.kind = ErrorKind::InvalidDate;
+        error
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.0.kind {
+            ErrorKind::Syntax => write!(
+                f,
+                "expected {}, found {} at line {}, column {}",
+                self.0.expected, self.0.found, self.0.span.line, self.0.span.column
+            )?,
+            ErrorKind::DuplicateKey { name, first } => write!(
+                f,
+                "duplicate key {:?} at line {}, column {}, first defined at line {}, column {}",
+                name, self.0.span.line, self.0.span.column, first.line, first.column
+            )?,
+            ErrorKind::DuplicateBinding { name, first } => write!(
+                f,
+                "`{}` is bound twice at line {}, column {}, first bound at line {}, column {}",
+                name, self.0.span.line, self.0.span.column, first.line, first.column
+            )?,
+            ErrorKind::InvalidDate => write!(
+                f,
+                "invalid date {} at line {}, column {}",
+                self.0.found, self.0.span.line, self.0.span.column
+            )?,
+        }
+        if !self.0.context.is_empty() {
+            write!(f, "\n  in {}", self.0.context.join(" > "))?;
+        }
+        for line in self.0.snippet.lines() {
+            write!(f, "\n {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The word or symbol at the start of `rest`, used for the "found" part.
+fn next_token(rest: &str) -> &str {
+    let rest = rest.trim_end_matches(['\r', '\n']);
+    match rest.chars().next() {
+        None => "",
+        Some(c) if c.is_alphanumeric() || c == '_' => {
+            let end = rest
+                .char_indices()
+                .find(|(_, c)| !(c.is_alphanumeric() || *c == '_'))
+                .map_or(rest.len(), |(i, _)| i);
+            &rest[..end]
+        }
+        Some(c) => &rest[..c.len_utf8()],
+    }
+}
+
+fn describe_context(ctx: &str) -> &'static str {
+    match ctx {
+        "root" => "a dict or a list",
+        "dict_head" | "dict_literal_head" => "'{'",
+        "dict_body" | "dict_literal_body" => "a key",
+        "dict_tail" | "dict_literal_tail" => "',' or '}'",
+        "parse_lst" | "parse_literal_lst" => "a value, ',' or ']'",
+        "parse_expr_token_chunk" | "expr_tokens" => "an expression",
+        "block_comment" => "'*/'",
+        "fn_call" => "a function call",
+        "reference" => "a reference",
+        "parse_for" => "a loop variable",
+        "for_in" => "'in'",
+        "clause_body" => "':', 'if' or 'for'",
+        _ => "a value",
+    }
+}
+
+fn describe_kind(kind: NomErrorKind) -> &'static str {
+    match kind {
+        NomErrorKind::Eof => "end of input",
+        NomErrorKind::Digit | NomErrorKind::HexDigit | NomErrorKind::OctDigit => "a digit",
+        NomErrorKind::Alpha | NomErrorKind::AlphaNumeric => "an identifier",
+        NomErrorKind::OneOf => "an identifier",
+        NomErrorKind::TakeUntil => "a closing delimiter",
+        _ => "a value",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::root;
+
+    #[test]
+    fn test_missing_colon() {
+        let err = root("{\n    \"port\" 1\n}").unwrap_err();
+        assert_eq!(err.expected(), "':'");
+        assert_eq!(err.found(), "'1'");
+        assert_eq!((err.line(), err.column()), (2, 12));
+        assert_eq!(err.context(), vec!["root", "parse_dict", "dict_body"]);
+        assert_eq!(err.snippet(), "2 |     \"port\" 1\n  |            ^");
+        assert_eq!(
+            err.to_string(),
+            "expected ':', found '1' at line 2, column 12\n  in root > parse_dict > dict_body\n 2 |     \"port\" 1\n   |            ^"
+        );
+    }
+
+    #[test]
+    fn test_unclosed_dict() {
+        let err = root("{\"a\": 1,").unwrap_err();
+        assert_eq!(err.expected(), "'}'");
+        assert_eq!(err.found(), "end of input");
+        assert_eq!((err.line(), err.column()), (1, 9));
+    }
+
+    #[test]
+    fn test_trailing_input() {
+        let err = root("{}\n[1] x").unwrap_err();
+        assert_eq!(err.expected(), "end of input");
+        assert_eq!(err.found(), "'['");
+        assert_eq!((err.line(), err.column()), (2, 1));
+        assert_eq!(err.context(), vec!["root"]);
+    }
+
+    #[test]
+    fn test_duplicate_key() {
+        let err = root("{\n    \"port\": 1,\n    \"port\": 2,\n}").unwrap_err();
+        assert_eq!(err.found(), "\"port\"");
+        assert_eq!(
+            err.to_string(),
+            "duplicate key \"port\" at line 3, column 5, first defined at line 2, column 5\n 3 |     \"port\": 2,\n   |     ^"
+        );
+    }
+
+    #[test]
+    fn test_bad_value() {
+        let err = root("{ \"a\": tru }").unwrap_err();
+        assert_eq!((err.expected(), err.found()), ("a value", "'tru'"));
+        assert_eq!((err.line(), err.column()), (1, 8));
+        let err = root("{ \"a\": 1, \"b\": ${ foo( } }").unwrap_err();
+        assert_eq!(err.found(), "'('");
+        assert_eq!(err.column(), 22);
+    }
+
+    #[test]
+    fn test_not_a_document() {
+        let err = root("  null").unwrap_err();
+        assert_eq!(err.expected(), "a dict or a list");
+        assert_eq!(err.found(), "'null'");
+        assert_eq!((err.line(), err.column()), (1, 3));
+    }
+}
@@ -5,8 +5,11 @@ use nom::error::VerboseError;
 use nom::IResult;
 use nom::multi::many0;
 
+use crate::expr_token::chunk::ExprTokenChunk;
 use crate::expr_token::ExprToken;
+use crate::span::Spanned;
 use crate::util::Iter;
+use crate::{session, Error};
 
 // Resolve valid variable or function identifiers
 // The identifier can contain only letters (a to z, A to Z), digits (0 to 9), and underscores (_).
@@ -20,12 +23,15 @@ pub fn legal_id(input: &str) -> IResult<&str, &str, VerboseError<&str>> {
     ))(input)
 }
 
-#[derive(Debug, PartialEq)]
+/// An expression tree, see [`ExprTokenChunk::to_expr`].
+#[derive(Debug, Clone, PartialEq)]
 pub enum ExprChunk {
     Primary(ExprToken),
     PrefixOp(ExprToken, Box<ExprChunk>),
     InfixOp(ExprToken, Box<ExprChunk>, Box<ExprChunk>),
     PostfixOp(ExprToken, Box<ExprChunk>),
+    /// `condition ? then : otherwise`
+    Ternary(Box<ExprChunk>, Box<ExprChunk>, Box<ExprChunk>),
 }
 
 impl Display for ExprChunk {
@@ -35,22 +41,59 @@ impl Display for ExprChunk {
             ExprChunk::PrefixOp(token, rhs) => write!(f, "({}{})", token, rhs),
             ExprChunk::InfixOp(token, lhs, rhs) => write!(f, "({}{}{})", lhs, token, rhs),
             ExprChunk::PostfixOp(token, lhs) => write!(f, "({}{})", lhs, token),
+            ExprChunk::Ternary(condition, then, otherwise) => write!(
+                f,
+                "({}{}{}{}{})",
+                condition,
+                ExprToken::Q,
+                then,
+                ExprToken::COLON,
+                otherwise
+            ),
+        }
+    }
+}
+
+impl ExprTokenChunk {
+    /// The expression tree of these tokens, grouped by operator precedence.
+    ///
+    /// Chunks produced by the parser are checked while parsing, so this only
+    /// fails for chunks put together by hand.
+    pub fn to_expr(&self) -> Result<ExprChunk, Error> {
+        let end = self.0.last().map_or(0, |token| token.span.end);
+        let mut parser = Parser::spanned(self.0.clone(), end);
+        let expr = parser.parse(0)?;
+        match parser.tokens.take_next() {
+            Some(token) => Err(session::error_at_offset(token.span.start, "an operator")),
+            None => Ok(expr),
         }
     }
 }
 
-struct Parser(Iter<ExprToken>);
+struct Parser {
+    tokens: Iter<Spanned<ExprToken>>,
+    /// Where the tokens end, for errors about a missing operand.
+    end: usize,
+}
 
 impl Parser {
+    #[cfg(test)]
     fn new(tokens: Vec<ExprToken>) -> Self {
-        Parser(Iter::from(tokens))
+        Parser::spanned(tokens.into_iter().map(Spanned::from).collect(), 0)
     }
 
+    fn spanned(tokens: Vec<Spanned<ExprToken>>, end: usize) -> Self {
+        Parser {
+            tokens: Iter::from(tokens),
+            end,
+        }
+    }
+
+    /// The binding power of an infix operator, 0 for anything else.
     fn precedence(token: &ExprToken) -> u8 {
         match token {
-            ExprToken::Or => 50,
-            ExprToken::Val(..) => 0,
-
+            ExprToken::Pipe => 10,
+            ExprToken::Q => 20,
             ExprToken::Or => 25,
             ExprToken::And => 30,
 
@@ -59,86 +102,88 @@ impl Parser {
 
             ExprToken::Plus | ExprToken::Minus => 60,
             ExprToken::Mul | ExprToken::Div | ExprToken::Mod => 70,
-
-            ExprToken::Not => 80,
-
-            ExprToken::FnCall(..) => 90, // TODO to be check
-            ExprToken::Ref(..) => 90,
-            ExprToken::Group(..) => 90,
             _ => 0,
         }
     }
 
-    fn parse(&mut self, prec: u8) -> ExprChunk {
-        let token = self.0.take_next().unwrap();
-        let mut lhs = match token {
-            ExprToken::Val(..) | ExprToken::FnCall(..) | ExprToken::Ref(..) => {
-                ExprChunk::Primary(token)
+    /// The binding power of the prefix operators `!`, `+` and `-`.
+    const PREFIX: u8 = 80;
+
+    fn parse(&mut self, prec: u8) -> Result<ExprChunk, Error> {
+        let Some(token) = self.tokens.take_next() else {
+            return Err(session::error_at_offset(self.end, "an operand"));
+        };
+        let mut lhs = match token.node {
+            ExprToken::Val(..)
+            | ExprToken::FnCall(..)
+            | ExprToken::Ref(..)
+            | ExprToken::Var(..)
+            | ExprToken::Group(..) => ExprChunk::Primary(token.node),
+            ExprToken::Not | ExprToken::Plus | ExprToken::Minus => {
+                ExprChunk::PrefixOp(token.node, Box::new(self.parse(Self::PREFIX)?))
             }
-            ExprToken::Group(..) => ExprChunk::Primary(token),
-            ExprToken::Not => ExprChunk::PrefixOp(
-                token,
-                Box::new(self.parse(Self::precedence(&ExprToken::Not))),
-            ),
-            ExprToken::Plus => ExprChunk::PrefixOp(
-                token,
-                Box::new(self.parse(Self::precedence(&ExprToken::Plus))),
-            ),
-            ExprToken::Minus => ExprChunk::PrefixOp(
-                token,
-                Box::new(self.parse(Self::precedence(&ExprToken::Minus))),
-            ),
-            _ => panic!("Unexpected prefix token {:?}", &token),
+            _ => return Err(session::error_at_offset(token.span.start, "an operand")),
         };
-        let mut precedence_r = self.0.peek().map_or(0, Self::precedence);
+        let mut precedence_r = self.tokens.peek().map_or(0, |t| Self::precedence(&t.node));
 
+        // operators of equal precedence group to the left
         while prec < precedence_r {
-            let token = self.0.take_next().unwrap();
-            lhs = match token {
-                ExprToken::Or => ExprChunk::InfixOp(
-                    token,
-                    Box::new(lhs),
-                    Box::new(self.parse(Self::precedence(&ExprToken::Or))),
-                ),
-                ExprToken::And => ExprChunk::InfixOp(
-                    token,
-                    Box::new(lhs),
-                    Box::new(self.parse(Self::precedence(&ExprToken::And))),
-                ),
-                ExprToken::Eq | ExprToken::Ne => ExprChunk::InfixOp(
-                    token,
-                    Box::new(lhs),
-                    Box::new(self.parse(Self::precedence(&ExprToken::Eq))),
-                ),
-                ExprToken::Lt | ExprToken::Gt | ExprToken::Le | ExprToken::Ge => {
-                    ExprChunk::InfixOp(
-                        token,
-                        Box::new(lhs),
-                        Box::new(self.parse(Self::precedence(&ExprToken::Lt))),
-                    )
+            let token = self.tokens.take_next().unwrap();
+            lhs = match token.node {
+                ExprToken::Q => self.ternary(lhs)?,
+                ExprToken::Pipe => self.pipe(lhs)?,
+                _ => {
+                    let rhs = self.parse(precedence_r)?;
+                    ExprChunk::InfixOp(token.node, Box::new(lhs), Box::new(rhs))
                 }
-                ExprToken::Plus | ExprToken::Minus => ExprChunk::InfixOp(
-                    token,
-                    Box::new(lhs),
-                    Box::new(self.parse(Self::precedence(&ExprToken::Plus))),
-                ),
-                ExprToken::Mul | ExprToken::Div | ExprToken::Mod => ExprChunk::InfixOp(
-                    token,
-                    Box::new(lhs),
-                    Box::new(self.parse(Self::precedence(&ExprToken::Mul))),
-                ),
-                ExprToken::Not => ExprChunk::PrefixOp(
-                    token,
-                    Box::new(self.parse(Self::precedence(&ExprToken::Not))),
-                ),
-                ExprToken::FnCall(..) => ExprChunk::PostfixOp(token, Box::new(lhs)),
-                ExprToken::Ref(..) => ExprChunk::PostfixOp(token, Box::new(lhs)),
-                ExprToken::Group(..) => ExprChunk::PostfixOp(token, Box::new(lhs)),
-                _ => panic!("Unexpected infix or postfix token {:?}", token),
             };
-            precedence_r = self.0.peek().map_or(0, Self::precedence);
+            precedence_r = self.tokens.peek().map_or(0, |t| Self::precedence(&t.node));
         }
-        lhs
+        Ok(lhs)
+    }
+
+    /// The rest of `lhs | f(a, b)` or `lhs | f` after the `|`, the function
+    /// it calls with `lhs` in front of its arguments.
+    fn pipe(&mut self, lhs: ExprChunk) -> Result<ExprChunk, Error> {
+        let function = match self.tokens.take_next() {
+            Some(token) if matches!(token.node, ExprToken::FnCall(..) | ExprToken::Var(..)) => {
+                token.node
+            }
+            Some(token) => return Err(session::error_at_offset(token.span.start, "a function")),
+            None => return Err(session::error_at_offset(self.end, "a function")),
+        };
+        // `|` binds the loosest, so `a | f + 1` would add to the function
+        if let Some(token) = self.tokens.peek() {
+            if Self::precedence(&token.node) > Self::precedence(&ExprToken::Pipe) {
+                return Err(session::error_at_offset(
+                    token.span.start,
+                    "'|' or the end of the expression",
+                ));
+            }
+        }
+        Ok(ExprChunk::InfixOp(
+            ExprToken::Pipe,
+            Box::new(lhs),
+            Box::new(ExprChunk::Primary(function)),
+        ))
+    }
+
+    /// The rest of `condition ? then : otherwise` after the `?`. The
+    /// `otherwise` branch takes in a further `?`, so conditionals group to the
+    /// right.
+    fn ternary(&mut self, condition: ExprChunk) -> Result<ExprChunk, Error> {
+        let then = self.parse(0)?;
+        match self.tokens.take_next() {
+            Some(token) if token.node == ExprToken::COLON => {}
+            Some(token) => return Err(session::error_at_offset(token.span.start, "':'")),
+            None => return Err(session::error_at_offset(self.end, "':'")),
+        }
+        let otherwise = self.parse(Self::precedence(&ExprToken::Q) - 1)?;
+        Ok(ExprChunk::Ternary(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
     }
 }
 
@@ -166,7 +211,7 @@ mod tests {
                 );
 
                 let mut parser = crate::expr::Parser::new(chunk.into());
-                let chunk = parser.parse(0);
+                let chunk = parser.parse(0).unwrap();
                 assert_eq!(
                     chunk,
                     ExprChunk::InfixOp(
@@ -207,7 +252,7 @@ mod tests {
                 );
 
                 let mut parser = crate::expr::Parser::new(chunk.into());
-                let chunk = parser.parse(0);
+                let chunk = parser.parse(0).unwrap();
                 assert_eq!(
                     chunk,
                     ExprChunk::InfixOp(
@@ -230,4 +275,95 @@ mod tests {
             _ => todo!(),
         }
     }
+
+    #[test]
+    fn test_to_expr() {
+        let (_, expr) = eson("${ !a || b && -c }").unwrap();
+        let EsonSegment::Expr(chunk) = expr else {
+            panic!("expected an expression");
+        };
+        assert_eq!(chunk.to_expr().unwrap().to_string(), "((NotVar(a))Or(Var(b)And(MinusVar(c))))");
+        let (_, expr) = eson("${ 1 - 2 - 3 }").unwrap();
+        let EsonSegment::Expr(chunk) = expr else {
+            panic!("expected an expression");
+        };
+        assert_eq!(
+            chunk.to_expr().unwrap().to_string(),
+            "((Val(Int(1))MinusVal(Int(2)))MinusVal(Int(3)))"
+        );
+    }
+
+    #[test]
+    fn test_pipe() {
+        let (_, expr) = eson("${ a ? b : c + 1 | lower | upper }").unwrap();
+        let EsonSegment::Expr(chunk) = expr else {
+            panic!("expected an expression");
+        };
+        assert_eq!(
+            chunk.to_expr().unwrap().to_string(),
+            "(((Var(a)QVar(b)COLON(Var(c)PlusVal(Int(1))))PipeVar(lower))PipeVar(upper))"
+        );
+    }
+
+    #[test]
+    fn test_ternary() {
+        let expr = |src: &str| {
+            let (_, expr) = eson(src).unwrap();
+            let EsonSegment::Expr(chunk) = expr else {
+                panic!("expected an expression");
+            };
+            chunk.to_expr().unwrap().to_string()
+        };
+        assert_eq!(
+            expr("${ a || b ? 1 : 2 + 3 }"),
+            "((Var(a)OrVar(b))QVal(Int(1))COLON(Val(Int(2))PlusVal(Int(3))))"
+        );
+        // conditionals group to the right, in either branch
+        assert_eq!(
+            expr("${ a ? b : c ? d : e }"),
+            "(Var(a)QVar(b)COLON(Var(c)QVar(d)COLONVar(e)))"
+        );
+        assert_eq!(
+            expr("${ a ? b ? c : d : e }"),
+            "(Var(a)Q(Var(b)QVar(c)COLONVar(d))COLONVar(e))"
+        );
+        // a `:` without a `?` is the start of the format spec
+        let (_, value) = eson(r#"f"${ a ? 1 : 2 :>4 }""#).unwrap();
+        let EsonSegment::Template(parts) = value else {
+            panic!("expected a template");
+        };
+        let crate::TemplatePart::Expr(chunk, Some(_)) = &parts[0] else {
+            panic!("expected a format spec");
+        };
+        assert_eq!(
+            chunk.node.to_expr().unwrap().to_string(),
+            "(Var(a)QVal(Int(1))COLONVal(Int(2)))"
+        );
+    }
+
+    #[test]
+    fn test_invalid_expr() {
+        let err = crate::root("{a: ${ 1 + }}").unwrap_err();
+        assert_eq!((err.expected(), err.found()), ("an operand", "'}'"));
+        assert_eq!(err.column(), 12);
+        let err = crate::root("{a: ${ x y }}").unwrap_err();
+        assert_eq!((err.expected(), err.found()), ("an operator", "'y'"));
+        let err = crate::root("{a: ${ f(1 *) }}").unwrap_err();
+        assert_eq!((err.expected(), err.found()), ("an operand", "')'"));
+        let err = crate::root("{a: ${ x | 1 }}").unwrap_err();
+        assert_eq!((err.expected(), err.found()), ("a function", "'1'"));
+        let err = crate::root("{a: ${ x | }}").unwrap_err();
+        assert_eq!((err.expected(), err.found()), ("a function", "'}'"));
+        let err = crate::root("{a: ${ x | f ? 1 : 2 }}").unwrap_err();
+        assert_eq!(
+            (err.expected(), err.found()),
+            ("'|' or the end of the expression", "'?'")
+        );
+        let err = crate::root("{a: ${ x ? 1 }}").unwrap_err();
+        assert_eq!((err.expected(), err.found()), ("':'", "'}'"));
+        let err = crate::root("{a: ${ x ? 1 2 }}").unwrap_err();
+        assert_eq!((err.expected(), err.found()), ("':'", "'2'"));
+        let err = crate::root("{a: ${ x ? : 2 }}").unwrap_err();
+        assert_eq!((err.expected(), err.found()), ("an operand", "':'"));
+    }
 }
@@ -1,45 +1,53 @@
+use std::cell::Cell;
 use std::fmt::Display;
 
 use nom::branch::alt;
 use nom::bytes::complete::tag;
-use nom::character::complete::{digit1, multispace0};
-use nom::combinator::{map, map_res};
-use nom::error::{context, VerboseError};
+use nom::character::complete::{char as ch, digit1, one_of, satisfy};
+use nom::combinator::{cut, map, map_res, not, opt, verify};
+use nom::error::{context, ErrorKind, ParseError, VerboseError};
 use nom::IResult;
 use nom::multi::{many0, many1, separated_list0};
-use nom::sequence::{delimited, pair, separated_pair};
+use nom::sequence::{delimited, pair, preceded, separated_pair, terminated};
 
-use crate::{eson, EsonSegment};
+use crate::{eson, sp, EsonSegment};
 use crate::expr::legal_id;
 use crate::expr_token::chunk::ExprTokenChunk;
-use crate::string::parse_literal_string;
+use crate::format_spec::{parse_format_spec, FormatSpec};
+use crate::span::spanned;
+use crate::session;
+use crate::string::parse_string;
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum RefIndex {
     Int(i16),
     Str(String),
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum RefPronoun {
     Curr(Vec<RefIndex>),
     Super(Vec<RefIndex>),
     Root(Vec<RefIndex>), // $
+    Var(String, Vec<RefIndex>), // r.name, a variable with at least one index
 }
 
 pub(crate) mod chunk {
     use std::fmt::Display;
 
     use crate::expr_token::ExprToken;
+    use crate::span::Spanned;
 
-    #[derive(Debug, PartialEq)]
-    pub(crate) struct ExprTokenChunk(Vec<ExprToken>);
+    /// The tokens of an expression in source order, see
+    /// [`ExprTokenChunk::to_expr`] for the tree.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ExprTokenChunk(pub Vec<Spanned<ExprToken>>);
 
     impl Display for ExprTokenChunk {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             let mut s = String::new();
             for token in &self.0 {
-                s.push_str(&format!("{}", token));
+                s.push_str(&format!("{}", token.node));
             }
             write!(f, "{}", s)
         }
@@ -47,20 +55,26 @@ pub(crate) mod chunk {
 
     impl From<Vec<ExprToken>> for ExprTokenChunk {
         fn from(tokens: Vec<ExprToken>) -> Self {
+            ExprTokenChunk(tokens.into_iter().map(Spanned::from).collect())
+        }
+    }
+
+    impl From<Vec<Spanned<ExprToken>>> for ExprTokenChunk {
+        fn from(tokens: Vec<Spanned<ExprToken>>) -> Self {
             ExprTokenChunk(tokens)
         }
     }
 
     impl From<ExprTokenChunk> for Vec<ExprToken> {
         fn from(chunk: ExprTokenChunk) -> Self {
-            chunk.0
+            chunk.0.into_iter().map(Spanned::into_inner).collect()
         }
     }
 }
 
 
-#[derive(Debug, PartialEq)]
-pub(crate) enum ExprToken {
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprToken {
     None,
     Group(ExprTokenChunk),
     Val(EsonSegment),
@@ -122,6 +136,9 @@ impl Display for ExprToken {
             ExprToken::Ref(RefPronoun::Root(elements)) => {
                 write!(f, "Ref(Root({:?}))", elements)
             }
+            ExprToken::Ref(RefPronoun::Var(name, elements)) => {
+                write!(f, "Ref(Var({}, {:?}))", name, elements)
+            }
             ExprToken::Pipe => write!(f, "Pipe"),
             ExprToken::Eq => write!(f, "Eq"),
             ExprToken::Ne => write!(f, "Ne"),
@@ -145,21 +162,50 @@ impl Display for ExprToken {
 }
 
 pub(crate) fn expr_token_set(input: &str) -> IResult<&str, ExprTokenChunk, VerboseError<&str>> {
-    context(
+    let open = Cell::new(0);
+    let (remaining, chunk) = context(
         "expr_tokens",
         map(
-            many1(alt((fn_call, reference, value, var, operator))),
-            |tokens| ExprTokenChunk::from(tokens),
+            many1(spanned(alt((
+                fn_call,
+                reference,
+                value,
+                var,
+                operator,
+                |i| conditional(i, &open),
+            )))),
+            ExprTokenChunk::from,
         ),
-    )(input)
+    )(input)?;
+    // reject token sequences that do not form an expression, like `1 +`
+    match chunk.to_expr() {
+        Ok(_) => Ok((remaining, chunk)),
+        Err(e) => Err(session::fail(input, e)),
+    }
 }
 
+/// Words that end an expression instead of being variables, so `if` and
+/// `for` clauses can follow one.
+const KEYWORDS: [&str; 3] = ["if", "for", "in"];
+
+// a, or r.name => Token::Ref
 fn var(input: &str) -> IResult<&str, ExprToken, VerboseError<&str>> {
     context(
         "var",
-        map(delimited(multispace0, legal_id, multispace0), |id: &str| {
-            ExprToken::Var(id.to_string())
-        }),
+        map(
+            delimited(
+                sp,
+                pair(
+                    verify(legal_id, |id: &str| !KEYWORDS.contains(&id)),
+                    many0(delimited(sp, ref_element, sp)),
+                ),
+                sp,
+            ),
+            |(id, elements)| match elements.is_empty() {
+                true => ExprToken::Var(id.to_string()),
+                false => ExprToken::Ref(RefPronoun::Var(id.to_string(), elements)),
+            },
+        ),
     )(input)
 }
 
@@ -169,14 +215,14 @@ fn fn_call(input: &str) -> IResult<&str, ExprToken, VerboseError<&str>> {
         map(
             separated_pair(
                 legal_id,
-                delimited(multispace0, tag("("), multispace0),
+                delimited(sp, tag("("), sp),
                 delimited(
-                    multispace0,
+                    sp,
                     separated_list0(
-                        delimited(multispace0, tag(","), multispace0),
+                        delimited(sp, tag(","), sp),
                         expr_token_set,
                     ),
-                    delimited(multispace0, tag(")"), multispace0),
+                    delimited(sp, tag(")"), sp),
                 ),
             ),
             |(id, args)| ExprToken::FnCall(id.to_string(), args),
@@ -185,11 +231,16 @@ fn fn_call(input: &str) -> IResult<&str, ExprToken, VerboseError<&str>> {
 }
 
 fn value(input: &str) -> IResult<&str, ExprToken, VerboseError<&str>> {
+    // a sign is the `+`/`-` operator here, not part of a number literal
+    let (rest, _) = sp(input)?;
+    if rest.starts_with(['+', '-']) {
+        return Err(nom::Err::Error(VerboseError::from_error_kind(rest, ErrorKind::Char)));
+    }
     context("value", map(eson, |v| ExprToken::Val(v)))(input)
 }
 
 // reference (eg. self.ele, super["ele"], $[0]) => Token::Ref
-fn reference(input: &str) -> IResult<&str, ExprToken, VerboseError<&str>> {
+pub(crate) fn reference(input: &str) -> IResult<&str, ExprToken, VerboseError<&str>> {
     // self, super, $
     let ref_head = alt((
         map(tag("self"), |_| RefPronoun::Curr(vec![])),
@@ -197,50 +248,53 @@ fn reference(input: &str) -> IResult<&str, ExprToken, VerboseError<&str>> {
         map(tag("$"), |_| RefPronoun::Root(vec![])),
     ));
 
-    // .ele => RefIndex::Str("ele".to_string())
-    // ["ele"] => RefIndex::Str("ele".to_string())
-    // [0] => RefIndex::Int(0)
-    let ref_element = alt((
+    context(
+        "reference",
+        map(
+            pair(
+                ref_head,
+                many0(delimited(sp, ref_element, sp)),
+            ),
+            |(head, elements)| match head {
+                RefPronoun::Curr(_) => ExprToken::Ref(RefPronoun::Curr(elements)),
+                RefPronoun::Super(_) => ExprToken::Ref(RefPronoun::Super(elements)),
+                RefPronoun::Root(_) => ExprToken::Ref(RefPronoun::Root(elements)),
+                RefPronoun::Var(..) => unreachable!(),
+            },
+        ),
+    )(input)
+}
+
+// .ele => RefIndex::Str("ele".to_string())
+// ["ele"] => RefIndex::Str("ele".to_string())
+// [0] => RefIndex::Int(0)
+fn ref_element(input: &str) -> IResult<&str, RefIndex, VerboseError<&str>> {
+    alt((
         map(
             delimited(
-                delimited(multispace0, tag("."), multispace0),
+                delimited(sp, tag("."), sp),
                 legal_id,
-                multispace0,
+                sp,
             ),
             |s| RefIndex::Str(s.to_string()),
         ),
         map(
             delimited(
-                delimited(multispace0, tag("["), multispace0),
-                parse_literal_string,
-                delimited(multispace0, tag("]"), multispace0),
+                delimited(sp, tag("["), sp),
+                parse_string,
+                delimited(sp, tag("]"), sp),
             ),
             |s| RefIndex::Str(s.to_string()),
         ),
         map(
             delimited(
-                delimited(multispace0, tag("["), multispace0),
+                delimited(sp, tag("["), sp),
                 map_res(digit1, |s: &str| s.parse::<i16>()),
-                delimited(multispace0, tag("]"), multispace0),
+                delimited(sp, tag("]"), sp),
             ),
             |i| RefIndex::Int(i),
         ),
-    ));
-
-    context(
-        "reference",
-        map(
-            pair(
-                ref_head,
-                many0(delimited(multispace0, ref_element, multispace0)),
-            ),
-            |(head, elements)| match head {
-                RefPronoun::Curr(_) => ExprToken::Ref(RefPronoun::Curr(elements)),
-                RefPronoun::Super(_) => ExprToken::Ref(RefPronoun::Super(elements)),
-                RefPronoun::Root(_) => ExprToken::Ref(RefPronoun::Root(elements)),
-            },
-        ),
-    )(input)
+    ))(input)
 }
 
 fn operator(input: &str) -> IResult<&str, ExprToken, VerboseError<&str>> {
@@ -248,22 +302,23 @@ fn operator(input: &str) -> IResult<&str, ExprToken, VerboseError<&str>> {
         "symbol",
         map(
             alt((
-                delimited(multispace0, tag("=="), multispace0),
-                delimited(multispace0, tag("!="), multispace0),
-                delimited(multispace0, tag("<="), multispace0),
-                delimited(multispace0, tag(">="), multispace0),
-                delimited(multispace0, tag("&&"), multispace0),
-                delimited(multispace0, tag("||"), multispace0),
-                delimited(multispace0, tag("!"), multispace0),
-                delimited(multispace0, tag(">"), multispace0),
-                delimited(multispace0, tag("<"), multispace0),
-                delimited(multispace0, tag("+"), multispace0),
-                delimited(multispace0, tag("-"), multispace0),
-                delimited(multispace0, tag("*"), multispace0),
-                delimited(multispace0, tag("/"), multispace0),
-                delimited(multispace0, tag("%"), multispace0),
-                delimited(multispace0, tag("^"), multispace0),
-                delimited(multispace0, tag("-"), multispace0),
+                delimited(sp, tag("=="), sp),
+                delimited(sp, tag("!="), sp),
+                delimited(sp, tag("<="), sp),
+                delimited(sp, tag(">="), sp),
+                delimited(sp, tag("&&"), sp),
+                delimited(sp, tag("||"), sp),
+                delimited(sp, tag("|"), sp),
+                delimited(sp, tag("!"), sp),
+                delimited(sp, tag(">"), sp),
+                delimited(sp, tag("<"), sp),
+                delimited(sp, tag("+"), sp),
+                delimited(sp, tag("-"), sp),
+                delimited(sp, tag("*"), sp),
+                delimited(sp, tag("/"), sp),
+                delimited(sp, tag("%"), sp),
+                delimited(sp, tag("^"), sp),
+                delimited(sp, tag("-"), sp),
             )),
             |op| match op {
                 "==" => ExprToken::Eq,
@@ -272,6 +327,7 @@ fn operator(input: &str) -> IResult<&str, ExprToken, VerboseError<&str>> {
                 ">=" => ExprToken::Ge,
                 "&&" => ExprToken::And,
                 "||" => ExprToken::Or,
+                "|" => ExprToken::Pipe,
                 "!" => ExprToken::Not,
                 ">" => ExprToken::Gt,
                 "<" => ExprToken::Lt,
@@ -291,9 +347,88 @@ pub(crate) fn parse_expr_token_chunk(input: &str) -> IResult<&str, ExprTokenChun
     context(
         "parse_expr_token_chunk",
         delimited(
-            pair(tag("${"), multispace0),
+            pair(tag("${"), sp),
             expr_token_set,
-            pair(multispace0, tag("}")),
+            pair(sp, tag("}")),
+        ),
+    )(input)
+}
+
+// ...expr in a dict or list
+pub(crate) fn parse_spread(input: &str) -> IResult<&str, ExprTokenChunk, VerboseError<&str>> {
+    context("parse_spread", preceded(pair(tag("..."), sp), cut(expr_token_set)))(input)
+}
+
+// the `?` and `:` of `a ? b : c`, `open` counts the `?` still waiting for
+// their `:`. Any other `:` ends the expression, like the one in front of the
+// format spec of an interpolation.
+fn conditional<'a>(
+    input: &'a str,
+    open: &Cell<usize>,
+) -> IResult<&'a str, ExprToken, VerboseError<&'a str>> {
+    let (rest, symbol) = delimited(sp, one_of("?:"), sp)(input)?;
+    match symbol {
+        '?' => {
+            open.set(open.get() + 1);
+            Ok((rest, ExprToken::Q))
+        }
+        _ if open.get() > 0 => {
+            open.set(open.get() - 1);
+            Ok((rest, ExprToken::COLON))
+        }
+        _ => Err(nom::Err::Error(VerboseError::from_error_kind(input, ErrorKind::Char))),
+    }
+}
+
+// `if expr` in front of a list item or dict entry, `if: 1` is an entry
+pub(crate) fn parse_condition(input: &str) -> IResult<&str, ExprTokenChunk, VerboseError<&str>> {
+    context("parse_condition", preceded(pair(keyword("if"), sp), expr_token_set))(input)
+}
+
+// `for name in expr` or `for key, value in expr` in front of a list item
+pub(crate) fn parse_for(
+    input: &str,
+) -> IResult<&str, (Vec<String>, ExprTokenChunk), VerboseError<&str>> {
+    let name = |input| map(legal_id, String::from)(input);
+    context(
+        "parse_for",
+        preceded(
+            pair(keyword("for"), sp),
+            // `for: 1` is an entry, only a name commits to a `for` clause
+            pair(
+                map(
+                    pair(name, opt(preceded(delimited(sp, ch(','), sp), cut(name)))),
+                    |(first, second)| [Some(first), second].into_iter().flatten().collect(),
+                ),
+                cut(preceded(
+                    context("for_in", delimited(sp, keyword("in"), sp)),
+                    expr_token_set,
+                )),
+            ),
+        ),
+    )(input)
+}
+
+/// `word`, but not as the start of a longer identifier.
+pub(crate) fn keyword<'a>(
+    word: &'static str,
+) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str, VerboseError<&'a str>> {
+    terminated(tag(word), not(satisfy(|c| c.is_ascii_alphanumeric() || c == '_')))
+}
+
+// ${ ... } or ${ ... : spec } in a format string
+pub(crate) fn parse_interpolation(
+    input: &str,
+) -> IResult<&str, (ExprTokenChunk, Option<FormatSpec>), VerboseError<&str>> {
+    context(
+        "parse_interpolation",
+        delimited(
+            pair(tag("${"), sp),
+            pair(
+                expr_token_set,
+                opt(preceded(pair(sp, ch(':')), cut(preceded(sp, parse_format_spec)))),
+            ),
+            pair(sp, tag("}")),
         ),
     )(input)
 }
@@ -341,7 +476,7 @@ mod tests {
     fn test_separated_list() {
         fn sl(i: &str) -> IResult<&str, Vec<ExprTokenChunk>, VerboseError<&str>> {
             separated_list0(
-                delimited(multispace0, tag(","), multispace0),
+                delimited(sp, tag(","), sp),
                 expr_token_set,
             )(i)
         }
@@ -0,0 +1,254 @@
+//! Format specs, which control how an interpolation in a format string is
+//! rendered: `f"${ port:05 }"`.
+//!
+//! ```text
+//! spec      = "json" | "join(" (string | "'" text "'") ")" | standard
+//! standard  = [[fill] align] ["+"] ["0"] [width] ["." precision] [format]
+//! align     = "<" | ">" | "^"
+//! format    = "f" | "e" | "x" | "X" | "o" | "b"
+//! ```
+//!
+//! The standard spec follows Rust's and Python's: `>10` right-aligns in ten
+//! columns, `05` pads a number with zeros to five digits, `.2f` writes two
+//! decimals.
+//!
+//! The separator of `join` may be single-quoted, `f"${ xs:join(', ') }"`,
+//! since a `"` would end the format string.
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_while1};
+use nom::character::complete::char as ch;
+use nom::combinator::{map, not, peek, value};
+use nom::error::VerboseError;
+use nom::sequence::{delimited, terminated, tuple};
+use nom::IResult;
+
+use crate::session;
+use crate::sp;
+use crate::string::{parse_single_quoted, parse_string};
+
+/// How an interpolated value is rendered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatSpec {
+    /// `json`: the value as compact JSON, so strings come out quoted.
+    Json,
+    /// `join(", ")` or `join(', ')`: the items of a list, separated by the
+    /// given string.
+    Join(String),
+    /// `>10`, `05`, `.2f` and the like.
+    Standard(StandardSpec),
+}
+
+/// `[[fill] align] ["+"] ["0"] [width] ["." precision] [format]`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StandardSpec {
+    /// The character padding to `width`, a space by default.
+    pub fill: Option<char>,
+    /// Where the value goes within `width`. Numbers default to the right,
+    /// everything else to the left.
+    pub align: Option<Align>,
+    /// `+`: write a plus sign before positive numbers.
+    pub sign: bool,
+    /// `0`: pad numbers with zeros after their sign.
+    pub zero: bool,
+    pub width: Option<usize>,
+    /// Digits after the point for numbers, the number of characters kept
+    /// for anything else.
+    pub precision: Option<usize>,
+    pub format: Option<NumberFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    /// `<`
+    Left,
+    /// `>`
+    Right,
+    /// `^`
+    Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberFormat {
+    /// `f`, fixed point: `3.14`
+    Fixed,
+    /// `e`, scientific: `3.14e0`
+    Exponent,
+    /// `x`: `ff`
+    Hex,
+    /// `X`: `FF`
+    UpperHex,
+    /// `o`: `377`
+    Octal,
+    /// `b`: `11111111`
+    Binary,
+}
+
+/// A format spec, up to the `}` closing the interpolation.
+pub(crate) fn parse_format_spec(input: &str) -> IResult<&str, FormatSpec, VerboseError<&str>> {
+    let (rest, spec) = alt((
+        value(
+            FormatSpec::Json,
+            terminated(tag("json"), not(take_while1(char::is_alphanumeric))),
+        ),
+        map(
+            delimited(
+                tuple((tag("join"), sp, ch('('), sp)),
+                alt((parse_string, parse_single_quoted)),
+                tuple((sp, ch(')'))),
+            ),
+            FormatSpec::Join,
+        ),
+        map(standard, FormatSpec::Standard),
+    ))(input)?;
+    // whatever `standard` cannot use ends the spec, and that must be the `}`
+    match peek(tuple((sp, ch::<_, VerboseError<&str>>('}'))))(rest) {
+        Ok(_) => Ok((rest, spec)),
+        Err(_) => {
+            let error = session::error_at(rest, "a format spec", vec![]);
+            Err(session::fail(rest, error))
+        }
+    }
+}
+
+fn standard(input: &str) -> IResult<&str, StandardSpec, VerboseError<&str>> {
+    let align = |c| match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    };
+    let mut spec = StandardSpec::default();
+    let mut rest = input;
+
+    let mut chars = rest.chars();
+    match (chars.next(), chars.next().and_then(align)) {
+        (Some(fill), Some(a)) if fill != '}' => {
+            spec.fill = Some(fill);
+            spec.align = Some(a);
+            rest = &rest[fill.len_utf8() + 1..];
+        }
+        (Some(c), _) if align(c).is_some() => {
+            spec.align = align(c);
+            rest = &rest[1..];
+        }
+        _ => {}
+    }
+    if let Some(after) = rest.strip_prefix('+') {
+        spec.sign = true;
+        rest = after;
+    }
+    if let Some(after) = rest.strip_prefix('0') {
+        spec.zero = true;
+        rest = after;
+    }
+    let (after, width) = digits(rest);
+    spec.width = width;
+    rest = after;
+    if let Some(after) = rest.strip_prefix('.') {
+        let (after, precision) = digits(after);
+        if precision.is_none() {
+            let error = session::error_at(after, "the precision", vec![]);
+            return Err(session::fail(after, error));
+        }
+        spec.precision = precision;
+        rest = after;
+    }
+    let format = match rest.chars().next() {
+        Some('f') => Some(NumberFormat::Fixed),
+        Some('e') => Some(NumberFormat::Exponent),
+        Some('x') => Some(NumberFormat::Hex),
+        Some('X') => Some(NumberFormat::UpperHex),
+        Some('o') => Some(NumberFormat::Octal),
+        Some('b') => Some(NumberFormat::Binary),
+        _ => None,
+    };
+    if format.is_some() {
+        spec.format = format;
+        rest = &rest[1..];
+    }
+    Ok((rest, spec))
+}
+
+fn digits(input: &str) -> (&str, Option<usize>) {
+    let end = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    (&input[end..], input[..end].parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(src: &str) -> FormatSpec {
+        let (rest, spec) = parse_format_spec(src).unwrap();
+        assert_eq!(rest.trim_start(), "}", "{}", src);
+        spec
+    }
+
+    #[test]
+    fn test_standard() {
+        let standard = |s: StandardSpec| FormatSpec::Standard(s);
+        assert_eq!(
+            spec("05}"),
+            standard(StandardSpec {
+                zero: true,
+                width: Some(5),
+                ..StandardSpec::default()
+            })
+        );
+        assert_eq!(
+            spec(".2f}"),
+            standard(StandardSpec {
+                precision: Some(2),
+                format: Some(NumberFormat::Fixed),
+                ..StandardSpec::default()
+            })
+        );
+        assert_eq!(
+            spec(">10}"),
+            standard(StandardSpec {
+                align: Some(Align::Right),
+                width: Some(10),
+                ..StandardSpec::default()
+            })
+        );
+        assert_eq!(
+            spec("*^+8.1e}"),
+            standard(StandardSpec {
+                fill: Some('*'),
+                align: Some(Align::Center),
+                sign: true,
+                width: Some(8),
+                precision: Some(1),
+                format: Some(NumberFormat::Exponent),
+                ..StandardSpec::default()
+            })
+        );
+        assert_eq!(
+            spec(">}"),
+            standard(StandardSpec {
+                align: Some(Align::Right),
+                ..StandardSpec::default()
+            })
+        );
+        assert_eq!(spec("}"), standard(StandardSpec::default()));
+    }
+
+    #[test]
+    fn test_json_join() {
+        assert_eq!(spec("json }"), FormatSpec::Json);
+        assert_eq!(spec(r#"join(", ")}"#), FormatSpec::Join(", ".to_string()));
+        assert_eq!(spec(r#"join( "}" ) }"#), FormatSpec::Join("}".to_string()));
+        assert_eq!(spec("join(', ')}"), FormatSpec::Join(", ".to_string()));
+        assert_eq!(spec(r"join('\'\n')}"), FormatSpec::Join("'\n".to_string()));
+        for src in ["jsonx}", "5q}", ".f}", "join(1)}", "json"] {
+            assert!(
+                matches!(parse_format_spec(src), Err(nom::Err::Failure(_))),
+                "{}",
+                src
+            );
+        }
+    }
+}
@@ -1,63 +1,134 @@
-use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::str;
+use std::time::Duration;
+
+use indexmap::IndexMap;
 
 use nom::{
     branch::alt,
-    combinator::{map, opt},
-    error::VerboseError,
+    combinator::{all_consuming, map},
+    error::{context, VerboseError},
     IResult,
-    sequence::{delimited, preceded},
+    sequence::{preceded, terminated, tuple},
 };
 use nom::character::complete::multispace1;
 use nom::multi::many0;
 
 pub use annotation::Annotation;
+pub use bigdecimal::BigDecimal;
+pub use bindings::unused_bindings;
+pub use dict::{Key, KeyKind};
+pub use error::{Error, ErrorKind};
+pub use expr::ExprChunk;
+pub use expr_token::chunk::ExprTokenChunk;
+pub use expr_token::{ExprToken, RefIndex, RefPronoun};
+pub use format_spec::{Align, FormatSpec, NumberFormat, StandardSpec};
+pub use num_bigint::BigInt;
+pub use options::{DuplicateKeys, ParseOptions};
+pub use span::{Span, Spanned};
+pub use string::{encode_base64, TemplatePart};
+pub use temporal::Temporal;
+pub use units::{format_duration, ByteSize};
 
 use crate::annotation::parse_annotations;
 use crate::boolean::{parse_boolean, parse_literal_boolean};
 use crate::comments::comment;
-use crate::dict::{Key, parse_dict, parse_literal_dict};
+use crate::dict::{parse_bindings, parse_dict, parse_literal_dict, recover_bindings};
 use crate::expr::legal_id;
-use crate::expr_token::{chunk::ExprTokenChunk, parse_expr_token_chunk};
+use crate::expr_token::parse_expr_token_chunk;
 use crate::list::{parse_literal_lst, parse_lst};
 use crate::null::{parse_literal_null, parse_null};
 use crate::numeric::{parse_literal_number, parse_numeric};
-use crate::string::{parse_literal_string, parse_string};
+use crate::span::{span_between, spanned};
+use crate::string::{parse_bytes, parse_string, parse_template};
+use crate::temporal::parse_temporal;
 
 mod annotation;
+mod bindings;
 mod boolean;
 mod comments;
+pub mod cst;
 mod dict;
+mod error;
 mod expr;
 mod expr_token;
+mod format_spec;
 mod list;
 mod null;
 mod numeric;
+mod options;
+mod session;
+mod span;
 mod string;
+mod temporal;
+mod units;
 mod util;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum EsonSegment {
     Null,
     Str(String),
+    /// A format string such as `f"port ${ $.port }"`, interpolated when
+    /// evaluated.
+    Template(Vec<TemplatePart>),
     Boolean(bool),
     Int(i64),
+    /// An integer beyond `i64`, with [`ParseOptions::big_integers`].
+    BigInt(BigInt),
     Float(f64),
-    List(Vec<EsonSegment>),
-    Dict(HashMap<Key, EsonSegment>),
+    /// An exact decimal, with [`ParseOptions::decimals`].
+    Decimal(BigDecimal),
+    /// A duration such as `30s`, `250ms` or `1h30m`.
+    Duration(Duration),
+    /// A byte size such as `512B`, `10MiB` or `1.5GB`.
+    ByteSize(ByteSize),
+    /// A date, time or date-time such as `2024-05-01T12:00:00Z` or `d"2024-05-01"`.
+    Temporal(Temporal),
+    /// Binary data such as `b"\x00\xff"`, `b64"AP8="` or `hex"00ff"`.
+    Bytes(Vec<u8>),
+    List(Vec<Spanned<EsonSegment>>),
+    Dict(IndexMap<Key, Spanned<EsonSegment>>),
     Expr(ExprTokenChunk),
+    /// `...expr`, splicing a list into the list or merging a dict into the
+    /// dict it is written in. Only found as a list item or as the value of a
+    /// [`KeyKind::Spread`] entry.
+    Spread(ExprTokenChunk),
+    /// `if cond: item`, the item only when `cond` is true. Only found as a
+    /// list item or as the value of a [`KeyKind::If`] entry, where the item
+    /// is the entry as a one-entry dict.
+    If(ExprTokenChunk, Box<Spanned<EsonSegment>>),
+    /// `for x in xs: item`, the item once for every element of `xs`, or
+    /// `for k, v in xs` with both the key or index and the element. Only
+    /// found as a list item or as the value of a [`KeyKind::For`] entry,
+    /// where the item is the entry as a one-entry dict.
+    For(Vec<String>, ExprTokenChunk, Box<Spanned<EsonSegment>>),
+    /// `[expr]: value` or `f"...": value`, an entry whose key, an
+    /// [`EsonSegment::Expr`] or [`EsonSegment::Template`], is evaluated to a
+    /// string. Only found as the value of a [`KeyKind::Computed`] entry.
+    Computed(Box<Spanned<EsonSegment>>, Box<Spanned<EsonSegment>>),
+    /// `fn(a, b) => value`, a function of the scope it is written in, called
+    /// as `name(x, y)` with its parameters bound to the arguments. Only found
+    /// as the value of a [`KeyKind::Let`] binding.
+    Fn(Vec<String>, Box<Spanned<EsonSegment>>),
+    /// A value that failed to parse, only produced by [`root_recovering`].
+    Error,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum EsonLiteralSegment {
     Null,
     Str(String),
     Boolean(bool),
     Int(i64),
+    BigInt(BigInt),
     Float(f64),
+    Decimal(BigDecimal),
+    Duration(Duration),
+    ByteSize(ByteSize),
+    Temporal(Temporal),
+    Bytes(Vec<u8>),
     List(Vec<EsonLiteralSegment>),
-    Dict(HashMap<Key, EsonLiteralSegment>),
+    Dict(IndexMap<Key, EsonLiteralSegment>),
 }
 
 pub(crate) fn sp(input: &str) -> IResult<&str, &str, VerboseError<&str>> {
@@ -66,52 +137,167 @@ pub(crate) fn sp(input: &str) -> IResult<&str, &str, VerboseError<&str>> {
     map(many0(alt((multispace1, comment))), |_v| "")(input)
 }
 
-pub fn eson(i: &str) -> IResult<&str, EsonSegment, VerboseError<&str>> {
+pub(crate) fn eson(i: &str) -> IResult<&str, EsonSegment, VerboseError<&str>> {
     preceded(
         sp,
-        alt((
+        context("value", alt((
             map(parse_string, EsonSegment::Str),
+            map(parse_template, EsonSegment::Template),
+            map(parse_bytes, EsonSegment::Bytes),
+            map(parse_temporal, EsonSegment::Temporal),
             map(parse_numeric, |n| n),
             map(parse_boolean, |b| b),
             map(parse_null, |_| EsonSegment::Null),
             map(parse_lst, EsonSegment::List),
             map(parse_dict, EsonSegment::Dict),
             map(parse_expr_token_chunk, EsonSegment::Expr),
-        )),
+        ))),
     )(i)
 }
 
-pub fn eson_literal(i: &str) -> IResult<&str, EsonLiteralSegment, VerboseError<&str>> {
+pub(crate) fn eson_literal(i: &str) -> IResult<&str, EsonLiteralSegment, VerboseError<&str>> {
     preceded(
         sp,
         alt((
+            map(parse_temporal, EsonLiteralSegment::Temporal),
             map(parse_literal_number, |n| n),
             map(parse_literal_boolean, |b| b),
             map(parse_literal_null, |_| EsonLiteralSegment::Null),
-            map(parse_literal_string, EsonLiteralSegment::Str),
+            map(parse_string, EsonLiteralSegment::Str),
+            map(parse_bytes, EsonLiteralSegment::Bytes),
             map(parse_literal_lst, EsonLiteralSegment::List),
             map(parse_literal_dict, EsonLiteralSegment::Dict),
         )),
     )(i)
 }
 
-/// the root element of a JSON parser is either an object or an array
-pub fn root(input: &str) -> IResult<&str, EsonSegment, VerboseError<&str>> {
-    delimited(
-        sp,
-        alt((
-            // map(hash, EsonValue::Lit),
-            // map(array, EsonValue::Lit),
-            map(parse_null, |_| EsonSegment::Null),
-        )),
-        opt(sp),
-    )(input)
+/// A complete `.eson` document: the root dict or list, together with the
+/// document-level annotations and `let` bindings written before it.
+///
+/// ```eson
+/// @license("Apache-2.0")
+/// @example
+/// let name = "Eson";
+/// {
+///     "name": ${ name },
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub annotations: Vec<Annotation>,
+    /// The `let` prelude, keyed by [`KeyKind::Let`] keys.
+    pub bindings: IndexMap<Key, Spanned<EsonSegment>>,
+    pub value: Spanned<EsonSegment>,
+}
+
+/// Parse a complete `.eson` document.
+///
+/// the root element of an eson document is either an object or an array,
+/// optionally preceded by annotations and then `let` bindings. Leading and trailing whitespace and
+/// comments are skipped, anything else left over is an error.
+pub fn root(input: &str) -> Result<Document, Error> {
+    root_with(input, &ParseOptions::default())
+}
+
+/// [`root`] with non-default [`ParseOptions`].
+pub fn root_with(input: &str, options: &ParseOptions) -> Result<Document, Error> {
+    parse_complete(
+        input,
+        options,
+        "root",
+        map(
+            tuple((
+                parse_annotations,
+                parse_bindings,
+                preceded(sp, spanned(document_value)),
+            )),
+            |(annotations, bindings, value)| Document {
+                annotations,
+                bindings,
+                value,
+            },
+        ),
+    )
+}
+
+/// Parse a complete `.eson` document, carrying on past syntax errors.
+///
+/// A broken entry or list item is skipped up to the next `,`, `}` or `]`
+/// (a broken value is kept as [`EsonSegment::Error`]), so the returned tree
+/// holds everything that did parse and the errors list every problem found,
+/// in source order. The errors are empty exactly when [`root`] succeeds.
+pub fn root_recovering(input: &str) -> (Document, Vec<Error>) {
+    root_recovering_with(input, &ParseOptions::default())
+}
+
+/// [`root_recovering`] with non-default [`ParseOptions`].
+pub fn root_recovering_with(input: &str, options: &ParseOptions) -> (Document, Vec<Error>) {
+    let (document, mut errors) = session::with_session(input, options, true, || {
+        let (rest, annotations) = match context("root", parse_annotations)(input) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                session::report(session::error(e));
+                (input, vec![])
+            }
+        };
+        let mut bindings = IndexMap::new();
+        let rest = recover_bindings(rest, &mut bindings);
+        let rest = sp(rest).map_or(rest, |(rest, _)| rest);
+        let (rest, value) = match context("root", spanned(document_value))(rest) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                session::report(session::error(e));
+                let span = span_between(rest, "");
+                ("", Spanned::new(EsonSegment::Error, span))
+            }
+        };
+        let rest = sp(rest).map_or(rest, |(rest, _)| rest);
+        if !rest.is_empty() {
+            session::report(session::error_at(rest, "end of input", vec!["root"]));
+        }
+        Document {
+            annotations,
+            bindings,
+            value,
+        }
+    });
+    errors.sort_by_key(|e| e.span().start);
+    (document, errors)
+}
+
+fn document_value(i: &str) -> IResult<&str, EsonSegment, VerboseError<&str>> {
+    alt((
+        map(parse_dict, EsonSegment::Dict),
+        map(parse_lst, EsonSegment::List),
+    ))(i)
+}
+
+/// Parse a single value (which may be an expression), e.g. `42` or `${ a + 1 }`.
+pub fn parse_value(input: &str) -> Result<Spanned<EsonSegment>, Error> {
+    parse_complete(input, &ParseOptions::default(), "value", preceded(sp, spanned(eson)))
+}
+
+/// Parse a single literal value, as accepted in annotation arguments.
+pub fn parse_literal(input: &str) -> Result<EsonLiteralSegment, Error> {
+    parse_complete(input, &ParseOptions::default(), "literal", eson_literal)
+}
+
+fn parse_complete<'a, O>(
+    input: &'a str,
+    options: &ParseOptions,
+    name: &'static str,
+    parser: impl FnMut(&'a str) -> IResult<&'a str, O, VerboseError<&'a str>>,
+) -> Result<O, Error> {
+    session::with_session(input, options, false, || {
+        context(name, all_consuming(terminated(parser, sp)))(input)
+            .map(|(_, output)| output)
+            .map_err(session::error)
+    })
+    .0
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::EsonLiteralSegment::Dict;
-    use crate::string::parse_string;
 
     use super::*;
 
@@ -175,39 +361,49 @@ mod tests {
         }
         "###;
 
-        // dbg!(root(dat));
-        // dbg!(hash(dat));
-
-        let r = Dict(
-            vec![(
-                Key {
-                    name: "c".to_string(),
-                    annotation: Some(vec![Annotation {
+        let doc = root(dat).unwrap();
+        assert_eq!(
+            doc,
+            Document {
+                annotations: vec![],
+                bindings: IndexMap::new(),
+                value: EsonSegment::Dict(
+                    vec![
+                        (
+                            Key {
+                                name: "c".to_string(),
+                                annotation: Some(vec![Annotation {
+                                    name: "foo".to_string(),
+                                    value: None,
+                                    span: Span::default(),
+                                }]),
+                                span: Span::default(),
+                                kind: KeyKind::Entry,
+                            },
+                            EsonSegment::Str("hello".to_string()).into(),
+                        ),
+                        ("d".into(), EsonSegment::Str("bar".to_string()).into()),
+                    ]
+                        .into_iter()
+                        .collect(),
+                )
+                    .into(),
+            }
+        );
+        match doc.value.node {
+            EsonSegment::Dict(map) => {
+                let (key, _) = map.get_key_value(&Key::from("c")).unwrap();
+                assert_eq!(
+                    key.annotation,
+                    Some(vec![Annotation {
                         name: "foo".to_string(),
                         value: None,
-                    }]),
-                },
-                EsonLiteralSegment::Str("hello".to_string()),
-            )]
-                .into_iter()
-                .collect(),
-        );
-
-        // assert_eq!(root(dat), Ok(("", r)));
-
-        // assert_eq!(
-        //     root(dat),
-        //     Ok((
-        //         "",
-        //         JsonValue::Object(
-        //             vec![
-        //                 ("c".into(), JsonValue::Str("hello".to_string())),
-        //             ]
-        //             .into_iter()
-        //             .collect()
-        //         )
-        //     ))
-        // );
+                        span: Span::default(),
+                    }])
+                );
+            }
+            _ => unreachable!(),
+        }
     }
 
     #[test]
@@ -221,28 +417,22 @@ mod tests {
         }
         "###;
 
-        // dbg!(root(dat));
-
-        // assert_eq!(
-        //     root(dat),
-        //     Ok((
-        //         "",
-        //         JsonValue::Object(
-        //             vec![(
-        //                 Key {
-        //                     name: "c".to_string(),
-        //                     annotation: Some(vec![Annotation {
-        //                         name: "hello".to_string(),
-        //                         value: None
-        //                     }])
-        //                 },
-        //                 JsonValue::Object(HashMap::new())
-        //             )]
-        //             .into_iter()
-        //             .collect()
-        //         )
-        //     ))
-        // );
+        assert_eq!(
+            root(dat),
+            Ok(Document {
+                annotations: vec![],
+                bindings: IndexMap::new(),
+                value: EsonSegment::Dict(
+                    vec![
+                        ("c".into(), EsonSegment::Dict(IndexMap::new()).into()),
+                        ("d".into(), EsonSegment::Str("bar".to_string()).into()),
+                    ]
+                        .into_iter()
+                        .collect()
+                )
+                    .into(),
+            })
+        );
     }
 
     #[test]
@@ -266,63 +456,607 @@ mod tests {
         }
         "###;
 
-        // assert_eq!(
-        //     root(dat),
-        //     Ok((
-        //         "",
-        //         EsonLiteral::Object(
-        //             vec![
-        //                 ("a".into(), EsonLiteral::Int(42)),
-        //                 (
-        //                     "b".into(),
-        //                     EsonLiteral::Array(vec![
-        //                         EsonLiteral::Str("x".to_string()),
-        //                         EsonLiteral::Str("y".to_string()),
-        //                         EsonLiteral::Int(12),
-        //                     ])
-        //                 ),
-        //                 (
-        //                     Key {
-        //                         name: "c".to_string(),
-        //                         annotation: Some(vec![Annotation {
-        //                             name: "hello".to_string(),
-        //                             value: None,
-        //                         }]),
-        //                     },
-        //                     EsonLiteral::Object(
-        //                         vec![
-        //                             (
-        //                                 Key {
-        //                                     name: "hello".to_string(),
-        //                                     annotation: None,
-        //                                 },
-        //                                 EsonLiteral::Str("world".to_string())
-        //                             ),
-        //                             (
-        //                                 Key {
-        //                                     name: "foo".to_string(),
-        //                                     annotation: None,
-        //                                 },
-        //                                 EsonLiteral::Str("bar".to_string())
-        //                             ),
-        //                             (
-        //                                 Key {
-        //                                     name: "bar".to_string(),
-        //                                     annotation: None,
-        //                                 },
-        //                                 EsonLiteral::Str("hello TODO!".to_string())
-        //                             ),
-        //                         ]
-        //                         .into_iter()
-        //                         .collect()
-        //                     )
-        //                 ),
-        //             ]
-        //             .into_iter()
-        //             .collect()
-        //         )
-        //     ))
-        // );
+        let doc = root(dat).unwrap();
+        let EsonSegment::Dict(map) = doc.value.node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(map.len(), 3);
+        assert_eq!(map[&Key::from("a")].node, EsonSegment::Int(42));
+        assert_eq!(
+            map[&Key::from("b")].node,
+            EsonSegment::List(vec![
+                EsonSegment::Str("x".to_string()).into(),
+                EsonSegment::Str("y".to_string()).into(),
+                EsonSegment::Int(12).into(),
+            ])
+        );
+        let EsonSegment::Dict(c) = &map[&Key::from("c")].node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(c[&Key::from("hello")].node, EsonSegment::Str("world".to_string()));
+        assert_eq!(c[&Key::from("foo")].node, EsonSegment::Str("bar".to_string()));
+        assert!(c.contains_key(&Key::from("bar")));
+    }
+
+    #[test]
+    fn test_root_annotations() {
+        let dat = r###"
+        @license("Apache-2.0")
+        @example
+        {
+            // This is a comment
+            "name": "Eson",
+        }
+        "###;
+
+        assert_eq!(
+            root(dat),
+            Ok(Document {
+                annotations: vec![
+                    Annotation {
+                        name: "license".to_string(),
+                        value: Some(vec![EsonLiteralSegment::Str("Apache-2.0".to_string())]),
+                        span: Span::default(),
+                    },
+                    Annotation {
+                        name: "example".to_string(),
+                        value: None,
+                        span: Span::default(),
+                    },
+                ],
+                bindings: IndexMap::new(),
+                value: EsonSegment::Dict(
+                    vec![("name".into(), EsonSegment::Str("Eson".to_string()).into())]
+                        .into_iter()
+                        .collect()
+                )
+                    .into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_root_list() {
+        assert_eq!(
+            root("// list\n[1, 2,]\n"),
+            Ok(Document {
+                annotations: vec![],
+                bindings: IndexMap::new(),
+                value: EsonSegment::List(vec![
+                    EsonSegment::Int(1).into(),
+                    EsonSegment::Int(2).into(),
+                ])
+                    .into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_root_rejects() {
+        assert!(root("").is_err());
+        assert!(root("1").is_err());
+        assert!(root("null").is_err());
+        assert!(root("{} {}").is_err());
+        assert!(root("[1] x").is_err());
+        assert!(root("{\"a\": 1,").is_err());
+    }
+
+    #[test]
+    fn test_root_recovering() {
+        let dat = "{\n  \"a\": 1,\n  \"b\" 2,\n  \"c\": [1, 2 3, 4],\n  \"d\": true\n  \"e\": ,\n  \"f\": null,\n}";
+        let (doc, errors) = root_recovering(dat);
+
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line(), e.column(), e.expected().to_string()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (3, 7, "':'".to_string()),
+                (4, 14, "',' or ']'".to_string()),
+                (6, 3, "',' or '}'".to_string()),
+                (6, 8, "a value".to_string()),
+            ]
+        );
+
+        let EsonSegment::Dict(map) = doc.value.node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(map[&Key::from("a")].node, EsonSegment::Int(1));
+        assert_eq!(map[&Key::from("b")].node, EsonSegment::Error);
+        assert_eq!(
+            map[&Key::from("c")].node,
+            EsonSegment::List(vec![
+                EsonSegment::Int(1).into(),
+                EsonSegment::Int(2).into(),
+                EsonSegment::Int(3).into(),
+                EsonSegment::Int(4).into(),
+            ])
+        );
+        assert_eq!(map[&Key::from("d")].node, EsonSegment::Boolean(true));
+        assert_eq!(map[&Key::from("e")].node, EsonSegment::Error);
+        assert_eq!(map[&Key::from("f")].node, EsonSegment::Null);
+    }
+
+    #[test]
+    fn test_root_recovering_nested() {
+        let dat = "[{\"a\": }, {\"b\": [1, }, 2] x";
+        let (doc, errors) = root_recovering(dat);
+        let found: Vec<_> = errors.iter().map(|e| (e.column(), e.expected())).collect();
+        assert_eq!(
+            found,
+            vec![(8, "a value"), (21, "']'"), (27, "end of input")]
+        );
+        assert_eq!(
+            doc.value.node,
+            EsonSegment::List(vec![
+                EsonSegment::Dict(
+                    vec![("a".into(), EsonSegment::Error.into())]
+                        .into_iter()
+                        .collect()
+                )
+                .into(),
+                EsonSegment::Dict(
+                    vec![(
+                        "b".into(),
+                        EsonSegment::List(vec![EsonSegment::Int(1).into()]).into()
+                    )]
+                    .into_iter()
+                    .collect()
+                )
+                .into(),
+                EsonSegment::Int(2).into(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_root_recovering_unclosed() {
+        // one diagnostic for the missing bracket, not also one for a comma
+        let (_, errors) = root_recovering("[1, 2");
+        let found: Vec<_> = errors.iter().map(|e| (e.column(), e.expected())).collect();
+        assert_eq!(found, vec![(6, "']'")]);
+        let (_, errors) = root_recovering("{\"a\": 1");
+        let found: Vec<_> = errors.iter().map(|e| (e.column(), e.expected())).collect();
+        assert_eq!(found, vec![(8, "'}'")]);
+        let (_, errors) = root_recovering("[{\"a\": [1 }]");
+        let found: Vec<_> = errors.iter().map(|e| (e.column(), e.expected())).collect();
+        assert_eq!(found, vec![(11, "']'")]);
+    }
+
+    #[test]
+    fn test_root_recovering_valid() {
+        let dat = "@doc\n{\"a\": [1, 2], \"b\": {\"c\": null}}";
+        let (doc, errors) = root_recovering(dat);
+        assert!(errors.is_empty());
+        assert_eq!(doc, root(dat).unwrap());
+
+        let (doc, errors) = root_recovering("  null");
+        assert_eq!(errors[0].expected(), "a dict or a list");
+        assert_eq!(doc.value.node, EsonSegment::Error);
+    }
+
+    #[test]
+    fn test_root_key_order() {
+        let doc = root("{\"z\": 1, \"a\": {\"y\": 2, \"b\": 3}, \"m\": 4}").unwrap();
+        let EsonSegment::Dict(map) = &doc.value.node else {
+            panic!("expected a dict");
+        };
+        let keys: Vec<_> = map.keys().map(|k| k.name.as_str()).collect();
+        assert_eq!(keys, vec!["z", "a", "m"]);
+        let EsonSegment::Dict(inner) = &map[1].node else {
+            panic!("expected a dict");
+        };
+        let keys: Vec<_> = inner.keys().map(|k| k.name.as_str()).collect();
+        assert_eq!(keys, vec!["y", "b"]);
+    }
+
+    #[test]
+    fn test_root_duplicate_keys() {
+        let dat = "{\n  @a \"k\": 1,\n  \"x\": 0,\n  @b \"k\": 2,\n}";
+
+        let err = root(dat).unwrap_err();
+        let ErrorKind::DuplicateKey { name, first } = err.kind() else {
+            panic!("expected a duplicate key error");
+        };
+        assert_eq!(name, "k");
+        assert_eq!((first.line, first.column), (2, 6));
+        assert_eq!((err.line(), err.column(), err.span().len()), (4, 6, 3));
+
+        let entry = |policy| {
+            let options = ParseOptions {
+                duplicate_keys: policy,
+                ..ParseOptions::default()
+            };
+            let EsonSegment::Dict(map) = root_with(dat, &options).unwrap().value.node else {
+                panic!("expected a dict");
+            };
+            let (index, key, value) = map.get_full(&Key::from("k")).unwrap();
+            let annotation = key.annotation.as_ref().unwrap()[0].name.clone();
+            (map.len(), index, annotation, value.node == EsonSegment::Int(1))
+        };
+        assert_eq!(entry(DuplicateKeys::LastWins), (2, 0, "b".to_string(), false));
+        assert_eq!(entry(DuplicateKeys::FirstWins), (2, 0, "a".to_string(), true));
+    }
+
+    #[test]
+    fn test_root_recovering_duplicate_keys() {
+        let dat = "{\"a\": 1, \"a\": 2, @x({\"c\": 1, \"c\": 2}) \"b\": 3, \"a\": 3}";
+        let (doc, errors) = root_recovering(dat);
+        let found: Vec<_> = errors.iter().map(|e| e.column()).collect();
+        assert_eq!(found, vec![10, 30, 47]);
+        assert!(errors.iter().all(|e| matches!(e.kind(), ErrorKind::DuplicateKey { .. })));
+        let EsonSegment::Dict(map) = doc.value.node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(map[&Key::from("a")].node, EsonSegment::Int(1));
+    }
+
+    #[test]
+    fn test_root_comments() {
+        let dat = concat!(
+            "/* header */ {\r\n",
+            "  \"a\": /* inline */ 1, // one\r\n",
+            "  \"b\": ${ 1 /* two */ + 2 // three\n },\r\n",
+            "} // end",
+        );
+        let doc = root(dat).unwrap();
+        let EsonSegment::Dict(map) = &doc.value.node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(map[&Key::from("a")].node, EsonSegment::Int(1));
+        let EsonSegment::Expr(chunk) = &map[&Key::from("b")].node else {
+            panic!("expected an expression");
+        };
+        assert_eq!(chunk.to_string(), "Val(Int(1))PlusVal(Int(2))");
+
+        let err = root("{\"a\": 1 /* open\n}").unwrap_err();
+        assert_eq!((err.expected(), err.found()), ("'*/'", "end of input"));
+
+        let dat = "# settings\n{\"a\": 1, # one\n}";
+        assert!(root(dat).is_err());
+        let options = ParseOptions {
+            hash_comments: true,
+            ..ParseOptions::default()
+        };
+        assert!(root_with(dat, &options).is_ok());
+    }
+
+    #[test]
+    fn test_root_spans() {
+        let dat = "@doc\n{\n  // c\n  @tag(1)\n  \"a\": [1, ${ x + 2 }],\n}\n";
+        let doc = root(dat).unwrap();
+
+        let anno = &doc.annotations[0].span;
+        assert_eq!((anno.start, anno.end, anno.line, anno.column), (0, 4, 1, 1));
+        let value = &doc.value.span;
+        assert_eq!((value.start, value.end, value.line, value.column), (5, dat.len() - 1, 2, 1));
+
+        let EsonSegment::Dict(map) = &doc.value.node else {
+            panic!("expected a dict");
+        };
+        let (key, list) = map.iter().next().unwrap();
+        assert_eq!(&dat[key.span.start..key.span.end], "\"a\"");
+        assert_eq!((key.span.line, key.span.column), (5, 3));
+        let tag = &key.annotation.as_ref().unwrap()[0].span;
+        assert_eq!(&dat[tag.start..tag.end], "@tag(1)");
+        assert_eq!((tag.line, tag.column), (4, 3));
+        assert_eq!(&dat[list.span.start..list.span.end], "[1, ${ x + 2 }]");
+
+        let EsonSegment::List(items) = &list.node else {
+            panic!("expected a list");
+        };
+        assert_eq!((items[0].span.line, items[0].span.column), (5, 9));
+        let expr = &items[1];
+        assert_eq!(&dat[expr.span.start..expr.span.end], "${ x + 2 }");
+        let EsonSegment::Expr(chunk) = &expr.node else {
+            panic!("expected an expression");
+        };
+        let tokens: Vec<&str> = chunk
+            .0
+            .iter()
+            .map(|t| &dat[t.span.start..t.span.end])
+            .collect();
+        assert_eq!(tokens, vec!["x", "+", "2"]);
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value(" 1 ").unwrap().node, EsonSegment::Int(1));
+        let value = parse_value("// sum\n${ 1 + 2 }").unwrap();
+        assert_eq!((value.span.line, value.span.column), (2, 1));
+        assert!(matches!(value.node, EsonSegment::Expr(_)));
+
+        let err = parse_value("1 2").unwrap_err();
+        assert_eq!(err.expected(), "end of input");
+        assert_eq!(err.found(), "'2'");
+        assert_eq!(err.context(), vec!["value"]);
+    }
+
+    #[test]
+    fn test_parse_literal() {
+        assert_eq!(
+            parse_literal("[1, \"a\"]"),
+            Ok(EsonLiteralSegment::List(vec![
+                EsonLiteralSegment::Int(1),
+                EsonLiteralSegment::Str("a".to_string()),
+            ]))
+        );
+        assert!(parse_literal("${ 1 }").is_err());
+    }
+
+    #[test]
+    fn test_root_triple_string() {
+        let src = r#"{
+    query: """
+        SELECT *
+        FROM users
+        """,
+    cert: r"""+
+        -----BEGIN CERTIFICATE-----
+        MIIB
+        -----END CERTIFICATE-----
+        """,
+}"#;
+        let doc = root(src).unwrap();
+        let EsonSegment::Dict(dict) = &doc.value.node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(
+            dict[&Key::from("query")].node,
+            EsonSegment::Str("SELECT *\nFROM users".to_string())
+        );
+        assert_eq!(
+            dict[&Key::from("cert")].node,
+            EsonSegment::Str(
+                "-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n".to_string()
+            )
+        );
+
+        let err = root("{\n  a: \"\"\"\n    open\n}").unwrap_err();
+        assert_eq!(err.expected(), "a closing \"\"\"");
+        assert_eq!((err.line(), err.column()), (4, 2));
+    }
+
+    #[test]
+    fn test_root_bytes() {
+        let doc = root(r#"{ key: hex"00ff", blob: ${ b"\x01" } }"#).unwrap();
+        let EsonSegment::Dict(dict) = &doc.value.node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(dict[&Key::from("key")].node, EsonSegment::Bytes(vec![0, 255]));
+
+        let err = root("{ key: hex\"00fg\" }").unwrap_err();
+        assert_eq!(err.expected(), "a hex digit");
+        assert_eq!((err.line(), err.column()), (1, 15));
+    }
+
+    #[test]
+    fn test_root_spread() {
+        let doc = root("{ ...$.base, a: 1, ...{b: 2}, c: 3 }").unwrap();
+        let EsonSegment::Dict(dict) = &doc.value.node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(dict.len(), 4);
+        let (key, value) = dict.get_index(0).unwrap();
+        assert_eq!(key.kind, KeyKind::Spread);
+        assert_eq!((value.span.column, value.span.len()), (3, "...$.base".len()));
+        assert!(matches!(value.node, EsonSegment::Spread(_)));
+        assert_eq!(dict.get_index(2).unwrap().0.kind, KeyKind::Spread);
+        assert_eq!(dict[&Key::from("c")].node, EsonSegment::Int(3));
+        // written keys still follow the duplicate key policy
+        assert!(root("{ a: 1, ...$.b, a: 2 }").is_err());
+
+        let doc = root("[1, ... $.items, 2]").unwrap();
+        let EsonSegment::List(items) = &doc.value.node else {
+            panic!("expected a list");
+        };
+        assert!(matches!(items[1].node, EsonSegment::Spread(_)));
+
+        let err = root("{ a: 1, ... }").unwrap_err();
+        assert_eq!((err.line(), err.column()), (1, 13));
+        assert!(root("{ a: ...$.b }").is_err());
+
+        let (doc, errors) = root_recovering("{ ..., ...$.a, b: 1 }");
+        assert_eq!(errors.len(), 1);
+        let EsonSegment::Dict(dict) = &doc.value.node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(dict.len(), 2);
+    }
+
+    #[test]
+    fn test_root_conditionals() {
+        let doc =
+            root(r#"{ if: 1, if $.env == "dev": debug: true, if ok: if !no: ...$.x }"#).unwrap();
+        let EsonSegment::Dict(dict) = &doc.value.node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(dict.len(), 3);
+        assert_eq!(dict[&Key::from("if")].node, EsonSegment::Int(1));
+        let (key, value) = dict.get_index(1).unwrap();
+        assert_eq!(key.kind, KeyKind::If);
+        assert_eq!((value.span.column, value.span.len()), (10, 30));
+        let EsonSegment::If(_, entry) = &value.node else {
+            panic!("expected an if entry");
+        };
+        let EsonSegment::Dict(entry) = &entry.node else {
+            panic!("expected the entry as a dict");
+        };
+        assert_eq!(entry[&Key::from("debug")].node, EsonSegment::Boolean(true));
+        let EsonSegment::If(_, nested) = &dict.get_index(2).unwrap().1.node else {
+            panic!("expected an if entry");
+        };
+        assert!(matches!(&nested.node, EsonSegment::Dict(d) if d.len() == 1));
+
+        let doc = root("[80, if $.tls: 443, if a && b: [1]]").unwrap();
+        let EsonSegment::List(items) = &doc.value.node else {
+            panic!("expected a list");
+        };
+        let EsonSegment::If(_, item) = &items[1].node else {
+            panic!("expected an if item");
+        };
+        assert_eq!(item.node, EsonSegment::Int(443));
+        assert!(matches!(&items[2].node, EsonSegment::If(..)));
+
+        let err = root("{ if a b: 1 }").unwrap_err();
+        assert_eq!((err.column(), err.expected()), (8, "an operator"));
+        let err = root("[if a]").unwrap_err();
+        assert_eq!((err.column(), err.expected()), (6, "':', 'if' or 'for'"));
+        for src in ["{ if a: 1 }", "[if a:]", "{ if a: b }", "[if 1 +: 2]"] {
+            assert!(root(src).is_err(), "{}", src);
+        }
+
+        let (doc, errors) = root_recovering("{ if a: , b: 1, if c: d: 2 }");
+        assert_eq!(errors.len(), 1);
+        let EsonSegment::Dict(dict) = &doc.value.node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(dict.len(), 2);
+    }
+
+    #[test]
+    fn test_root_comprehensions() {
+        let doc = root("[for r in $.regions if r.enabled: { name: ${ r.name } }]").unwrap();
+        let EsonSegment::List(items) = &doc.value.node else {
+            panic!("expected a list");
+        };
+        let EsonSegment::For(names, iterable, body) = &items[0].node else {
+            panic!("expected a for item");
+        };
+        assert_eq!(names, &["r"]);
+        assert_eq!(iterable.to_string(), "Ref(Root([Str(\"regions\")]))");
+        let EsonSegment::If(condition, item) = &body.node else {
+            panic!("expected an if clause");
+        };
+        assert_eq!(condition.to_string(), "Ref(Var(r, [Str(\"enabled\")]))");
+        assert!(matches!(&item.node, EsonSegment::Dict(_)));
+
+        let doc = root("[for k, v in $.d: for x in v: ${ k }]").unwrap();
+        let EsonSegment::List(items) = &doc.value.node else {
+            panic!("expected a list");
+        };
+        let EsonSegment::For(names, _, body) = &items[0].node else {
+            panic!("expected a for item");
+        };
+        assert_eq!(names, &["k", "v"]);
+        assert!(matches!(&body.node, EsonSegment::For(..)));
+
+        let err = root("[for x $.a: 1]").unwrap_err();
+        assert_eq!((err.column(), err.expected()), (8, "'in'"));
+        let err = root("[for x in $.a]").unwrap_err();
+        assert_eq!((err.column(), err.expected()), (14, "':', 'if' or 'for'"));
+        for src in ["[for in $.a: 1]", "[for x, in $.a: 1]", "[for x in: 1]", "let for = 1; 2"] {
+            assert!(root(src).is_err(), "{}", src);
+        }
+
+        let doc = root(
+            r#"{ for: 1, for s in $.svc if s.on: [s.name]: ${ s.port }, f"${ $.a }-b": 2, [$.c]: 3 }"#,
+        )
+        .unwrap();
+        let EsonSegment::Dict(dict) = &doc.value.node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(dict.len(), 4);
+        assert_eq!(dict[&Key::from("for")].node, EsonSegment::Int(1));
+        let (key, value) = dict.get_index(1).unwrap();
+        assert_eq!(key.kind, KeyKind::For);
+        let EsonSegment::For(_, _, entry) = &value.node else {
+            panic!("expected a for entry");
+        };
+        let EsonSegment::Dict(entry) = &entry.node else {
+            panic!("expected the entry as a dict");
+        };
+        let (key, value) = entry.get_index(0).unwrap();
+        assert_eq!(key.kind, KeyKind::If);
+        assert!(matches!(&value.node, EsonSegment::If(..)));
+        let (key, value) = dict.get_index(2).unwrap();
+        assert_eq!((key.kind, key.span.column, key.span.len()), (KeyKind::Computed, 58, 13));
+        let EsonSegment::Computed(name, value) = &value.node else {
+            panic!("expected a computed entry");
+        };
+        assert!(matches!(&name.node, EsonSegment::Template(_)));
+        assert_eq!(value.node, EsonSegment::Int(2));
+        let (key, _) = dict.get_index(3).unwrap();
+        assert_eq!((key.kind, key.span.column), (KeyKind::Computed, 76));
+
+        let err = root("{ [$.a: 1 }").unwrap_err();
+        assert_eq!((err.column(), err.expected()), (7, "']'"));
+        let err = root("{ for x in $.a }").unwrap_err();
+        assert_eq!((err.column(), err.expected()), (16, "':', 'if' or 'for'"));
+        for src in ["{ []: 1 }", "{ [$.a] 1 }", "{ for x: 1 }"] {
+            assert!(root(src).is_err(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_root_bindings() {
+        let doc = root("@config\nlet port = 80; let host = \"a\";\n{ x: ${ port } }").unwrap();
+        assert_eq!(doc.annotations.len(), 1);
+        let (key, value) = doc.bindings.get_index(1).unwrap();
+        assert_eq!(key, &Key::binding("host"));
+        assert_eq!((key.span.line, key.span.column), (2, 20));
+        assert_eq!(value.node, EsonSegment::Str("a".to_string()));
+
+        let doc = root("{ let a = 1; let b = [${ a }]; let: 2, a: 3 }").unwrap();
+        let EsonSegment::Dict(dict) = &doc.value.node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(dict.len(), 4);
+        assert_eq!(dict[&Key::binding("a")].node, EsonSegment::Int(1));
+        assert_eq!(dict[&Key::from("let")].node, EsonSegment::Int(2));
+        assert_eq!(dict[&Key::from("a")].node, EsonSegment::Int(3));
+
+        let err = root("let a = 1;\nlet a = 2; {}").unwrap_err();
+        let ErrorKind::DuplicateBinding { name, first } = err.kind() else {
+            panic!("expected a duplicate binding error");
+        };
+        assert_eq!(name, "a");
+        assert_eq!((first.line, first.column), (1, 5));
+        assert_eq!((err.line(), err.column()), (2, 5));
+        for src in [
+            "{ a: 1, let b = 2; }",
+            "let self = 1; {}",
+            "let a = 1 {}",
+            "{ let a = ; }",
+        ] {
+            assert!(root(src).is_err(), "{}", src);
+        }
+
+        let (doc, errors) = root_recovering("let a = ; let b = 2; { let c = 1 d: 2, e: 3 }");
+        assert_eq!(errors.len(), 2);
+        assert_eq!(doc.bindings.len(), 1);
+        let EsonSegment::Dict(dict) = &doc.value.node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(dict.len(), 1);
+    }
+
+    #[test]
+    fn test_root_functions() {
+        let doc = root("let svc = fn(name, port) => { url: f\"${ name }:${ port }\" };\n{ let one = fn () => 1; a: ${ svc(\"a\", one()) } }").unwrap();
+        let value = &doc.bindings[&Key::binding("svc")];
+        let EsonSegment::Fn(params, body) = &value.node else {
+            panic!("expected a function");
+        };
+        assert_eq!(params, &["name", "port"]);
+        assert!(matches!(&body.node, EsonSegment::Dict(_)));
+        assert_eq!((body.span.column, body.span.len()), (29, 31));
+        let EsonSegment::Dict(dict) = &doc.value.node else {
+            panic!("expected a dict");
+        };
+        let EsonSegment::Fn(params, body) = &dict[&Key::binding("one")].node else {
+            panic!("expected a function");
+        };
+        assert!(params.is_empty());
+        assert_eq!(body.node, EsonSegment::Int(1));
+
+        let err = root("let f = fn(a) ${ a }; {}").unwrap_err();
+        assert_eq!((err.column(), err.expected()), (15, "a value"));
+        for src in ["{ f: fn(a) => 1 }", "[fn() => 1]", "let fn = 1; {}", "let f = fn(1) => 1; {}"] {
+            assert!(root(src).is_err(), "{}", src);
+        }
     }
 
     #[test]
@@ -331,7 +1065,14 @@ mod tests {
         // comment1
         {}
         "##;
-        // assert_eq!(root(json), Ok(("", EsonLiteral::Object(HashMap::new()))));
+        assert_eq!(
+            root(json),
+            Ok(Document {
+                annotations: vec![],
+                bindings: IndexMap::new(),
+                value: EsonSegment::Dict(IndexMap::new()).into(),
+            })
+        );
     }
 
     #[test]
@@ -341,7 +1082,14 @@ mod tests {
         // comment2
         {}
         "##;
-        // assert_eq!(root(json), Ok(("", EsonLiteral::Object(HashMap::new()))));
+        assert_eq!(
+            root(json),
+            Ok(Document {
+                annotations: vec![],
+                bindings: IndexMap::new(),
+                value: EsonSegment::Dict(IndexMap::new()).into(),
+            })
+        );
     }
 
     #[test]
@@ -365,39 +1113,16 @@ mod tests {
             }
         }
         "##;
-        // assert_eq!(
-        //     root(json),
-        //     Ok((
-        //         "",
-        //         EsonLiteral::Object(
-        //             vec![
-        //                 ("a".into(), EsonLiteral::Int(42)),
-        //                 (
-        //                     "b".into(),
-        //                     EsonLiteral::Array(vec![
-        //                         EsonLiteral::Str("x".to_string()),
-        //                         EsonLiteral::Str("y".to_string()),
-        //                         EsonLiteral::Int(12),
-        //                     ])
-        //                 ),
-        //                 (
-        //                     "c".into(),
-        //                     EsonLiteral::Object(
-        //                         vec![
-        //                             ("hello".into(), EsonLiteral::Str("world".to_string())),
-        //                             ("foo".into(), EsonLiteral::Str("bar".to_string())),
-        //                             ("bar".into(), EsonLiteral::Str("hello TODO!".to_string())),
-        //                         ]
-        //                         .into_iter()
-        //                         .collect()
-        //                     )
-        //                 ),
-        //             ]
-        //             .into_iter()
-        //             .collect()
-        //         )
-        //     ))
-        // );
+        let doc = root(json).unwrap();
+        assert!(doc.annotations.is_empty());
+        let EsonSegment::Dict(map) = doc.value.node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(map[&Key::from("a")].node, EsonSegment::Int(42));
+        let EsonSegment::Dict(c) = &map[&Key::from("c")].node else {
+            panic!("expected a dict");
+        };
+        assert_eq!(c.len(), 3);
     }
 
     #[test]
@@ -422,38 +1147,30 @@ mod tests {
 
     #[test]
     fn test_f_string() {
-        assert_eq!(
-            parse_string(r#"f"${name}""#),
-            Ok(("", String::from("Var(name)"))) // @todo
-        );
-        assert_eq!(
-            parse_string(r#"f"hello ${name}""#),
-            Ok(("", String::from("hello TODO!")))
-        );
-        assert_eq!(
-            parse_string(r#"f"hello ${ name }""#),
-            Ok(("", String::from("hello TODO!")))
-        );
-        assert_eq!(
-            parse_string(r#"f"hello ${ name } world""#),
-            Ok(("", String::from("hello TODO! world")))
-        );
-        assert_eq!(
-            parse_string(r#"f"hello ${ name } world ${ name }""#),
-            Ok(("", String::from("hello TODO! world TODO!")))
-        );
-        assert_eq!(
-            parse_string(r####"f#"hello ${ name }"#"####),
-            Ok(("", String::from("hello TODO!")))
-        );
-        assert_eq!(
-            parse_string(r####"f#"hello ${ foo(bar) }"#"####),
-            Ok(("", String::from("hello TODO!")))
-        );
-        assert_eq!(
-            parse_string(r####"f#"hello ${ foo(bar, foo()) }"#"####),
-            Ok(("", String::from("hello TODO!")))
-        );
+        let doc = root(r#"{ greeting: f"hello ${ $.name }!", name: "eson" }"#).unwrap();
+        let EsonSegment::Dict(dict) = &doc.value.node else {
+            panic!("expected a dict");
+        };
+        let EsonSegment::Template(parts) = &dict[&Key::from("greeting")].node else {
+            panic!("expected a template");
+        };
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], TemplatePart::Text("hello ".to_string()));
+        assert_eq!(parts[2], TemplatePart::Text("!".to_string()));
+        let TemplatePart::Expr(expr, None) = &parts[1] else {
+            panic!("expected an interpolation");
+        };
+        assert_eq!((expr.span.line, expr.span.column), (1, 21));
+        assert_eq!(expr.span.len(), "${ $.name }".len());
+
+        let err = root(r#"{ a: f"${ 1 :05z}" }"#).unwrap_err();
+        assert_eq!(err.expected(), "a format spec");
+        assert_eq!((err.line(), err.column()), (1, 16));
+        assert!(root(r#"{ a: ${ 1:05 } }"#).is_err());
+
+        let err = root(r#"{ a: f"x ${ 1 + } y" }"#).unwrap_err();
+        assert_eq!(err.expected(), "an operand");
+        assert_eq!((err.line(), err.column()), (1, 17));
     }
 
     #[test]
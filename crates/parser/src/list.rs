@@ -1,30 +1,103 @@
+use nom::branch::alt;
 use nom::character::complete::char;
-use nom::combinator::{cut, opt};
+use nom::combinator::{cut, map, opt};
 use nom::error::{context, VerboseError};
 use nom::IResult;
 use nom::multi::separated_list0;
-use nom::sequence::{preceded, terminated, tuple};
+use nom::sequence::{pair, preceded, terminated, tuple};
 
 use crate::{eson, eson_literal, EsonLiteralSegment, EsonSegment, sp};
+use crate::expr_token::{parse_condition, parse_for, parse_spread};
+use crate::session;
+use crate::span::{span_between, spanned, Spanned};
+use crate::util::skip_to_sync;
 
 /// some combinators, like `separated_list0` or `many0`, will call a parser repeatedly,
 /// accumulating results in a `Vec`, until it encounters an error.
 /// If you want more control on the parser application, check out the `iterator`
 /// combinator (cf `examples/iterator.rs`)
-pub fn parse_lst(i: &str) -> IResult<&str, Vec<EsonSegment>, VerboseError<&str>> {
+pub(crate) fn parse_lst(i: &str) -> IResult<&str, Vec<Spanned<EsonSegment>>, VerboseError<&str>> {
+    if session::recovering() {
+        return parse_lst_recovering(i);
+    }
     context(
         "parse_lst",
         preceded(
             char('['),
             cut(terminated(
-                separated_list0(preceded(sp, char(',')), eson),
+                separated_list0(preceded(sp, char(',')), preceded(sp, spanned(item))),
                 tuple((sp, opt(char(',')), sp, char(']'))),
             )),
         ),
     )(i)
 }
 
-pub fn parse_literal_lst(i: &str) -> IResult<&str, Vec<EsonLiteralSegment>, VerboseError<&str>> {
+/// `parse_lst` for recovery mode: a broken element is reported and kept as
+/// `EsonSegment::Error`, parsing resumes at the next `,` or `]`.
+fn parse_lst_recovering(i: &str) -> IResult<&str, Vec<Spanned<EsonSegment>>, VerboseError<&str>> {
+    let (mut input, _) = context("parse_lst", char('['))(i)?;
+    let mut list = Vec::new();
+    loop {
+        (input, _) = sp(input)?;
+        if let Ok((remaining, _)) = char::<_, VerboseError<&str>>(']')(input) {
+            return Ok((remaining, list));
+        }
+        if input.is_empty() || input.starts_with('}') {
+            session::report(session::error_at(input, "']'", vec!["parse_lst"]));
+            return Ok((input, list));
+        }
+
+        input = match context("parse_lst", spanned(item))(input) {
+            Ok((remaining, value)) => {
+                list.push(value);
+                remaining
+            }
+            Err(e) => {
+                session::report(session::error(e));
+                let remaining = skip_to_sync(input);
+                list.push(Spanned::new(EsonSegment::Error, span_between(input, remaining)));
+                remaining
+            }
+        };
+
+        (input, _) = sp(input)?;
+        if let Ok((remaining, _)) = char::<_, VerboseError<&str>>(',')(input) {
+            input = remaining;
+        } else if !(input.is_empty() || input.starts_with([']', '}'])) {
+            // most likely a forgotten comma, carry on with the next item
+            session::report(session::error_at(input, "',' or ']'", vec!["parse_lst"]));
+        }
+    }
+}
+
+/// A value, a `...expr` spread or an `if cond: item`.
+pub(crate) fn item(i: &str) -> IResult<&str, EsonSegment, VerboseError<&str>> {
+    alt((map(parse_spread, EsonSegment::Spread), clauses, eson))(i)
+}
+
+/// `if cond` and `for x in xs` clauses in front of an item, each one taking
+/// the next clause, or the item after the `:`, as its body.
+fn clauses(i: &str) -> IResult<&str, EsonSegment, VerboseError<&str>> {
+    let body = |i| {
+        context(
+            "clause_body",
+            preceded(
+                sp,
+                alt((spanned(clauses), preceded(pair(char(':'), sp), spanned(item)))),
+            ),
+        )(i)
+    };
+    alt((
+        map(pair(parse_condition, cut(body)), |(condition, item)| {
+            EsonSegment::If(condition, Box::new(item))
+        }),
+        map(pair(parse_for, cut(body)), |((names, iterable), item)| {
+            EsonSegment::For(names, iterable, Box::new(item))
+        }),
+    ))(i)
+}
+
+pub(crate) fn parse_literal_lst(i: &str) -> IResult<&str, Vec<EsonLiteralSegment>, VerboseError<&str>> {
     context(
         "parse_literal_lst",
         preceded(
@@ -5,10 +5,10 @@ use nom::IResult;
 
 use crate::{EsonLiteralSegment, EsonSegment};
 
-pub fn parse_null(input: &str) -> IResult<&str, EsonSegment, VerboseError<&str>> {
+pub(crate) fn parse_null(input: &str) -> IResult<&str, EsonSegment, VerboseError<&str>> {
     map(tag("null"), |_| EsonSegment::Null)(input)
 }
 
-pub fn parse_literal_null(input: &str) -> IResult<&str, EsonLiteralSegment, VerboseError<&str>> {
+pub(crate) fn parse_literal_null(input: &str) -> IResult<&str, EsonLiteralSegment, VerboseError<&str>> {
     map(tag("null"), |_| EsonLiteralSegment::Null)(input)
 }
\ No newline at end of file
@@ -1,99 +1,220 @@
+//! Numeric literals.
+//!
+//! ```text
+//! number    = [sign] (hex-float | radix | decimal | "Infinity") | "NaN"
+//! sign      = "+" | "-"
+//! radix     = "0" ("x" | "X") hex-digits
+//!           | "0" ("o" | "O") oct-digits
+//!           | "0" ("b" | "B") bin-digits
+//! hex-float = "0" ("x" | "X") hex-digits ["." [hex-digits]] ("p" | "P") [sign] digits
+//! decimal   = (digits ["." [digits]] | "." digits) [("e" | "E") [sign] digits]
+//! digits    = digit {["_"] digit}
+//! ```
+//!
+//! `1_000_000`, `-0x1F`, `+0B1010`, `.5`, `5.`, `0x1.8p3`, `0x1p3` are all
+//! numbers. A decimal without a fraction or exponent, and every radix
+//! literal, is an integer; anything else is a float (or a decimal, see
+//! [`ParseOptions::decimals`](crate::ParseOptions)). Inside `${ ... }` a sign
+//! is always the `+`/`-` operator, so `a -1` still means `a - 1`.
+//!
+//! A decimal directly followed by a unit is a duration or byte size instead,
+//! see the `units` module.
+
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
 use nom::branch::alt;
-use nom::bytes::complete::{tag, tag_no_case, take_while1};
-use nom::character::complete::{char as ch, digit1};
-use nom::combinator::{map, opt};
+use nom::bytes::complete::{tag, tag_no_case};
+use nom::character::complete::{char as ch, one_of, satisfy};
+use nom::combinator::{map, opt, recognize};
 use nom::error::VerboseError;
-use nom::IResult;
+use nom::multi::many0_count;
 use nom::number::complete::double;
-use nom::sequence::{preceded, tuple};
+use nom::sequence::{pair, preceded, tuple};
+use nom::IResult;
+use num_bigint::BigInt;
 
-use crate::{EsonLiteralSegment, EsonSegment};
+use crate::units::{parse_quantity, Quantity};
+use crate::{session, EsonLiteralSegment, EsonSegment};
 
 pub(crate) fn parse_numeric(input: &str) -> nom::IResult<&str, EsonSegment, VerboseError<&str>> {
-    alt((
-        map(parse_bin, |s| {
-            EsonSegment::Int(i64::from_str_radix(s, 2).expect("TODO"))
-        }),
-        map(parse_oct, |s| {
-            EsonSegment::Int(i64::from_str_radix(s, 8).expect("TODO"))
-        }),
-        map(parse_hex, |s| {
-            EsonSegment::Int(i64::from_str_radix(s, 16).expect("TODO"))
-        }),
-        map(
-            tuple((
-                digit1,
-                opt(preceded(ch('.'), digit1)),
-                opt(preceded(
-                    tag_no_case("e"),
-                    tuple((opt(alt((ch('+'), ch('-')))), digit1)),
-                )),
-            )),
-            |(int_part, decimal_part, exp_part): (
-                &str,
-                Option<&str>,
-                Option<(Option<char>, &str)>,
-            )| {
-                if decimal_part.is_none() && exp_part.is_none() {
-                    // 没有小数点或指数部分 => 整数
-                    // int_part.parse::<i64>().map(JsonValue::Int)
-                    EsonSegment::Int(int_part.parse::<i64>().expect("TODO"))
-                } else {
-                    let num_str = format!(
-                        "{}{}{}",
-                        int_part,
-                        decimal_part.map_or(String::from(""), |d| format!(".{}", d)),
-                        exp_part.map_or(String::from(""), |(sign, e)| format!(
-                            "e{}{}",
-                            sign.unwrap_or('+'),
-                            e
-                        ))
-                    );
-                    // dbg!(num_str.clone());
-                    // num_str.parse::<f64>().map(JsonValue::Float)
-                    EsonSegment::Float(num_str.parse::<f64>().expect("TODO"))
-                }
-            },
-        ),
-        map(tag("Infinity"), |_| EsonSegment::Float(f64::INFINITY)),
-        map(tag("-Infinity"), |_| EsonSegment::Float(f64::NEG_INFINITY)),
-        map(tag("NaN"), |_| EsonSegment::Float(f64::NAN)),
-    ))(input)
+    if let Ok((remaining, _)) = tag::<_, _, VerboseError<&str>>("NaN")(input) {
+        return Ok((remaining, EsonSegment::Float(f64::NAN)));
+    }
+    let (rest, sign) = opt(one_of("+-"))(input)?;
+    let negative = sign == Some('-');
+    if let Ok((remaining, _)) = tag::<_, _, VerboseError<&str>>("Infinity")(rest) {
+        let infinity = if negative { f64::NEG_INFINITY } else { f64::INFINITY };
+        return Ok((remaining, EsonSegment::Float(infinity)));
+    }
+    if let Ok((remaining, float)) = parse_hex_float(rest) {
+        return Ok((remaining, EsonSegment::Float(if negative { -float } else { float })));
+    }
+    if let Ok((remaining, (radix, digits))) = alt((
+        map(parse_bin, |s| (2, s)),
+        map(parse_oct, |s| (8, s)),
+        map(parse_hex, |s| (16, s)),
+    ))(rest)
+    {
+        return Ok((remaining, integer(input, negative, digits, radix)?));
+    }
+    if let Ok((remaining, quantity)) = parse_quantity(rest) {
+        if sign.is_some() {
+            let expected = "a duration or byte size without a sign";
+            return Err(session::fail(input, session::error_at(input, expected, vec![])));
+        }
+        return Ok((remaining, match quantity {
+            Quantity::Duration(d) => EsonSegment::Duration(d),
+            Quantity::ByteSize(s) => EsonSegment::ByteSize(s),
+        }));
+    }
+    decimal(input, negative, rest)
 }
 
-pub fn parse_literal_number(input: &str) -> IResult<&str, EsonLiteralSegment, VerboseError<&str>> {
+fn decimal<'a>(
+    input: &'a str,
+    negative: bool,
+    rest: &'a str,
+) -> nom::IResult<&'a str, EsonSegment, VerboseError<&'a str>> {
+    let (remaining, ((int_part, decimal_part), exp_part)) = tuple((
+        alt((
+            map(pair(dec_digits, opt(preceded(ch('.'), opt(dec_digits)))), |(i, d)| (Some(i), d)),
+            map(preceded(ch('.'), dec_digits), |d| (None, Some(Some(d)))),
+        )),
+        opt(preceded(
+            tag_no_case("e"),
+            recognize(pair(opt(one_of("+-")), dec_digits)),
+        )),
+    ))(rest)?;
+    if decimal_part.is_none() && exp_part.is_none() {
+        // 没有小数点或指数部分 => 整数
+        return Ok((remaining, integer(input, negative, int_part.unwrap_or("0"), 10)?));
+    }
+    let text = format!(
+        "{}{}{}{}",
+        if negative { "-" } else { "" },
+        int_part.unwrap_or("0"),
+        decimal_part.flatten().map_or(String::new(), |d| format!(".{}", d)),
+        exp_part.map_or(String::new(), |e| format!("e{}", e)),
+    )
+    .replace('_', "");
+    let number = if session::options().decimals {
+        BigDecimal::from_str(&text).ok().map(EsonSegment::Decimal)
+    } else {
+        text.parse::<f64>().ok().map(EsonSegment::Float)
+    };
+    match number {
+        Some(number) => Ok((remaining, number)),
+        None => Err(session::fail(input, session::error_at(input, "a number", vec![]))),
+    }
+}
+
+/// The integer written as `digits` in `radix`, an `i64` unless it only fits
+/// in a big integer.
+fn integer<'a>(
+    at: &'a str,
+    negative: bool,
+    digits: &str,
+    radix: u32,
+) -> Result<EsonSegment, nom::Err<VerboseError<&'a str>>> {
+    let digits = format!("{}{}", if negative { "-" } else { "" }, digits.replace('_', ""));
+    if let Ok(i) = i64::from_str_radix(&digits, radix) {
+        return Ok(EsonSegment::Int(i));
+    }
+    match BigInt::parse_bytes(digits.as_bytes(), radix) {
+        Some(i) if session::options().big_integers => Ok(EsonSegment::BigInt(i)),
+        _ => {
+            let expected = "an integer in the 64-bit range";
+            Err(session::fail(at, session::error_at(at, expected, vec![])))
+        }
+    }
+}
+
+pub(crate) fn parse_literal_number(input: &str) -> IResult<&str, EsonLiteralSegment, VerboseError<&str>> {
     let (remaining, number) = parse_numeric(input)?;
     match number {
         EsonSegment::Int(i) => Ok((remaining, EsonLiteralSegment::Int(i))),
+        EsonSegment::BigInt(i) => Ok((remaining, EsonLiteralSegment::BigInt(i))),
         EsonSegment::Float(f) => Ok((remaining, EsonLiteralSegment::Float(f))),
+        EsonSegment::Decimal(d) => Ok((remaining, EsonLiteralSegment::Decimal(d))),
+        EsonSegment::Duration(d) => Ok((remaining, EsonLiteralSegment::Duration(d))),
+        EsonSegment::ByteSize(s) => Ok((remaining, EsonLiteralSegment::ByteSize(s))),
         _ => unreachable!()
     }
 }
 
+/// `digit {["_"] digit}` for the digits accepted by `is_digit`.
+fn digits<'a>(
+    is_digit: fn(char) -> bool,
+) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str, VerboseError<&'a str>> {
+    recognize(pair(
+        satisfy(is_digit),
+        many0_count(preceded(opt(ch('_')), satisfy(is_digit))),
+    ))
+}
+
+pub(crate) fn dec_digits(input: &str) -> nom::IResult<&str, &str, VerboseError<&str>> {
+    digits(|c| c.is_ascii_digit())(input)
+}
+
+/// `0x1.8p3`, the mantissa in hex and the exponent a power of two.
+fn parse_hex_float(input: &str) -> nom::IResult<&str, f64, VerboseError<&str>> {
+    let hex = |c: char| c.is_ascii_hexdigit();
+    let (remaining, (int_part, frac_part, exp)) = tuple((
+        preceded(tag_no_case("0x"), digits(hex)),
+        opt(preceded(ch('.'), opt(digits(hex)))),
+        preceded(tag_no_case("p"), recognize(pair(opt(one_of("+-")), dec_digits))),
+    ))(input)?;
+    let frac_part = frac_part.flatten().unwrap_or("").replace('_', "");
+    let mantissa = int_part
+        .chars()
+        .chain(frac_part.chars())
+        .filter_map(|c| c.to_digit(16))
+        .fold(0f64, |m, d| m * 16.0 + d as f64);
+    if mantissa == 0.0 {
+        return Ok((remaining, 0.0));
+    }
+    // an exponent out of range still gives infinity or zero by its sign
+    let exp = exp.replace('_', "").parse::<i32>().unwrap_or(match exp.starts_with('-') {
+        true => i32::MIN,
+        false => i32::MAX,
+    });
+    let mut exp = exp.saturating_sub(4 * frac_part.len() as i32);
+    // scale in steps, a long mantissa may need an exponent below the
+    // smallest a single power of two can hold
+    let mut value = mantissa;
+    while exp != 0 && value != 0.0 && value.is_finite() {
+        let step = exp.clamp(-1000, 1000);
+        value *= 2f64.powi(step);
+        exp -= step;
+    }
+    Ok((remaining, value))
+}
+
 fn parse_f64(input: &str) -> nom::IResult<&str, f64, VerboseError<&str>> {
     double(input)
 }
 
 fn parse_hex(input: &str) -> nom::IResult<&str, &str, VerboseError<&str>> {
-    let is_hex_digit = |c: char| c.is_digit(16);
-    let (remaining, _) = tag("0x")(input)?;
-    take_while1(is_hex_digit)(remaining)
+    let (remaining, _) = tag_no_case("0x")(input)?;
+    digits(|c| c.is_ascii_hexdigit())(remaining)
 }
 
 fn parse_oct(input: &str) -> nom::IResult<&str, &str, VerboseError<&str>> {
-    let is_oct_digit = |c: char| c.is_digit(8);
-    let (remaining, _) = tag("0o")(input)?;
-    take_while1(is_oct_digit)(remaining)
+    let (remaining, _) = tag_no_case("0o")(input)?;
+    digits(|c| c.is_digit(8))(remaining)
 }
 
 fn parse_bin(input: &str) -> nom::IResult<&str, &str, VerboseError<&str>> {
-    let is_bin_digit = |c: char| c.is_digit(2);
-    let (remaining, _) = tag("0b")(input)?;
-    take_while1(is_bin_digit)(remaining)
+    let (remaining, _) = tag_no_case("0b")(input)?;
+    digits(|c| c.is_digit(2))(remaining)
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::session::with_session;
+    use crate::ParseOptions;
+
     use super::*;
 
     #[test]
@@ -121,4 +242,167 @@ mod tests {
 
         // let i = 123e2;
     }
+
+    #[test]
+    fn test_grammar() {
+        for (src, expected) in [
+            ("-42", -42),
+            ("+42", 42),
+            ("1_000_000", 1_000_000),
+            ("0X1F", 31),
+            ("0xff_ff", 65535),
+            ("-0x10", -16),
+            ("+0B1010", 10),
+            ("-0b1", -1),
+            ("0O17", 15),
+            ("-0o17", -15),
+            ("-9223372036854775808", i64::MIN),
+        ] {
+            let expected = Ok(("", EsonLiteralSegment::Int(expected)));
+            assert_eq!(parse_literal_number(src), expected, "{}", src);
+        }
+        for (src, expected) in [
+            (".5", 0.5),
+            ("-.5", -0.5),
+            ("5.", 5.0),
+            ("-5.e1", -50.0),
+            ("1_000.000_1", 1000.0001),
+            ("+1.5E+2", 150.0),
+            ("0x1.8p3", 12.0),
+            ("0X0.8P-1", 0.25),
+            ("-0x1_0.p0", -16.0),
+            ("0x1.p-99999999999", 0.0),
+            ("0x1.p99999999999", f64::INFINITY),
+            ("0x0.p99999999999", 0.0),
+            ("0x100000000000000000000.p-1100", 2f64.powi(-1020)),
+            ("0x1p3", 8.0),
+            ("-0X1_0P-4", -1.0),
+        ] {
+            let expected = Ok(("", EsonLiteralSegment::Float(expected)));
+            assert_eq!(parse_literal_number(src), expected, "{}", src);
+        }
+        assert_eq!(
+            parse_literal_number("-Infinity"),
+            Ok(("", EsonLiteralSegment::Float(f64::NEG_INFINITY)))
+        );
+        assert_eq!(
+            parse_literal_number("+Infinity"),
+            Ok(("", EsonLiteralSegment::Float(f64::INFINITY)))
+        );
+
+        // separators only go between digits
+        assert_eq!(parse_literal_number("1__0"), Ok(("__0", EsonLiteralSegment::Int(1))));
+        assert_eq!(parse_literal_number("1_"), Ok(("_", EsonLiteralSegment::Int(1))));
+        assert!(parse_literal_number("_1").is_err());
+        assert!(parse_literal_number("-").is_err());
+        assert!(parse_literal_number(".").is_err());
+    }
+
+    #[test]
+    fn test_signs_in_context() {
+        let src = "@range(-1, +0x10, -.5)";
+        let (_, annotations) = crate::annotation::parse_annotations(src).unwrap();
+        assert_eq!(
+            annotations[0].value,
+            Some(vec![
+                EsonLiteralSegment::Int(-1),
+                EsonLiteralSegment::Int(16),
+                EsonLiteralSegment::Float(-0.5),
+            ])
+        );
+        let doc = crate::root("[-1, 2_000, .25, ${ a -1 }]").unwrap();
+        let EsonSegment::List(items) = doc.value.node else {
+            panic!("expected a list");
+        };
+        assert_eq!(items[0].node, EsonSegment::Int(-1));
+        assert_eq!(items[1].node, EsonSegment::Int(2000));
+        assert_eq!(items[2].node, EsonSegment::Float(0.25));
+        let EsonSegment::Expr(chunk) = &items[3].node else {
+            panic!("expected an expression");
+        };
+        assert_eq!(chunk.to_string(), "Var(a)MinusVal(Int(1))");
+    }
+
+    #[test]
+    fn test_overflow() {
+        for src in [
+            "[99999999999999999999]",
+            "[0xFFFFFFFFFFFFFFFFF]",
+            "{\"a\": 0b1111111111111111111111111111111111111111111111111111111111111111}",
+        ] {
+            let err = crate::root(src).unwrap_err();
+            assert_eq!(err.expected(), "an integer in the 64-bit range");
+        }
+        let err = crate::root("{\"a\": [1,\n  99999999999999999999]}").unwrap_err();
+        assert_eq!((err.line(), err.column()), (2, 3));
+        assert_eq!(err.found(), "'99999999999999999999'");
+        assert_eq!(
+            parse_literal_number("9223372036854775807"),
+            Ok(("", EsonLiteralSegment::Int(i64::MAX)))
+        );
+    }
+
+    #[test]
+    fn test_big_integers() {
+        let options = ParseOptions {
+            big_integers: true,
+            ..ParseOptions::default()
+        };
+        with_session("", &options, false, || {
+            let big = BigInt::from_str("99999999999999999999").unwrap();
+            assert_eq!(
+                parse_numeric("99999999999999999999"),
+                Ok(("", EsonSegment::BigInt(big)))
+            );
+            let big = BigInt::from_str("295147905179352825855").unwrap();
+            assert_eq!(
+                parse_numeric("0xFFFFFFFFFFFFFFFFF"),
+                Ok(("", EsonSegment::BigInt(big)))
+            );
+            assert_eq!(parse_numeric("42"), Ok(("", EsonSegment::Int(42))));
+        });
+    }
+
+    #[test]
+    fn test_decimals() {
+        let options = ParseOptions {
+            decimals: true,
+            ..ParseOptions::default()
+        };
+        with_session("", &options, false, || {
+            let (_, number) = parse_literal_number("19.90").unwrap();
+            let EsonLiteralSegment::Decimal(d) = number else {
+                panic!("expected a decimal");
+            };
+            assert_eq!(d.to_string(), "19.90");
+            assert_eq!(d, BigDecimal::from_str("19.9").unwrap());
+            let (_, number) = parse_numeric("0.1e2").unwrap();
+            assert_eq!(number, EsonSegment::Decimal(BigDecimal::from(10)));
+            assert_eq!(parse_numeric("7"), Ok(("", EsonSegment::Int(7))));
+        });
+    }
+
+    #[test]
+    fn test_units() {
+        let doc = crate::root("{timeout: 1h30m, limit: 10MiB, retry: ${ 250ms + 1s }}").unwrap();
+        let EsonSegment::Dict(dict) = doc.value.node else {
+            panic!("expected a dict");
+        };
+        let values: Vec<_> = dict.values().map(|v| &v.node).collect();
+        assert_eq!(values[0], &EsonSegment::Duration(std::time::Duration::from_secs(5400)));
+        assert_eq!(values[1], &EsonSegment::ByteSize(crate::ByteSize(10 << 20)));
+        assert!(matches!(values[2], EsonSegment::Expr(_)));
+        assert_eq!(
+            parse_literal_number("1.5GB"),
+            Ok(("", EsonLiteralSegment::ByteSize(crate::ByteSize(1_500_000_000))))
+        );
+        // a number followed by a word is still a number
+        assert_eq!(parse_numeric("5min"), Ok(("min", EsonSegment::Int(5))));
+
+        let err = crate::root("{timeout: -5s}").unwrap_err();
+        assert_eq!(err.expected(), "a duration or byte size without a sign");
+        let err = crate::root("{size: 0.5B}").unwrap_err();
+        assert_eq!(err.expected(), "a whole number of bytes");
+        assert_eq!((err.line(), err.column()), (1, 8));
+    }
 }
@@ -0,0 +1,43 @@
+/// Settings that change how a document is parsed, see [`root_with`](crate::root_with).
+///
+/// ```
+/// use parser::{DuplicateKeys, ParseOptions};
+///
+/// let options = ParseOptions {
+///     duplicate_keys: DuplicateKeys::LastWins,
+///     ..ParseOptions::default()
+/// };
+/// let doc = parser::root_with(r#"{"a": 1, "a": 2}"#, &options).unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParseOptions {
+    /// What to do when a dict spells out the same key twice.
+    pub duplicate_keys: DuplicateKeys,
+    /// Accept `# ...` line comments next to `// ...`, for files written for
+    /// tools that use them.
+    pub hash_comments: bool,
+    /// Let `/* ... */` comments nest, so a block holding a comment can be
+    /// commented out as a whole.
+    pub nested_comments: bool,
+    /// Parse integers that do not fit in an `i64` as [`EsonSegment::BigInt`](crate::EsonSegment::BigInt)
+    /// instead of rejecting them.
+    pub big_integers: bool,
+    /// Parse numbers with a fraction or an exponent as exact
+    /// [`EsonSegment::Decimal`](crate::EsonSegment::Decimal)s instead of `f64`,
+    /// so `0.10` stays `0.10`.
+    pub decimals: bool,
+}
+
+/// How a key that appears twice in the same dict is handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// Reject the document, the error points at the second occurrence and
+    /// names the first one.
+    #[default]
+    Error,
+    /// Keep the last value (and its key annotations) at the position of the
+    /// first occurrence, like most JSON parsers do.
+    LastWins,
+    /// Keep the first value and ignore the later ones.
+    FirstWins,
+}
@@ -0,0 +1,196 @@
+use std::cell::RefCell;
+
+use nom::error::{ErrorKind, ParseError, VerboseError};
+
+use crate::error::Error;
+use crate::options::ParseOptions;
+use crate::span::Span;
+
+/// The document being parsed and the state shared by every parser working on it.
+///
+/// The parsers only ever see suffixes of the document, so the entry points
+/// open a session over the whole text; positions, diagnostics and parse modes
+/// are looked up here. Nested sessions (e.g. parsing an imported file) shadow
+/// the outer one until they return.
+struct Session {
+    base: usize,
+    text: String,
+    line_starts: Vec<usize>,
+    options: ParseOptions,
+    recover: bool,
+    diagnostics: Vec<Error>,
+    /// An error found outside of nom, which takes precedence over the nom
+    /// error it was raised through.
+    fatal: Option<Error>,
+}
+
+impl Session {
+    fn new(text: &str, options: &ParseOptions, recover: bool) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Session {
+            base: text.as_ptr() as usize,
+            text: text.to_string(),
+            line_starts,
+            options: options.clone(),
+            recover,
+            diagnostics: Vec::new(),
+            fatal: None,
+        }
+    }
+
+    fn offset_of(&self, s: &str) -> Option<usize> {
+        let ptr = s.as_ptr() as usize;
+        (ptr >= self.base && ptr + s.len() <= self.base + self.text.len()).then(|| ptr - self.base)
+    }
+
+    fn span(&self, start: usize, end: usize) -> Span {
+        let line = self.line_starts.partition_point(|&l| l <= start);
+        let line_start = self.line_starts[line - 1];
+        Span {
+            start,
+            end,
+            line,
+            column: self.text[line_start..start].chars().count() + 1,
+        }
+    }
+}
+
+thread_local! {
+    static SESSIONS: RefCell<Vec<Session>> = const { RefCell::new(Vec::new()) };
+}
+
+struct SessionGuard;
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        SESSIONS.with(|s| s.borrow_mut().pop());
+    }
+}
+
+/// Run `f` in a session over `source`, returning its output together with
+/// the diagnostics reported while recovering from syntax errors.
+pub(crate) fn with_session<T>(
+    source: &str,
+    options: &ParseOptions,
+    recover: bool,
+    f: impl FnOnce() -> T,
+) -> (T, Vec<Error>) {
+    SESSIONS.with(|s| s.borrow_mut().push(Session::new(source, options, recover)));
+    let guard = SessionGuard;
+    let output = f();
+    let diagnostics = SESSIONS.with(|s| {
+        s.borrow_mut()
+            .last_mut()
+            .map(|session| std::mem::take(&mut session.diagnostics))
+            .unwrap_or_default()
+    });
+    drop(guard);
+    (output, diagnostics)
+}
+
+/// The span of `start..start + len` in `at`, or a default span outside of a session.
+pub(crate) fn locate(at: &str, lead: usize, len: usize) -> Span {
+    SESSIONS.with(|s| match s.borrow().last() {
+        Some(session) => match session.offset_of(at) {
+            Some(offset) => session.span(offset + lead, offset + lead + len),
+            None => Span::default(),
+        },
+        None => Span::default(),
+    })
+}
+
+/// The options the current document is parsed with.
+pub(crate) fn options() -> ParseOptions {
+    SESSIONS.with(|s| {
+        s.borrow()
+            .last()
+            .map(|session| session.options.clone())
+            .unwrap_or_default()
+    })
+}
+
+/// Whether syntax errors should be reported and skipped instead of aborting.
+pub(crate) fn recovering() -> bool {
+    SESSIONS.with(|s| s.borrow().last().is_some_and(|session| session.recover))
+}
+
+/// Convert a nom error raised on the current document.
+pub(crate) fn error(err: nom::Err<VerboseError<&str>>) -> Error {
+    SESSIONS.with(|s| match s.borrow_mut().last_mut() {
+        Some(session) => match session.fatal.take() {
+            Some(fatal) => fatal,
+            None => Error::from_nom(&session.text, err, |i| session.offset_of(i)),
+        },
+        None => Error::from_nom("", err, |_| None),
+    })
+}
+
+/// An error at `at`, for problems found outside of a nom parser.
+pub(crate) fn error_at(at: &str, expected: &str, context: Vec<&'static str>) -> Error {
+    SESSIONS.with(|s| match s.borrow().last() {
+        Some(session) => {
+            let offset = session.offset_of(at).unwrap_or(session.text.len());
+            Error::at(&session.text, offset, expected.to_string(), context)
+        }
+        None => Error::at("", 0, expected.to_string(), context),
+    })
+}
+
+/// An error at the first token from byte `offset` of the current document.
+pub(crate) fn error_at_offset(offset: usize, expected: &str) -> Error {
+    SESSIONS.with(|s| match s.borrow().last() {
+        Some(session) => {
+            let rest = &session.text[offset.min(session.text.len())..];
+            let offset = session.text.len() - rest.trim_start().len();
+            Error::at(&session.text, offset, expected.to_string(), vec![])
+        }
+        None => Error::at("", 0, expected.to_string(), vec![]),
+    })
+}
+
+/// Record a diagnostic, the parser carries on with a partial tree.
+pub(crate) fn report(error: Error) {
+    SESSIONS.with(|s| {
+        if let Some(session) = s.borrow_mut().last_mut() {
+            session.diagnostics.push(error);
+        }
+    });
+}
+
+/// Abort parsing with `error`, to be returned by the nom failure raised at `at`.
+pub(crate) fn fail(at: &str, error: Error) -> nom::Err<VerboseError<&str>> {
+    SESSIONS.with(|s| {
+        if let Some(session) = s.borrow_mut().last_mut() {
+            session.fatal.get_or_insert(error);
+        }
+    });
+    nom::Err::Failure(VerboseError::from_error_kind(at, ErrorKind::Verify))
+}
+
+/// The error for the key `name` at `span`, a duplicate of the one at `first`.
+pub(crate) fn duplicate_key(name: &str, span: Span, first: Span) -> Error {
+    SESSIONS.with(|s| match s.borrow().last() {
+        Some(session) => Error::duplicate_key(&session.text, span, name, first),
+        None => Error::duplicate_key("", Span::default(), name, first),
+    })
+}
+
+/// The error for the date or time literal of `len` bytes at `at`, which
+/// names no real moment.
+pub(crate) fn invalid_date(at: &str, len: usize) -> Error {
+    let span = locate(at, 0, len);
+    SESSIONS.with(|s| match s.borrow().last() {
+        Some(session) => Error::invalid_date(&session.text, span),
+        None => Error::invalid_date("", Span::default()),
+    })
+}
+
+/// The error for binding `name` at `span` again, after binding it at `first`.
+pub(crate) fn duplicate_binding(name: &str, span: Span, first: Span) -> Error {
+    SESSIONS.with(|s| match s.borrow().last() {
+        Some(session) => Error::duplicate_binding(&session.text, span, name, first),
+        None => Error::duplicate_binding("", Span::default(), name, first),
+    })
+}
@@ -0,0 +1,159 @@
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+use nom::IResult;
+
+use crate::session;
+
+/// A region of the source text a node was parsed from.
+///
+/// `start` and `end` are byte offsets into the document, `line` and `column`
+/// are the 1-based position of `start` (columns count characters, not bytes).
+///
+/// Spans never take part in equality or hashing: two trees parsed from
+/// differently formatted sources compare equal as long as their values do.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Span {}
+
+impl Hash for Span {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+/// A node together with the span it was parsed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Self {
+        Spanned { node, span }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.node
+    }
+}
+
+impl<T> Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node
+    }
+}
+
+impl<T> From<T> for Spanned<T> {
+    fn from(node: T) -> Self {
+        Spanned {
+            node,
+            span: Span::default(),
+        }
+    }
+}
+
+/// The span covering `from` up to (but excluding) `to`, where `to` is what is
+/// left of `from` after a parser ran. Surrounding whitespace is not included.
+pub(crate) fn span_between(from: &str, to: &str) -> Span {
+    let consumed = &from[..from.len() - to.len()];
+    let trimmed = consumed.trim_start();
+    let lead = consumed.len() - trimmed.len();
+    let len = trimmed.trim_end().len();
+    session::locate(from, lead, len)
+}
+
+/// Wrap the output of `parser` with the span of the text it consumed.
+pub(crate) fn spanned<'a, O, E, F>(
+    mut parser: F,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Spanned<O>, E>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, O, E>,
+{
+    move |input: &'a str| {
+        let (remaining, node) = parser(input)?;
+        Ok((
+            remaining,
+            Spanned::new(node, span_between(input, remaining)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nom::bytes::complete::tag;
+    use nom::error::VerboseError;
+
+    use crate::session::with_session;
+    use crate::ParseOptions;
+
+    use super::*;
+
+    #[test]
+    fn test_span_between() {
+        let src = "ab\n  cd  \nef";
+        with_session(src, &ParseOptions::default(), false, || {
+            let span = span_between(&src[3..], &src[9..]);
+            assert_eq!((span.start, span.end, span.line, span.column), (5, 7, 2, 3));
+
+            let (_, s) = spanned(tag::<_, _, VerboseError<&str>>("ef"))(&src[10..]).unwrap();
+            assert_eq!(
+                (s.span.start, s.span.end, s.span.line, s.span.column),
+                (10, 12, 3, 1)
+            );
+        });
+    }
+
+    #[test]
+    fn test_span_unicode_column() {
+        let src = "// 注释\n  x";
+        with_session(src, &ParseOptions::default(), false, || {
+            let span = span_between(&src[src.len() - 1..], "");
+            assert_eq!((span.line, span.column), (2, 3));
+            let span = span_between(&src[6..], &src[9..]);
+            assert_eq!((span.line, span.column), (1, 5));
+        });
+    }
+
+    #[test]
+    fn test_span_without_source() {
+        let span = span_between("abc", "");
+        assert_eq!((span.start, span.end, span.line), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_span_eq() {
+        let a = Span {
+            start: 1,
+            end: 2,
+            line: 1,
+            column: 2,
+        };
+        assert_eq!(a, Span::default());
+        assert_eq!(Spanned::new(1, a), Spanned::from(1));
+        assert_ne!(Spanned::new(1, a), Spanned::from(2));
+    }
+}
@@ -1,13 +1,47 @@
+//! String literals.
+//!
+//! ```text
+//! "text"              escapes such as \n and \u{1F601}
+//! r"text"  r#"text"#  raw, no escapes
+//! f"text"  f#"text"#  format, with ${ ... } interpolation, `\$` for a `$`
+//!                     and ${ ... : spec } to control the rendering, see
+//!                     `format_spec`
+//! """               triple-quoted: any of the above with """ instead of ",
+//!   text            e.g. r#"""...."""# or f"""...""", spanning several
+//!   """             lines and dedented
+//!
+//! b"\x00\xff"         bytes, text as UTF-8 plus \xHH escapes
+//! b64"AP8="           bytes in base64, standard or URL-safe alphabet
+//! hex"00ff"           bytes in hex
+//! ```
+//!
+//! Whitespace is ignored in `b64"..."` and `hex"..."`, so a long blob can be
+//! split over several lines.
+//!
+//! A triple-quoted string drops the rest of its opening line and the
+//! indentation of its closing `"""` when they are blank, and removes the
+//! whitespace all its other non-blank lines start with, so it can be indented
+//! along with the config around it. The text then ends without a line break;
+//! writing `"""+` keeps the one before the closing `"""`.
+
+use std::ops::Range;
+
 use nom::branch::alt;
-use nom::bytes::complete::{is_not, tag, take, take_while_m_n};
+use nom::bytes::complete::{is_not, tag, take, take_till, take_while_m_n};
 use nom::character::complete::{char as ch, multispace1};
-use nom::combinator::{complete, map, map_opt, map_res, value, verify};
+use nom::combinator::{cut, map, map_opt, map_res, recognize, value, verify};
 use nom::error::VerboseError;
 use nom::IResult;
 use nom::multi::{count, fold_many0, many_till};
-use nom::sequence::{delimited, pair, preceded};
+use nom::sequence::{delimited, pair, preceded, terminated};
+
+use crate::expr_token::chunk::ExprTokenChunk;
+use crate::expr_token::parse_interpolation;
+use crate::format_spec::FormatSpec;
+use crate::session;
+use crate::span::{spanned, Spanned};
 
-use crate::expr_token::parse_expr_token_chunk;
+const TRIPLE: &str = r#"""""#;
 
 fn parse_unicode(input: &str) -> IResult<&str, char, VerboseError<&str>> {
     let parse_1_to_6_hex_num = take_while_m_n(1, 6, |c: char| c.is_ascii_hexdigit());
@@ -54,24 +88,65 @@ fn parse_escaped_whitespace(input: &str) -> IResult<&str, &str, VerboseError<&st
     preceded(ch('\\'), multispace1)(input)
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Parse a `\xHH` escape, only allowed in `b"..."`.
+fn parse_escaped_byte(input: &str) -> IResult<&str, u8, VerboseError<&str>> {
+    let parse_2_hex_num = take_while_m_n(2, 2, |c: char| c.is_ascii_hexdigit());
+    map_res(preceded(tag("\\x"), parse_2_hex_num), |hex| {
+        u8::from_str_radix(hex, 16)
+    })(input)
+}
+
+#[derive(Debug, Clone, PartialEq)]
 enum StringFragment<'a> {
     Literal(&'a str),
     EscapedChar(char),
     EscapedWS,
-    Value(String),
+    Value(Spanned<ExprTokenChunk>, Option<FormatSpec>),
 }
 
-fn parse_normal_string(input: &str) -> IResult<&str, String, VerboseError<&str>> {
-    let parse_literal = verify(is_not(r#"\""#), |s: &str| !s.is_empty());
-    let parse_fragment = alt((
+/// A piece of a format string: `f"port ${ $.port }"` is the text `port `
+/// followed by the interpolation `${ $.port }`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplatePart {
+    /// Text, with its escapes already applied.
+    Text(String),
+    /// A `${ ... }` interpolation, spanning from `${` to `}`, with the
+    /// format spec written after a `:`.
+    Expr(Spanned<ExprTokenChunk>, Option<FormatSpec>),
+}
+
+/// A piece of text up to one of `stop`, or an escape sequence.
+fn parse_escaped_fragment<'a>(
+    stop: &'static str,
+) -> impl FnMut(&'a str) -> IResult<&'a str, StringFragment<'a>, VerboseError<&'a str>> {
+    let parse_literal = verify(is_not(stop), |s: &str| !s.is_empty());
+    alt((
         map(parse_literal, StringFragment::Literal),
         map(parse_escaped_char, StringFragment::EscapedChar),
         value(StringFragment::EscapedWS, parse_escaped_whitespace),
-    ));
+    ))
+}
+
+fn parse_format_fragment(input: &str) -> IResult<&str, StringFragment<'_>, VerboseError<&str>> {
+    let parse_literal = verify(is_not(r#"\$"#), |s: &str| !s.is_empty());
+    alt((
+        map(parse_escaped_char, StringFragment::EscapedChar),
+        value(StringFragment::EscapedChar('$'), tag("\\$")),
+        value(StringFragment::EscapedWS, parse_escaped_whitespace),
+        map(spanned(parse_interpolation), |interpolation| {
+            let (chunk, spec) = interpolation.node;
+            StringFragment::Value(Spanned::new(chunk, interpolation.span), spec)
+        }),
+        map(parse_literal, StringFragment::Literal),
+        // a `$` not starting an interpolation is just text
+        map(recognize(ch('$')), StringFragment::Literal),
+    ))(input)
+}
+
+fn parse_normal_string(input: &str) -> IResult<&str, String, VerboseError<&str>> {
     fold_many0(
         // Our parser function– parses a single string fragment
-        parse_fragment,
+        parse_escaped_fragment(r#"\""#),
         // Our init value, an empty string
         String::new,
         // Our folding function. For each fragment, append the fragment to the string.
@@ -105,51 +180,342 @@ fn parse_raw_str(input: &str) -> IResult<&str, &str, VerboseError<&str>> {
 }
 
 // input: raw string => parse ${} and \ escape => format string
-fn parse_format_string(input: &str) -> IResult<&str, String, VerboseError<&str>> {
+fn parse_format_string(input: &str) -> IResult<&str, Vec<TemplatePart>, VerboseError<&str>> {
     let (remaining, raw_str) = parse_raw_str(input)?;
+    let (_, parts) = template_parts(raw_str, &[])?;
+    Ok((remaining, parts))
+}
 
-    let parse_literal = verify(is_not(r#"\$"#), |s: &str| !s.is_empty());
+/// The parts of a format string, leaving out the text of `body` in `cuts`.
+fn template_parts<'a>(
+    body: &'a str,
+    cuts: &[Range<usize>],
+) -> IResult<&'a str, Vec<TemplatePart>, VerboseError<&'a str>> {
+    let (rest, mut parts) = fold_many0(parse_format_fragment, Vec::new, |mut parts, fragment| {
+        match (fragment, parts.last_mut()) {
+            (StringFragment::Value(expr, spec), _) => parts.push(TemplatePart::Expr(expr, spec)),
+            (fragment, Some(TemplatePart::Text(text))) => push_fragment(text, fragment, body, cuts),
+            (fragment, _) => {
+                let mut text = String::new();
+                push_fragment(&mut text, fragment, body, cuts);
+                parts.push(TemplatePart::Text(text));
+            }
+        }
+        parts
+    })(body)?;
+    if !rest.is_empty() {
+        let error = session::error_at(rest, "an escape sequence", vec![]);
+        return Err(session::fail(rest, error));
+    }
+    // text made of nothing but indentation is cut down to nothing
+    parts.retain(|part| !matches!(part, TemplatePart::Text(text) if text.is_empty()));
+    Ok((rest, parts))
+}
 
+fn push_fragment(string: &mut String, fragment: StringFragment, body: &str, cuts: &[Range<usize>]) {
+    match fragment {
+        StringFragment::EscapedChar(c) => string.push(c),
+        StringFragment::Literal(s) => {
+            // literals are slices of `body`, so their offset tells what to cut
+            let start = s.as_ptr() as usize - body.as_ptr() as usize;
+            string.extend(
+                s.char_indices()
+                    .filter(|(i, _)| !cuts.iter().any(|cut| cut.contains(&(start + i))))
+                    .map(|(_, c)| c),
+            );
+        }
+        StringFragment::EscapedWS => {}
+        StringFragment::Value(..) => unreachable!("interpolations are not text"),
+    }
+}
+
+/// The body of a triple-quoted string, up to `closing`. Inside `"""` a
+/// backslash escapes the character after it, so `\"""` does not end it.
+fn parse_triple_body<'a>(
+    input: &'a str,
+    closing: &str,
+    escapes: bool,
+) -> IResult<&'a str, &'a str, VerboseError<&'a str>> {
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if input[i..].starts_with(closing) {
+            return Ok((&input[i + closing.len()..], &input[..i]));
+        }
+        if escapes && c == '\\' {
+            chars.next();
+        }
+    }
+    // a `"""` can only open a triple-quoted string, so this is final
+    let end = &input[input.len()..];
+    let expected = format!("a closing {}", closing);
+    Err(session::fail(
+        end,
+        session::error_at(end, &expected, vec![]),
+    ))
+}
+
+/// `"""` or `#"""`, with any number of `#`, up to the matching closing quotes.
+fn parse_fenced_triple(input: &str) -> IResult<&str, &str, VerboseError<&str>> {
+    let (remaining, hash_count) = fold_many0(tag("#"), || 0, |acc, _| acc + 1)(input)?;
+    let (remaining, _) = tag(TRIPLE)(remaining)?;
+    let closing = format!("{}{}", TRIPLE, "#".repeat(hash_count));
+    parse_triple_body(remaining, &closing, false)
+}
+
+/// The byte ranges of a triple-quoted `body` that are not part of its text:
+/// the blank opening and closing lines, the common indentation of the other
+/// lines, all of a blank line and the `\r` of each `\r\n`.
+fn dedent_cuts(body: &str) -> Vec<Range<usize>> {
+    let blank = |line: &str| line.trim_start_matches([' ', '\t']).is_empty();
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for line in body.split('\n') {
+        let end = start + line.trim_end_matches('\r').len();
+        lines.push(start..end);
+        start += line.len() + 1;
+    }
+
+    let mut cuts = Vec::new();
+    for line in &lines {
+        if line.end < body.len() && body[line.end..].starts_with('\r') {
+            cuts.push(line.end..line.end + 1);
+        }
+    }
+    if lines.len() > 1 {
+        let keep = body.starts_with('+') && blank(&body[1..lines[0].end]);
+        let last = lines[lines.len() - 1].clone();
+        if blank(&body[last.clone()]) {
+            // without `+` the line break before it goes too
+            let before = &lines[lines.len() - 2];
+            cuts.push(if keep { last.start } else { before.end }..body.len());
+            lines.pop();
+        }
+        if keep || blank(&body[lines[0].clone()]) {
+            cuts.push(0..lines[0].end);
+            if lines.len() > 1 {
+                cuts.push(lines[0].end..lines[1].start);
+            }
+            lines.remove(0);
+        }
+    }
+
+    let indent = lines
+        .iter()
+        .map(|line| &body[line.clone()])
+        .filter(|line| !blank(line))
+        .map(|line| &line[..line.len() - line.trim_start_matches([' ', '\t']).len()])
+        .reduce(|a, b| {
+            let common = a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y).count();
+            &a[..common]
+        })
+        .unwrap_or("");
+    for line in lines {
+        if blank(&body[line.clone()]) {
+            cuts.push(line);
+        } else {
+            cuts.push(line.start..line.start + indent.len());
+        }
+    }
+    cuts
+}
+
+fn parse_triple_string(input: &str) -> IResult<&str, String, VerboseError<&str>> {
+    let (remaining, body) = preceded(tag(TRIPLE), |i| parse_triple_body(i, TRIPLE, true))(input)?;
+    let cuts = dedent_cuts(body);
+    let (rest, string) = fold_many0(
+        parse_escaped_fragment("\\"),
+        String::new,
+        |mut string, fragment| {
+            push_fragment(&mut string, fragment, body, &cuts);
+            string
+        },
+    )(body)?;
+    if !rest.is_empty() {
+        let error = session::error_at(rest, "an escape sequence", vec![]);
+        return Err(session::fail(rest, error));
+    }
+    Ok((remaining, string))
+}
+
+fn parse_raw_triple_string(input: &str) -> IResult<&str, String, VerboseError<&str>> {
+    let (remaining, body) = preceded(ch('r'), parse_fenced_triple)(input)?;
+    let mut string = String::new();
+    push_fragment(
+        &mut string,
+        StringFragment::Literal(body),
+        body,
+        &dedent_cuts(body),
+    );
+    Ok((remaining, string))
+}
+
+fn parse_format_triple_string(input: &str) -> IResult<&str, Vec<TemplatePart>, VerboseError<&str>> {
+    let (remaining, body) = preceded(ch('f'), parse_fenced_triple)(input)?;
+    let (_, parts) = template_parts(body, &dedent_cuts(body))?;
+    Ok((remaining, parts))
+}
+
+fn parse_byte_string(input: &str) -> IResult<&str, Vec<u8>, VerboseError<&str>> {
     let parse_fragment = alt((
-        map(parse_escaped_char, StringFragment::EscapedChar),
-        value(StringFragment::EscapedWS, parse_escaped_whitespace),
-        map(parse_expr_token_chunk, |expr| StringFragment::Value(expr.to_string())),
-        map(parse_literal, StringFragment::Literal),
+        map(parse_escaped_byte, |byte| vec![byte]),
+        map(parse_escaped_fragment(r#"\""#), |fragment| match fragment {
+            StringFragment::Literal(s) => s.as_bytes().to_vec(),
+            StringFragment::EscapedChar(c) => c.to_string().into_bytes(),
+            _ => vec![],
+        }),
     ));
+    let parse_bytes = fold_many0(parse_fragment, Vec::new, |mut bytes, fragment| {
+        bytes.extend(fragment);
+        bytes
+    });
+    delimited(tag("b\""), parse_bytes, ch('"'))(input)
+}
 
-    let parse_string = fold_many0(parse_fragment, String::new, |mut string, fragment| {
-        match fragment {
-            StringFragment::EscapedChar(c) => string.push(c),
-            StringFragment::Literal(s) => string.push_str(s),
-            StringFragment::Value(s) => string.push_str(s.as_str()),
-            _ => {}
+/// Bytes decoded from text, or the offset of the first character that could
+/// not be used and what was expected there.
+type Decoded = Result<Vec<u8>, (usize, &'static str)>;
+
+/// `prefix"..."` decoded with `decode`.
+fn parse_encoded_bytes<'a>(
+    input: &'a str,
+    prefix: &str,
+    decode: fn(&str) -> Decoded,
+) -> IResult<&'a str, Vec<u8>, VerboseError<&'a str>> {
+    let (rest, _) = pair(tag(prefix), ch('"'))(input)?;
+    // once the prefix is seen this can only be a bytes literal
+    let (remaining, text) = cut(terminated(take_till(|c| c == '"'), ch('"')))(rest)?;
+    match decode(text) {
+        Ok(bytes) => Ok((remaining, bytes)),
+        Err((offset, expected)) => {
+            let at = &text[offset..];
+            Err(session::fail(at, session::error_at(at, expected, vec![])))
         }
-        string
-    });
+    }
+}
 
-    let (_remaining_in_f_str, string) = complete(parse_string)(raw_str)?;
-    // complete: assert(remaining_in_f_str == "")
+/// Binary data: `b"\x00\xff"`, `b64"AP8="` or `hex"00ff"`.
+pub(crate) fn parse_bytes(input: &str) -> IResult<&str, Vec<u8>, VerboseError<&str>> {
+    alt((
+        parse_byte_string,
+        |i| parse_encoded_bytes(i, "b64", decode_base64),
+        |i| parse_encoded_bytes(i, "hex", decode_hex),
+    ))(input)
+}
 
-    Ok((remaining, string))
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// `bytes` in standard, padded base64, as `b64"..."` literals and JSON
+/// output write them.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
 }
 
-pub fn parse_string(input: &str) -> IResult<&str, String, VerboseError<&str>> {
+fn decode_base64(text: &str) -> Decoded {
+    let mut bytes = Vec::new();
+    let (mut bits, mut count, mut padding) = (0u32, 0, false);
+    for (i, c) in text.char_indices() {
+        let digit = match c {
+            c if c.is_whitespace() => continue,
+            '=' => {
+                padding = true;
+                continue;
+            }
+            _ if padding => return Err((i, "the end of the base64 text")),
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' | '-' => 62,
+            '/' | '_' => 63,
+            _ => return Err((i, "a base64 digit")),
+        };
+        bits = bits << 6 | digit;
+        count += 1;
+        if count % 4 == 0 {
+            bytes.extend_from_slice(&bits.to_be_bytes()[1..]);
+            bits = 0;
+        }
+    }
+    match count % 4 {
+        0 => {}
+        1 => return Err((text.len(), "more base64 digits")),
+        rest => {
+            let bits = bits << (6 * (4 - rest));
+            bytes.extend_from_slice(&bits.to_be_bytes()[1..rest]);
+        }
+    }
+    Ok(bytes)
+}
+
+fn decode_hex(text: &str) -> Decoded {
+    let mut bytes = Vec::new();
+    let mut high = None;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let digit = c.to_digit(16).ok_or((i, "a hex digit"))? as u8;
+        match high.take() {
+            Some(high) => bytes.push(high << 4 | digit),
+            None => high = Some(digit),
+        }
+    }
+    match high {
+        Some(_) => Err((text.len(), "an even number of hex digits")),
+        None => Ok(bytes),
+    }
+}
+
+pub(crate) fn parse_string(input: &str) -> IResult<&str, String, VerboseError<&str>> {
     alt((
+        // """ ... """, r""" ... """, multi-line strings
+        parse_triple_string,
+        parse_raw_triple_string,
         // " ... ", normal string
         delimited(ch('"'), parse_normal_string, ch('"')),
         // r#" ... "#, row string
         map(preceded(ch('r'), parse_raw_str), String::from),
-        // f#" ... "#, format string
-        preceded(ch('f'), parse_format_string),
     ))(input)
 }
 
-pub fn parse_literal_string(input: &str) -> IResult<&str, String, VerboseError<&str>> {
+/// A `'...'` string, with the escapes of `"..."` and `\'`. Only the
+/// separator of a `join` format spec is written so, where a `"` would end
+/// the format string around it.
+pub(crate) fn parse_single_quoted(input: &str) -> IResult<&str, String, VerboseError<&str>> {
+    let fragment = alt((
+        value(StringFragment::EscapedChar('\''), tag("\\'")),
+        parse_escaped_fragment("\\'"),
+    ));
+    let text = fold_many0(fragment, String::new, |mut string, fragment| {
+        match fragment {
+            StringFragment::Literal(s) => string.push_str(s),
+            StringFragment::EscapedChar(c) => string.push(c),
+            _ => {}
+        }
+        string
+    });
+    delimited(ch('\''), text, ch('\''))(input)
+}
+
+/// A format string, `f"..."`, `f#"..."#` or `f"""..."""`.
+pub(crate) fn parse_template(input: &str) -> IResult<&str, Vec<TemplatePart>, VerboseError<&str>> {
     alt((
-        // " ... ", normal string
-        delimited(ch('"'), parse_normal_string, ch('"')),
-        // r#" ... "#, row string
-        map(preceded(ch('r'), parse_raw_str), String::from),
+        parse_format_triple_string,
+        // f#" ... "#, format string
+        preceded(ch('f'), parse_format_string),
     ))(input)
 }
 
@@ -158,41 +524,166 @@ mod tests {
     use std::string::String;
 
     use super::*;
+    use crate::format_spec::StandardSpec;
+    use crate::ExprToken;
+
+    fn text(s: &str) -> TemplatePart {
+        TemplatePart::Text(s.to_string())
+    }
+
+    fn expr(tokens: Vec<ExprToken>) -> TemplatePart {
+        TemplatePart::Expr(ExprTokenChunk::from(tokens).into(), None)
+    }
 
     #[test]
     fn test_format_string() {
+        let name = || expr(vec![ExprToken::Var("name".to_string())]);
+        assert_eq!(parse_template(r#"f"${name}""#), Ok(("", vec![name()])));
         assert_eq!(
-            parse_string(r#"f"${name}""#),
-            Ok(("", String::from("Var(name)")))
+            parse_template(r#"f"hello ${name}""#),
+            Ok(("", vec![text("hello "), name()]))
         );
         assert_eq!(
-            parse_string(r#"f"hello ${name}""#),
-            Ok(("", String::from("hello TODO!")))
+            parse_template(r#"f"hello ${ name } world ${ name }""#),
+            Ok(("", vec![text("hello "), name(), text(" world "), name()]))
         );
         assert_eq!(
-            parse_string(r#"f"hello ${ name }""#),
-            Ok(("", String::from("hello TODO!")))
+            parse_template(r####"f#"hello ${ foo(bar) }"#"####),
+            Ok((
+                "",
+                vec![
+                    text("hello "),
+                    expr(vec![ExprToken::FnCall(
+                        "foo".to_string(),
+                        vec![ExprTokenChunk::from(vec![ExprToken::Var(
+                            "bar".to_string()
+                        )])],
+                    )]),
+                ]
+            ))
         );
         assert_eq!(
-            parse_string(r#"f"hello ${ name } world""#),
-            Ok(("", String::from("hello TODO! world")))
+            parse_template(r#"f"a\tb \${x} $5""#),
+            Ok(("", vec![text("a\tb ${x} $5")]))
         );
         assert_eq!(
-            parse_string(r#"f"hello ${ name } world ${ name }""#),
-            Ok(("", String::from("hello TODO! world TODO!")))
+            parse_template("f\"\"\"\n    hello\n      ${ name }\n    \"\"\""),
+            Ok(("", vec![text("hello\n  "), name()]))
+        );
+        assert_eq!(
+            parse_template(r#"f"${ name :05}${name:json}""#),
+            Ok((
+                "",
+                vec![
+                    TemplatePart::Expr(
+                        ExprTokenChunk::from(vec![ExprToken::Var("name".to_string())]).into(),
+                        Some(FormatSpec::Standard(StandardSpec {
+                            zero: true,
+                            width: Some(5),
+                            ..StandardSpec::default()
+                        })),
+                    ),
+                    TemplatePart::Expr(
+                        ExprTokenChunk::from(vec![ExprToken::Var("name".to_string())]).into(),
+                        Some(FormatSpec::Json),
+                    ),
+                ]
+            ))
+        );
+        assert!(matches!(
+            parse_template(r#"f"${ name :05q}""#),
+            Err(nom::Err::Failure(_))
+        ));
+        assert_eq!(parse_template(r#"f"""#), Ok(("", vec![])));
+        assert!(matches!(
+            parse_template(r#"f"\q""#),
+            Err(nom::Err::Failure(_))
+        ));
+        assert!(parse_string(r#"f"hello""#).is_err());
+    }
+
+    #[test]
+    fn test_triple_string() {
+        let src = "\"\"\"
+            SELECT *
+              FROM users
+            WHERE id = \\\"1\\\"
+            \"\"\"";
+        assert_eq!(
+            parse_string(src),
+            Ok(("", String::from("SELECT *\n  FROM users\nWHERE id = \"1\"")))
         );
         assert_eq!(
-            parse_string(r####"f#"hello ${ name }"#"####),
-            Ok(("", String::from("hello TODO!")))
+            parse_string("\"\"\"+\n    line one\n\n      line two\n    \"\"\""),
+            Ok(("", String::from("line one\n\n  line two\n")))
         );
         assert_eq!(
-            parse_string(r####"f#"hello ${ foo(bar) }"#"####),
-            Ok(("", String::from("hello TODO!")))
+            parse_string("\"\"\"\r\n  a\r\n  b\r\n  \"\"\""),
+            Ok(("", String::from("a\nb")))
         );
         assert_eq!(
-            parse_string(r####"f#"hello ${ foo(bar, foo()) }"#"####),
-            Ok(("", String::from("hello TODO!")))
+            parse_string(r#""""say "hi\"""""#),
+            Ok(("", String::from(r#"say "hi""#)))
         );
+        assert_eq!(
+            parse_string("r\"\"\"\n  C:\\dir\\\n  \"\"\""),
+            Ok(("", String::from("C:\\dir\\")))
+        );
+        assert_eq!(
+            parse_string("r#\"\"\"\n  a \"\"\" b\n  \"\"\"#"),
+            Ok(("", String::from("a \"\"\" b")))
+        );
+        assert_eq!(
+            parse_string("\"\"\"\n  a\n  \"\"\", next"),
+            Ok((", next", String::from("a")))
+        );
+        assert!(matches!(
+            parse_string("\"\"\"\n  never closed"),
+            Err(nom::Err::Failure(_))
+        ));
+        assert!(matches!(
+            parse_string("\"\"\"\\q\"\"\""),
+            Err(nom::Err::Failure(_))
+        ));
+    }
+
+    #[test]
+    fn test_bytes() {
+        assert_eq!(
+            parse_bytes(r#"b"\x00\xffA\n""#),
+            Ok(("", vec![0, 255, b'A', b'\n']))
+        );
+        assert_eq!(parse_bytes(r#"b"é""#), Ok(("", vec![0xc3, 0xa9])));
+        assert_eq!(parse_bytes(r#"hex"00 ff 7F""#), Ok(("", vec![0, 255, 127])));
+        assert_eq!(
+            parse_bytes("b64\"aGVs\n  bG8=\""),
+            Ok(("", b"hello".to_vec()))
+        );
+        assert_eq!(parse_bytes(r#"b64"AP8""#), Ok(("", vec![0, 255])));
+        assert_eq!(parse_bytes(r#"b64"_-8=""#), Ok(("", vec![255, 239])));
+        assert_eq!(parse_bytes(r#"b64"""#), Ok(("", vec![])));
+        for src in [r#"hex"0g""#, r#"hex"abc""#, r#"b64"AP8*""#, r#"b64"A""#] {
+            assert!(
+                matches!(parse_bytes(src), Err(nom::Err::Failure(_))),
+                "{}",
+                src
+            );
+        }
+        assert!(parse_bytes(r#""00""#).is_err());
+    }
+
+    #[test]
+    fn test_encode_base64() {
+        for (bytes, text) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (&[0, 255, 239], "AP/v"),
+        ] {
+            assert_eq!(encode_base64(bytes), text);
+            assert_eq!(decode_base64(text), Ok(bytes.to_vec()));
+        }
     }
 
     #[test]
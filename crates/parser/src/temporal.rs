@@ -0,0 +1,185 @@
+//! ISO 8601 dates and times.
+//!
+//! ```text
+//! temporal  = date "T" time [offset]            ; bare, always with a time
+//!           | "d" '"' (date | time | date "T" time [offset]) '"'
+//! date      = yyyy "-" mm "-" dd
+//! time      = hh ":" mm [":" ss ["." fraction]]
+//! offset    = "Z" | ("+" | "-") hh ":" mm
+//! ```
+//!
+//! `2024-05-01T12:00:00Z` needs no quoting, `d"2024-05-01"` and
+//! `d"12:30"` do, so `2024-05-01` inside `${ ... }` is still a subtraction.
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat};
+use nom::bytes::complete::{tag, take_till, take_while, take_while_m_n};
+use nom::character::complete::char as ch;
+use nom::combinator::{cut, recognize};
+use nom::error::VerboseError;
+use nom::sequence::{terminated, tuple};
+use nom::IResult;
+
+use crate::session;
+
+/// A date, a time of day, or both, as written in an ISO 8601 literal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Temporal {
+    /// `d"2024-05-01"`
+    Date(NaiveDate),
+    /// `d"12:30:00"`
+    Time(NaiveTime),
+    /// `2024-05-01T12:30:00`, a wall-clock time without an offset.
+    DateTime(NaiveDateTime),
+    /// `2024-05-01T12:30:00Z` or `2024-05-01T14:30:00+02:00`, an instant.
+    Zoned(DateTime<FixedOffset>),
+}
+
+impl FromStr for Temporal {
+    type Err = chrono::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(zoned) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Temporal::Zoned(zoned));
+        }
+        if let Ok(date_time) = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f") {
+            return Ok(Temporal::DateTime(date_time));
+        }
+        if let Ok(date_time) = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M") {
+            return Ok(Temporal::DateTime(date_time));
+        }
+        if let Ok(time) = NaiveTime::parse_from_str(s, "%H:%M:%S%.f") {
+            return Ok(Temporal::Time(time));
+        }
+        if let Ok(time) = NaiveTime::parse_from_str(s, "%H:%M") {
+            return Ok(Temporal::Time(time));
+        }
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").map(Temporal::Date)
+    }
+}
+
+/// ISO 8601, with seconds always shown and `Z` for UTC.
+impl Display for Temporal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Temporal::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            Temporal::Time(time) => write!(f, "{}", time.format("%H:%M:%S%.f")),
+            Temporal::DateTime(dt) => write!(f, "{}", dt.format("%Y-%m-%dT%H:%M:%S%.f")),
+            Temporal::Zoned(dt) => write!(f, "{}", dt.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+        }
+    }
+}
+
+/// A date-time literal, either bare or quoted as `d"..."`.
+pub(crate) fn parse_temporal(input: &str) -> IResult<&str, Temporal, VerboseError<&str>> {
+    let (remaining, text) = match quoted(input) {
+        Ok(parsed) => parsed,
+        Err(nom::Err::Error(_)) => bare(input)?,
+        Err(e) => return Err(e),
+    };
+    // the shape is a date literal's, so do not backtrack into another value
+    match text.parse() {
+        Ok(temporal) => Ok((remaining, temporal)),
+        Err(_) => {
+            let len = input.len() - remaining.len();
+            Err(session::fail(input, session::invalid_date(input, len)))
+        }
+    }
+}
+
+fn quoted(input: &str) -> IResult<&str, &str, VerboseError<&str>> {
+    let (rest, _) = tag("d\"")(input)?;
+    // once `d"` is seen this can only be a date literal
+    cut(terminated(take_till(|c| c == '"'), ch('"')))(rest)
+}
+
+fn bare(input: &str) -> IResult<&str, &str, VerboseError<&str>> {
+    let digits = |n| take_while_m_n(n, n, |c: char| c.is_ascii_digit());
+    recognize(tuple((
+        digits(4),
+        ch('-'),
+        digits(2),
+        ch('-'),
+        digits(2),
+        ch('T'),
+        take_while(|c: char| c.is_ascii_digit() || ":.+-Z".contains(c)),
+    )))(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    fn parse(src: &str) -> Temporal {
+        let (rest, temporal) = parse_temporal(src).unwrap();
+        assert_eq!(rest, "", "{}", src);
+        temporal
+    }
+
+    #[test]
+    fn test_literals() {
+        let noon = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        assert_eq!(
+            parse("2024-05-01T12:00:00Z"),
+            Temporal::Zoned(noon.fixed_offset())
+        );
+        assert_eq!(
+            parse("2024-05-01T14:00:00+02:00"),
+            Temporal::Zoned(noon.fixed_offset())
+        );
+        assert_eq!(
+            parse("2024-05-01T12:00:00"),
+            Temporal::DateTime(noon.naive_utc())
+        );
+        assert_eq!(
+            parse(r#"d"2024-05-01""#),
+            Temporal::Date(NaiveDate::from_ymd_opt(2024, 5, 1).unwrap())
+        );
+        assert_eq!(
+            parse(r#"d"12:30""#),
+            Temporal::Time(NaiveTime::from_hms_opt(12, 30, 0).unwrap())
+        );
+        assert!(parse_temporal("2024-05-01").is_err());
+        assert!(matches!(
+            parse_temporal("2024-13-01T00:00:00Z"),
+            Err(nom::Err::Failure(_))
+        ));
+        assert!(matches!(
+            parse_temporal(r#"d"tomorrow""#),
+            Err(nom::Err::Failure(_))
+        ));
+        assert!(matches!(
+            parse_temporal(r#"d"2024"#),
+            Err(nom::Err::Failure(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let err = crate::root("{ d: 2024-02-30T00:00:00 }").unwrap_err();
+        assert_eq!(err.kind(), &crate::ErrorKind::InvalidDate);
+        assert!(err
+            .to_string()
+            .starts_with("invalid date '2024-02-30T00:00:00' at line 1, column 6"));
+        assert_eq!((err.span().start, err.span().end), (5, 24));
+        let err = crate::root(r#"[d"25:00"]"#).unwrap_err();
+        assert_eq!(err.found(), r#"'d"25:00"'"#);
+    }
+
+    #[test]
+    fn test_display() {
+        for src in [
+            "2024-05-01T12:00:00Z",
+            "2024-05-01T12:00:00.250+02:00",
+            "2024-05-01T12:00:00",
+        ] {
+            assert_eq!(parse(src).to_string(), src);
+        }
+        assert_eq!(parse(r#"d"2024-05-01""#).to_string(), "2024-05-01");
+        assert_eq!(parse(r#"d"12:30""#).to_string(), "12:30:00");
+    }
+}
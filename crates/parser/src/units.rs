@@ -0,0 +1,242 @@
+//! Unit-suffixed literals: durations (`30s`, `250ms`, `1h30m`, `1.5h`) and
+//! byte sizes (`512B`, `10MiB`, `1.5GB`).
+//!
+//! ```text
+//! duration  = amount time-unit {amount time-unit}
+//! byte-size = amount size-unit
+//! amount    = digits ["." digits]
+//! time-unit = "ns" | "us" | "µs" | "ms" | "s" | "m" | "h" | "d"
+//! size-unit = "B" | "KB" | "MB" | "GB" | "TB" | "PB" | "KiB" | "MiB" | "GiB" | "TiB" | "PiB"
+//! ```
+//!
+//! Fractions must come out as a whole number of nanoseconds or bytes.
+
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use nom::character::complete::char as ch;
+use nom::combinator::opt;
+use nom::error::{ErrorKind, ParseError, VerboseError};
+use nom::sequence::{pair, preceded};
+use nom::IResult;
+
+use crate::numeric::dec_digits;
+use crate::session;
+
+/// A number of bytes, written with a unit such as `10MiB` or `1.5GB`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteSize(pub u64);
+
+const TIME_UNITS: [(&str, u128); 8] = [
+    ("ns", 1),
+    ("us", 1_000),
+    ("µs", 1_000),
+    ("ms", 1_000_000),
+    ("s", 1_000_000_000),
+    ("m", 60_000_000_000),
+    ("h", 3_600_000_000_000),
+    ("d", 86_400_000_000_000),
+];
+
+const SIZE_UNITS: [(&str, u128); 11] = [
+    ("B", 1),
+    ("KB", 1_000),
+    ("MB", 1_000_000),
+    ("GB", 1_000_000_000),
+    ("TB", 1_000_000_000_000),
+    ("PB", 1_000_000_000_000_000),
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+    ("TiB", 1 << 40),
+    ("PiB", 1 << 50),
+];
+
+pub(crate) enum Quantity {
+    Duration(Duration),
+    ByteSize(ByteSize),
+}
+
+/// A duration or byte size literal, fails (without consuming) on a plain number.
+pub(crate) fn parse_quantity(input: &str) -> IResult<&str, Quantity, VerboseError<&str>> {
+    let (mut rest, first) = component(input)?;
+    let Some((amount, unit, is_time)) = first else {
+        return Err(nom::Err::Error(VerboseError::from_error_kind(
+            input,
+            ErrorKind::Tag,
+        )));
+    };
+    let mut total = amount.scale(unit);
+    if is_time {
+        while let Ok((remaining, Some((amount, unit, true)))) = component(rest) {
+            total = total
+                .zip(amount.scale(unit))
+                .and_then(|(t, a)| t.checked_add(a));
+            rest = remaining;
+        }
+    }
+    let quantity = match (total, is_time) {
+        (Some(nanos), true) => u64::try_from(nanos / 1_000_000_000)
+            .ok()
+            .map(|secs| Quantity::Duration(Duration::new(secs, (nanos % 1_000_000_000) as u32))),
+        (Some(bytes), false) => u64::try_from(bytes)
+            .ok()
+            .map(|b| Quantity::ByteSize(ByteSize(b))),
+        (None, _) => None,
+    };
+    quantity.map(|q| (rest, q)).ok_or_else(|| {
+        let expected = if is_time {
+            "a whole number of nanoseconds"
+        } else {
+            "a whole number of bytes"
+        };
+        session::fail(input, session::error_at(input, expected, vec![]))
+    })
+}
+
+/// An amount with its unit: `(amount, unit size, is a time unit)`.
+type Component = (Amount, u128, bool);
+
+/// The next amount and unit, or `None` for a number without a unit.
+fn component(input: &str) -> IResult<&str, Option<Component>, VerboseError<&str>> {
+    let (rest, (int_part, frac_part)) =
+        pair(dec_digits, opt(preceded(ch('.'), dec_digits)))(input)?;
+    let amount = Amount {
+        int_part: int_part.replace('_', ""),
+        frac_part: frac_part.map_or(String::new(), |f| f.replace('_', "")),
+    };
+    let units = TIME_UNITS
+        .iter()
+        .map(|(name, size)| (name, size, true))
+        .chain(SIZE_UNITS.iter().map(|(name, size)| (name, size, false)));
+    let unit = units
+        .filter(|(name, _, _)| {
+            rest.strip_prefix(**name)
+                .is_some_and(|after| !after.starts_with(|c: char| c.is_alphabetic() || c == '_'))
+        })
+        .max_by_key(|(name, _, _)| name.len());
+    Ok(match unit {
+        Some((name, size, is_time)) => (&rest[name.len()..], Some((amount, *size, is_time))),
+        None => (input, None),
+    })
+}
+
+struct Amount {
+    int_part: String,
+    frac_part: String,
+}
+
+impl Amount {
+    /// `amount * unit`, if that is a whole number that fits.
+    fn scale(&self, unit: u128) -> Option<u128> {
+        let digits = format!("{}{}", self.int_part, self.frac_part);
+        let scaled = digits.parse::<u128>().ok()?.checked_mul(unit)?;
+        let divisor = 10u128.checked_pow(self.frac_part.len() as u32)?;
+        scaled.is_multiple_of(divisor).then(|| scaled / divisor)
+    }
+}
+
+/// The largest unit that shows the size exactly with at most three decimals,
+/// e.g. `10MiB`, `1.5GB` or `0B`.
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let bytes = self.0 as u128;
+        let mut units = SIZE_UNITS;
+        units.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+        let (name, size) = units
+            .iter()
+            .find(|(_, size)| bytes >= *size && (bytes * 1000).is_multiple_of(*size))
+            .unwrap_or(&("B", 1));
+        write!(f, "{}{}", decimal(bytes * 1000 / size, 3), name)
+    }
+}
+
+/// A duration in the literal syntax, largest units first: `1h30m`,
+/// `1s500ms`, `0s`.
+pub fn format_duration(duration: &Duration) -> String {
+    let mut nanos = duration.as_nanos();
+    if nanos == 0 {
+        return "0s".to_string();
+    }
+    let mut out = String::new();
+    for (name, size) in TIME_UNITS.iter().rev().filter(|(name, _)| *name != "µs") {
+        if nanos >= *size {
+            out.push_str(&format!("{}{}", nanos / size, name));
+            nanos %= size;
+        }
+    }
+    out
+}
+
+/// `value / 10^scale` without trailing zeros.
+fn decimal(value: u128, scale: u32) -> String {
+    let divisor = 10u128.pow(scale);
+    let (int_part, frac_part) = (value / divisor, value % divisor);
+    if frac_part == 0 {
+        return int_part.to_string();
+    }
+    let frac = format!("{:0width$}", frac_part, width = scale as usize);
+    format!("{}.{}", int_part, frac.trim_end_matches('0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn duration(input: &str) -> Duration {
+        match parse_quantity(input) {
+            Ok(("", Quantity::Duration(d))) => d,
+            _ => panic!("{} is not a duration", input),
+        }
+    }
+
+    fn size(input: &str) -> u64 {
+        match parse_quantity(input) {
+            Ok(("", Quantity::ByteSize(s))) => s.0,
+            _ => panic!("{} is not a byte size", input),
+        }
+    }
+
+    #[test]
+    fn test_durations() {
+        assert_eq!(duration("30s"), Duration::from_secs(30));
+        assert_eq!(duration("5m"), Duration::from_secs(300));
+        assert_eq!(duration("250ms"), Duration::from_millis(250));
+        assert_eq!(duration("1h30m"), Duration::from_secs(5400));
+        assert_eq!(duration("1.5h"), Duration::from_secs(5400));
+        assert_eq!(duration("2d"), Duration::from_secs(172_800));
+        assert_eq!(duration("10us"), Duration::from_micros(10));
+        assert_eq!(duration("10µs"), Duration::from_micros(10));
+        assert_eq!(duration("1_000ns"), Duration::from_micros(1));
+        assert!(parse_quantity("1.5ns").is_err());
+        assert!(parse_quantity("30").is_err());
+        assert!(parse_quantity("30sec").is_err());
+    }
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(size("512B"), 512);
+        assert_eq!(size("10MiB"), 10 * 1024 * 1024);
+        assert_eq!(size("1.5GB"), 1_500_000_000);
+        assert_eq!(size("1KiB"), 1024);
+        assert!(parse_quantity("0.5B").is_err());
+        assert!(parse_quantity("99999999PiB").is_err());
+        // sizes do not combine
+        assert!(matches!(parse_quantity("1MB5KB"), Ok(("5KB", _))));
+    }
+
+    #[test]
+    fn test_canonical() {
+        assert_eq!(format_duration(&Duration::from_secs(5400)), "1h30m");
+        assert_eq!(format_duration(&Duration::from_millis(1500)), "1s500ms");
+        assert_eq!(format_duration(&Duration::ZERO), "0s");
+        assert_eq!(format_duration(&Duration::from_secs(90_000)), "1d1h");
+        assert_eq!(ByteSize(10 * 1024 * 1024).to_string(), "10MiB");
+        assert_eq!(ByteSize(1_500_000_000).to_string(), "1.5GB");
+        assert_eq!(ByteSize(1536).to_string(), "1.5KiB");
+        assert_eq!(ByteSize(1000).to_string(), "1KB");
+        assert_eq!(ByteSize(1001).to_string(), "1.001KB");
+        assert_eq!(ByteSize(999).to_string(), "999B");
+        assert_eq!(ByteSize(0).to_string(), "0B");
+    }
+}
@@ -1,16 +1,64 @@
-use crate::expr_token::ExprToken;
+use crate::comments::comment;
 
 pub(crate) struct Iter<T> {
     inner: Vec<T>,
     cursor: usize,
 }
 
-impl From<Vec<ExprToken>> for Iter<ExprToken> {
-    fn from(inner: Vec<ExprToken>) -> Self {
+impl<T> From<Vec<T>> for Iter<T> {
+    fn from(inner: Vec<T>) -> Self {
         Iter::new(inner)
     }
 }
 
+/// Skip to the next `,`, `}` or `]` that is not nested in brackets, strings
+/// or comments, this is where parsing resumes after a syntax error.
+pub(crate) fn skip_to_sync(input: &str) -> &str {
+    skip_until(input, &[',', '}', ']'])
+}
+
+/// Skip to the next of `stops` that is not nested in brackets, strings or
+/// comments.
+pub(crate) fn skip_until<'a>(input: &'a str, stops: &[char]) -> &'a str {
+    let mut depth = 0usize;
+    let mut rest = input;
+    while let Some(c) = rest.chars().next() {
+        if let Ok((remaining, _)) = comment(rest) {
+            rest = remaining;
+            continue;
+        }
+        match c {
+            '"' if rest.starts_with(r#"""""#) => {
+                let mut escaped = false;
+                let body = &rest[3..];
+                let end = body.char_indices().find(|&(i, c)| {
+                    let end = !escaped && body[i..].starts_with(r#"""""#);
+                    escaped = c == '\\' && !escaped;
+                    end
+                });
+                rest = end.map_or(&rest[rest.len()..], |(end, _)| &body[end + 3..]);
+                continue;
+            }
+            '"' => {
+                let mut escaped = false;
+                let end = rest[1..].find(|c| {
+                    let end = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    end
+                });
+                rest = end.map_or(&rest[rest.len()..], |end| &rest[end + 2..]);
+                continue;
+            }
+            '{' | '[' | '(' => depth += 1,
+            _ if depth == 0 && stops.contains(&c) => return rest,
+            '}' | ']' | ')' if depth > 0 => depth -= 1,
+            _ => {}
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    rest
+}
+
 impl<T> Iter<T> {
     fn new(inner: Vec<T>) -> Self {
         Iter { inner, cursor: 0 }
@@ -42,6 +90,18 @@ impl<T> Iter<T> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_skip_to_sync() {
+        assert_eq!(skip_to_sync("1 2, b: 3}"), ", b: 3}");
+        assert_eq!(skip_to_sync("{a: [1, 2]} }"), "}");
+        assert_eq!(skip_to_sync(r#""x, \"y]" ]"#), "]");
+        assert_eq!(skip_to_sync("\"\"\"\n  \"a\", b]\n\"\"\" ]"), "]");
+        assert_eq!(skip_to_sync("// a, b\n  , c"), ", c");
+        assert_eq!(skip_to_sync("/* a, b */ } c"), "} c");
+        assert_eq!(skip_to_sync("f(1, 2)"), "");
+        assert_eq!(skip_to_sync(", x"), ", x");
+    }
+
     #[test]
     fn test_iter() {
         let mut iter = Iter::new(vec![1, 2, 3]);